@@ -0,0 +1,209 @@
+// Fetching the certificates of issuers that are missing from a chain.
+//
+// Servers often leave intermediates out of the chains they send, relying on clients
+// to do as browsers do and fetch them from the caIssuers URL in the authority
+// information access extension of the certificate they issued. certitude makes no
+// network requests itself, so the fetching is left to an `IssuerFetcher` that the
+// caller provides.
+
+use std::fmt;
+use std::str;
+use std::sync::Arc;
+
+use der::{self, Reader};
+use issuers;
+use x509::Certificate;
+
+// The most URLs fetched while validating any one chain, however many issuers turn
+// out to be missing.
+const MAX_FETCHES: usize = 8;
+
+// The content type of a PKCS#7 signed-data message, which is how a bundle of
+// certificates is served.
+const OID_PKCS7_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+
+
+/// Fetches the certificates of issuers that a server left out of its chain.
+///
+/// A certificate may say where its issuer's certificate can be found, with a
+/// caIssuers URL in its authority information access extension. Given a verifier
+/// with a fetcher, when a chain can't be built because an issuer is missing, the
+/// verifier asks the fetcher for the URLs of the certificates whose issuers it
+/// doesn't have, adds what it gets back to the chain and tries again. certitude
+/// never makes network requests itself: the fetcher is where an HTTP client is
+/// plugged in.
+///
+/// No more than eight URLs are fetched for any one chain, and none is fetched twice.
+///
+/// # Examples
+///
+/// ```
+/// struct HttpFetcher(Client);
+///
+/// impl IssuerFetcher for HttpFetcher {
+///     fn fetch(&self, url: &str) -> Option<Vec<u8>> {
+///         self.0.get(url).send().ok()?.bytes().ok().map(|body| body.to_vec())
+///     }
+/// }
+///
+/// let verifier = Verifier::builder()
+///     .issuer_fetcher(Arc::new(HttpFetcher(Client::new())))
+///     .build();
+/// ```
+pub trait IssuerFetcher: Send + Sync {
+    /// Fetches the resource at the URL, returning its body, or `None` if it couldn't
+    /// be fetched. Any URL a certificate gives is passed on, so it's up to the fetcher
+    /// to refuse schemes it doesn't support, and to apply timeouts and size limits.
+    ///
+    /// As RFC 5280 describes, the body should be either a DER-encoded certificate or
+    /// a DER-encoded PKCS#7 message holding certificates. Anything else is ignored.
+    fn fetch(&self, url: &str) -> Option<Vec<u8>>;
+}
+
+// A verifier's fetcher, shared between its clones.
+#[derive(Clone)]
+pub(crate) struct SharedFetcher(pub Arc<dyn IssuerFetcher>);
+
+impl fmt::Debug for SharedFetcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("IssuerFetcher")
+    }
+}
+
+
+// Fetches the issuers of the certificates in the chain whose issuers aren't in it,
+// from the URLs they give. URLs already in `fetched` aren't fetched again, and those
+// fetched now are added to it. Returns the DER encodings of the certificates fetched
+// that weren't in the chain already.
+pub(crate) fn fetch_missing_issuers(fetcher: &dyn IssuerFetcher, encoded_certs: &[&[u8]], fetched: &mut Vec<String>)
+    -> Vec<Vec<u8>>
+{
+    // Certificates that can't be parsed are left for the backend to report.
+    let certs = encoded_certs.iter().filter_map(|cert| Certificate::parse(cert).ok()).collect::<Vec<_>>();
+    let mut found = Vec::new();
+    for index in issuers::missing_issuers(&certs) {
+        for url in &certs[index].ca_issuers {
+            let url = match str::from_utf8(url) {
+                Ok(url) => url,
+                Err(_) => continue,
+            };
+            if fetched.len() >= MAX_FETCHES {
+                return found;
+            }
+            if fetched.iter().any(|done| done == url) {
+                continue;
+            }
+            fetched.push(url.to_string());
+            let body = match fetcher.fetch(url) {
+                Some(body) => body,
+                None => continue,
+            };
+            for issuer in parse_certificates(&body) {
+                if !encoded_certs.contains(&&issuer[..]) && !found.contains(&issuer) {
+                    found.push(issuer);
+                }
+            }
+        }
+    }
+    found
+}
+
+// Returns the certificates in a fetched resource: either a single DER-encoded
+// certificate, or the certificates of a DER-encoded PKCS#7 message, such as a
+// "certs-only" message. Anything that can't be parsed is left out.
+fn parse_certificates(body: &[u8]) -> Vec<Vec<u8>> {
+    if Certificate::parse(body).is_ok() {
+        return vec![body.to_vec()];
+    }
+    let certs = match pkcs7_certificates(body) {
+        Ok(certs) => certs,
+        Err(_) => return Vec::new(),
+    };
+    certs.into_iter()
+        .filter(|cert| Certificate::parse(cert).is_ok())
+        .map(|cert| cert.to_vec())
+        .collect()
+}
+
+// Returns the complete encodings of the certificates in a PKCS#7 signed-data
+// message. The message's content and signers, if it has any, are ignored.
+fn pkcs7_certificates(message: &[u8]) -> Result<Vec<&[u8]>, der::Error> {
+    let mut content_info = Reader::new(der::parse(message, der::SEQUENCE)?);
+    if content_info.read(der::OID)? != OID_PKCS7_SIGNED_DATA {
+        return Err(der::Error);
+    }
+    let content = content_info.read(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)?;
+    content_info.finish()?;
+
+    let mut signed_data = Reader::new(der::parse(content, der::SEQUENCE)?);
+    signed_data.read(der::INTEGER)?;
+    signed_data.read(der::SET)?;
+    signed_data.read(der::SEQUENCE)?;
+    let mut certs = Vec::new();
+    if let Some(choices) = signed_data.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)? {
+        // Other choices of certificate, such as attribute certificates, have tags
+        // of their own.
+        let mut reader = Reader::new(choices);
+        while !reader.is_empty() {
+            let (tag, _, cert) = reader.read_any()?;
+            if tag == der::SEQUENCE {
+                certs.push(cert);
+            }
+        }
+    }
+    Ok(certs)
+}
+
+
+#[cfg(test)]
+mod test {
+    use aia::{fetch_missing_issuers, parse_certificates};
+    use test::{test_pki_chain, test_pki_root, MemoryFetcher};
+
+    #[test]
+    fn parses_fetched_certificates() {
+        let intermediate = test_pki_chain()[1];
+        assert_eq!(parse_certificates(intermediate), vec![intermediate.to_vec()]);
+        let bundle = include_bytes!("../fixtures/aia/intermediate.p7c");
+        assert_eq!(parse_certificates(bundle), vec![intermediate.to_vec()]);
+        assert!(parse_certificates(b"<html>Not Found</html>").is_empty());
+        assert!(parse_certificates(&intermediate[..100]).is_empty());
+    }
+
+    #[test]
+    fn fetches_only_missing_issuers() {
+        let leaf = include_bytes!("../fixtures/aia/leaf.crt");
+        let intermediate = test_pki_chain()[1];
+        let fetcher = MemoryFetcher::with_intermediate();
+        let mut fetched = Vec::new();
+
+        // The intermediate names the root, but doesn't say where to find it.
+        let found = fetch_missing_issuers(&fetcher, &[leaf, intermediate], &mut fetched);
+        assert!(found.is_empty());
+        assert!(fetcher.requests().is_empty());
+
+        let found = fetch_missing_issuers(&fetcher, &[leaf], &mut fetched);
+        assert_eq!(found, vec![intermediate.to_vec()]);
+        assert_eq!(fetched, vec!["http://certitude.test/intermediate.crt"]);
+
+        // Nothing is fetched twice.
+        let found = fetch_missing_issuers(&fetcher, &[leaf], &mut fetched);
+        assert!(found.is_empty());
+        assert_eq!(fetcher.requests().len(), 1);
+
+        // A self-signed certificate has no issuer to fetch.
+        let found = fetch_missing_issuers(&fetcher, &[test_pki_root()], &mut Vec::new());
+        assert!(found.is_empty());
+        assert_eq!(fetcher.requests().len(), 1);
+    }
+
+    #[test]
+    fn ignores_unusable_responses() {
+        let leaf = include_bytes!("../fixtures/aia/leaf.crt");
+        let fetcher = MemoryFetcher::new(&[("http://certitude.test/intermediate.crt", b"<html>Not Found</html>")]);
+        assert!(fetch_missing_issuers(&fetcher, &[leaf], &mut Vec::new()).is_empty());
+        let fetcher = MemoryFetcher::new(&[]);
+        assert!(fetch_missing_issuers(&fetcher, &[leaf], &mut Vec::new()).is_empty());
+        assert_eq!(fetcher.requests(), vec!["http://certitude.test/intermediate.crt"]);
+    }
+}
@@ -0,0 +1,280 @@
+// Restricting the algorithms and key sizes that a verified chain may use.
+//
+// Each platform has its own idea of which algorithms are too weak to trust, and
+// changes it from one release to the next. An `AlgorithmPolicy` is checked here,
+// against the chain the platform verified, so that a chain is accepted or rejected
+// for its algorithms the same way everywhere.
+
+use std::fmt;
+
+use error::ErrorKind;
+use signature::{self, KeyType};
+use x509::Certificate;
+
+
+/// A hash function that a certificate's signature is made with.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+/// A kind of signature, whatever hash it is made with.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SignatureAlgorithm {
+    /// RSA with PKCS#1 v1.5 padding.
+    RsaPkcs1,
+    /// RSA with PSS padding.
+    RsaPss,
+    /// ECDSA, on any curve.
+    Ecdsa,
+    /// EdDSA on Curve25519.
+    Ed25519,
+    /// EdDSA on Curve448.
+    Ed448,
+}
+
+/// A named elliptic curve that an ECDSA key is on.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Curve {
+    /// NIST P-192, also known as secp192r1.
+    P192,
+    /// NIST P-224, also known as secp224r1.
+    P224,
+    /// NIST P-256, also known as secp256r1 and prime256v1.
+    P256,
+    /// NIST P-384, also known as secp384r1.
+    P384,
+    /// NIST P-521, also known as secp521r1.
+    P521,
+}
+
+/// An algorithm or key that a chain was rejected for, as `ValidationError::algorithm`
+/// reports it.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Algorithm {
+    /// A signature made with the hash.
+    Hash(HashAlgorithm),
+    /// A signature of this kind.
+    Signature(SignatureAlgorithm),
+    /// An RSA key, with the size of its modulus in bits.
+    RsaKey(usize),
+    /// An elliptic curve key on the curve.
+    Curve(Curve),
+    /// A signature or elliptic curve that isn't any of the above. A policy that
+    /// restricts signatures or curves doesn't allow these.
+    Unrecognised,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Algorithm::Hash(hash) => f.write_str(match hash {
+                HashAlgorithm::Sha1 => "SHA-1",
+                HashAlgorithm::Sha256 => "SHA-256",
+                HashAlgorithm::Sha384 => "SHA-384",
+                HashAlgorithm::Sha512 => "SHA-512",
+            }),
+            Algorithm::Signature(signature) => f.write_str(match signature {
+                SignatureAlgorithm::RsaPkcs1 => "RSA PKCS#1 v1.5",
+                SignatureAlgorithm::RsaPss => "RSA-PSS",
+                SignatureAlgorithm::Ecdsa => "ECDSA",
+                SignatureAlgorithm::Ed25519 => "Ed25519",
+                SignatureAlgorithm::Ed448 => "Ed448",
+            }),
+            Algorithm::RsaKey(bits) => write!(f, "{}-bit RSA key", bits),
+            Algorithm::Curve(curve) => f.write_str(match curve {
+                Curve::P192 => "P-192",
+                Curve::P224 => "P-224",
+                Curve::P256 => "P-256",
+                Curve::P384 => "P-384",
+                Curve::P521 => "P-521",
+            }),
+            Algorithm::Unrecognised => f.write_str("unrecognised algorithm"),
+        }
+    }
+}
+
+
+/// Which algorithms and key sizes a verified chain may use.
+///
+/// Signatures are checked on every certificate below the trust anchor: the anchor's
+/// own signature, if it has one, isn't relied on. Keys are checked on every
+/// certificate, the anchor's included. A chain that breaks the policy fails with
+/// `ErrorKind::DisallowedAlgorithm`, or `ErrorKind::WeakKey` for an RSA key that is
+/// too small, and `ValidationError::algorithm` says what was found.
+///
+/// The default policy allows anything the platform does. Each method restricts one
+/// part of it, replacing any restriction made before.
+///
+/// # Examples
+///
+/// ```
+/// // No SHA-1 below the anchor, RSA keys of at least 2048 bits, and no P-192.
+/// let policy = AlgorithmPolicy::new()
+///     .hashes(&[HashAlgorithm::Sha256, HashAlgorithm::Sha384, HashAlgorithm::Sha512])
+///     .minimum_rsa_bits(2048)
+///     .curves(&[Curve::P256, Curve::P384, Curve::P521]);
+/// let verifier = Verifier::builder().algorithm_policy(policy).build();
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct AlgorithmPolicy {
+    // The algorithms allowed, or `None` to allow any.
+    hashes: Option<Vec<HashAlgorithm>>,
+    signatures: Option<Vec<SignatureAlgorithm>>,
+    curves: Option<Vec<Curve>>,
+    minimum_rsa_bits: usize,
+}
+
+impl AlgorithmPolicy {
+    /// A policy that allows anything the platform does.
+    pub fn new() -> AlgorithmPolicy {
+        AlgorithmPolicy::default()
+    }
+
+    /// Allow only signatures made with the given hashes. EdDSA signatures don't
+    /// have a separate hash, so aren't affected.
+    pub fn hashes(mut self, hashes: &[HashAlgorithm]) -> AlgorithmPolicy {
+        self.hashes = Some(hashes.to_vec());
+        self
+    }
+
+    /// Allow only the given kinds of signature.
+    pub fn signature_algorithms(mut self, signatures: &[SignatureAlgorithm]) -> AlgorithmPolicy {
+        self.signatures = Some(signatures.to_vec());
+        self
+    }
+
+    /// Allow only elliptic curve keys on the given curves.
+    pub fn curves(mut self, curves: &[Curve]) -> AlgorithmPolicy {
+        self.curves = Some(curves.to_vec());
+        self
+    }
+
+    /// Allow only RSA keys with a modulus of at least `bits`.
+    pub fn minimum_rsa_bits(mut self, bits: usize) -> AlgorithmPolicy {
+        self.minimum_rsa_bits = bits;
+        self
+    }
+
+    // Checks the certificates of a verified chain, from the leaf to the trust anchor.
+    // On failure, returns the position in the chain of the first certificate that
+    // breaks the policy, with how it breaks it.
+    pub(crate) fn check_chain(&self, certs: &[Certificate]) -> Result<(), (usize, ErrorKind, Algorithm)> {
+        for (index, cert) in certs.iter().enumerate() {
+            if index + 1 < certs.len() {
+                self.check_signature(cert).map_err(|algorithm| (index, ErrorKind::DisallowedAlgorithm, algorithm))?;
+            }
+            self.check_key(cert).map_err(|(kind, algorithm)| (index, kind, algorithm))?;
+        }
+        Ok(())
+    }
+
+    fn check_signature(&self, cert: &Certificate) -> Result<(), Algorithm> {
+        if self.hashes.is_none() && self.signatures.is_none() {
+            return Ok(());
+        }
+        let (signature, hash) = signature::signature_algorithm(&cert.signature_algorithm)
+            .ok_or(Algorithm::Unrecognised)?;
+        if !allows(&self.signatures, &signature) {
+            return Err(Algorithm::Signature(signature));
+        }
+        match hash {
+            Some(hash) if !allows(&self.hashes, &hash) => Err(Algorithm::Hash(hash)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_key(&self, cert: &Certificate) -> Result<(), (ErrorKind, Algorithm)> {
+        if let Some(bits) = signature::rsa_modulus_bits(&cert.spki) {
+            if bits < self.minimum_rsa_bits {
+                return Err((ErrorKind::WeakKey, Algorithm::RsaKey(bits)));
+            }
+        }
+        if self.curves.is_some() && signature::key_type(&cert.spki) == KeyType::Ec {
+            match signature::curve(&cert.spki) {
+                Some(curve) if allows(&self.curves, &curve) => {}
+                Some(curve) => return Err((ErrorKind::DisallowedAlgorithm, Algorithm::Curve(curve))),
+                None => return Err((ErrorKind::DisallowedAlgorithm, Algorithm::Unrecognised)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn allows<T: PartialEq>(allowed: &Option<Vec<T>>, algorithm: &T) -> bool {
+    allowed.as_ref().is_none_or(|allowed| allowed.contains(algorithm))
+}
+
+
+#[cfg(test)]
+mod test {
+    use algorithms::{Algorithm, AlgorithmPolicy, Curve, HashAlgorithm, SignatureAlgorithm};
+    use error::ErrorKind;
+    use test::{test_pki_chain, test_pki_root};
+    use x509::Certificate;
+
+    fn check(policy: &AlgorithmPolicy, certs: &[&'static [u8]]) -> Result<(), (usize, ErrorKind, Algorithm)> {
+        let certs = certs.iter().map(|cert| Certificate::parse(cert).unwrap()).collect::<Vec<_>>();
+        policy.check_chain(&certs)
+    }
+
+    fn no_sha1() -> AlgorithmPolicy {
+        AlgorithmPolicy::new().hashes(&[HashAlgorithm::Sha256, HashAlgorithm::Sha384, HashAlgorithm::Sha512])
+    }
+
+    #[test]
+    fn allows_anything_by_default() {
+        let chain = test_pki_chain();
+        let sha1_intermediate = include_bytes!("../fixtures/algorithms/sha1-intermediate.crt");
+        let p192 = include_bytes!("../fixtures/algorithms/p192.crt");
+        let policy = AlgorithmPolicy::new();
+        assert_eq!(check(&policy, &[chain[0], sha1_intermediate, test_pki_root()]), Ok(()));
+        assert_eq!(check(&policy, &[p192, chain[1], test_pki_root()]), Ok(()));
+    }
+
+    #[test]
+    fn checks_hashes_below_the_anchor() {
+        let chain = test_pki_chain();
+        let sha1_intermediate = include_bytes!("../fixtures/algorithms/sha1-intermediate.crt");
+        let sha1_root = include_bytes!("../fixtures/algorithms/sha1-root.crt");
+        assert_eq!(check(&no_sha1(), &[chain[0], chain[1], test_pki_root()]), Ok(()));
+        assert_eq!(check(&no_sha1(), &[chain[0], sha1_intermediate, test_pki_root()]),
+                   Err((1, ErrorKind::DisallowedAlgorithm, Algorithm::Hash(HashAlgorithm::Sha1))));
+        assert_eq!(check(&no_sha1(), &[chain[0], chain[1], sha1_root]), Ok(()));
+    }
+
+    #[test]
+    fn checks_signature_algorithms() {
+        // The intermediate is signed with RSA, and the leaf with ECDSA.
+        let chain = test_pki_chain();
+        let policy = AlgorithmPolicy::new().signature_algorithms(&[SignatureAlgorithm::Ecdsa]);
+        assert_eq!(check(&policy, &[chain[0], chain[1], test_pki_root()]),
+                   Err((1, ErrorKind::DisallowedAlgorithm, Algorithm::Signature(SignatureAlgorithm::RsaPkcs1))));
+        assert_eq!(check(&policy, &[chain[0], chain[1]]), Ok(()));
+    }
+
+    #[test]
+    fn checks_keys() {
+        let chain = test_pki_chain();
+        let p192 = include_bytes!("../fixtures/algorithms/p192.crt");
+        let policy = AlgorithmPolicy::new().curves(&[Curve::P256, Curve::P384, Curve::P521]);
+        assert_eq!(check(&policy, &[chain[0], chain[1], test_pki_root()]), Ok(()));
+        assert_eq!(check(&policy, &[p192, chain[1], test_pki_root()]),
+                   Err((0, ErrorKind::DisallowedAlgorithm, Algorithm::Curve(Curve::P192))));
+
+        // The anchor's key counts.
+        let policy = AlgorithmPolicy::new().minimum_rsa_bits(3072);
+        assert_eq!(check(&policy, &[chain[0], chain[1], test_pki_root()]),
+                   Err((2, ErrorKind::WeakKey, Algorithm::RsaKey(2048))));
+    }
+
+    #[test]
+    fn describes_algorithms() {
+        assert_eq!(Algorithm::Hash(HashAlgorithm::Sha1).to_string(), "SHA-1");
+        assert_eq!(Algorithm::RsaKey(1024).to_string(), "1024-bit RSA key");
+        assert_eq!(Algorithm::Curve(Curve::P192).to_string(), "P-192");
+    }
+}
@@ -0,0 +1,155 @@
+// A cache of the intermediates in chains a verifier has verified, for completing
+// later chains that are missing them.
+//
+// Platform verifiers remember the intermediates they see, so a server that leaves
+// one out still validates once some other chain has brought it. A verifier's cache
+// does the same, but belongs to that verifier and its clones: nothing is shared
+// with any other verifier.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+
+use chain::VerifiedChain;
+use issuers;
+use x509::{self, Certificate};
+
+
+pub(crate) struct IntermediateCache {
+    capacity: usize,
+    // The subject names and DER encodings of the cached certificates, least recently
+    // verified first.
+    entries: Mutex<VecDeque<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl IntermediateCache {
+    pub fn new(capacity: usize) -> IntermediateCache {
+        IntermediateCache {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // Remembers the intermediates of a verified chain, forgetting those verified
+    // least recently once the cache is full. Neither the leaf nor the trust anchor
+    // is an intermediate, so neither is remembered.
+    pub fn insert(&self, chain: &VerifiedChain) {
+        let mut entries = self.entries();
+        for encoded in chain.intermediates() {
+            let subject = match Certificate::parse(encoded) {
+                Ok(cert) => cert.subject.to_vec(),
+                Err(_) => continue,
+            };
+            if let Some(index) = entries.iter().position(|(_, cached)| cached == encoded) {
+                entries.remove(index);
+            }
+            entries.push_back((subject, encoded.clone()));
+            while entries.len() > self.capacity {
+                entries.pop_front();
+            }
+        }
+    }
+
+    // Returns the cached certificates that issued those in the chain whose issuers
+    // are missing from it, going by names, leaving out any already in the chain.
+    pub fn missing_issuers(&self, encoded_certs: &[&[u8]]) -> Vec<Vec<u8>> {
+        let certs = encoded_certs.iter().filter_map(|cert| Certificate::parse(cert).ok()).collect::<Vec<_>>();
+        let mut found = Vec::new();
+        for index in issuers::missing_issuers(&certs) {
+            for issuer in self.issuers(certs[index].issuer) {
+                if !encoded_certs.contains(&&issuer[..]) && !found.contains(&issuer) {
+                    found.push(issuer);
+                }
+            }
+        }
+        found
+    }
+
+    // Returns the DER encodings of the cached certificates with the given subject
+    // name, most recently verified first.
+    pub fn issuers(&self, name: &[u8]) -> Vec<Vec<u8>> {
+        self.entries().iter().rev()
+            .filter(|(subject, _)| x509::same_name(subject, name))
+            .map(|(_, encoded)| encoded.clone())
+            .collect()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, VecDeque<(Vec<u8>, Vec<u8>)>> {
+        // Nothing panics while holding the lock with the cache half-updated, so a
+        // poisoned cache is still usable.
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for IntermediateCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IntermediateCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.entries().len())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use cache::IntermediateCache;
+    use chain::VerifiedChain;
+    use test::{test_pki_chain, test_pki_root};
+    use x509::Certificate;
+
+    #[test]
+    fn remembers_intermediates() {
+        let chain = test_pki_chain();
+        let cache = IntermediateCache::new(4);
+        let verified = VerifiedChain::new(vec![chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]).unwrap();
+        cache.insert(&verified);
+        cache.insert(&verified);
+
+        let leaf = Certificate::parse(chain[0]).unwrap();
+        let intermediate = Certificate::parse(chain[1]).unwrap();
+        assert_eq!(cache.issuers(leaf.issuer), vec![chain[1].to_vec()]);
+        assert!(cache.issuers(intermediate.issuer).is_empty());
+        assert!(cache.issuers(leaf.subject).is_empty());
+
+        assert_eq!(cache.missing_issuers(&[chain[0]]), vec![chain[1].to_vec()]);
+        assert!(cache.missing_issuers(&[chain[0], chain[1]]).is_empty());
+    }
+
+    #[test]
+    fn matches_names_encoded_differently() {
+        // The leaf names its issuer in upper case and with PrintableStrings.
+        let chain = test_pki_chain();
+        let leaf = include_bytes!("../fixtures/reencoded-names/leaf.crt");
+        let cache = IntermediateCache::new(4);
+        cache.insert(&VerifiedChain::new(vec![chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]).unwrap());
+        assert_eq!(cache.missing_issuers(&[leaf]), vec![chain[1].to_vec()]);
+    }
+
+    #[test]
+    fn forgets_the_least_recently_verified() {
+        let chain = test_pki_chain();
+        let cross_signed = include_bytes!("../fixtures/cross-signed/intermediate-by-bridge.crt");
+        let bridge = include_bytes!("../fixtures/cross-signed/expired-bridge.crt");
+        let name = Certificate::parse(chain[1]).unwrap().subject;
+        let cache = IntermediateCache::new(2);
+        let verify = |intermediates: &[&[u8]]| {
+            let mut certs = vec![chain[0].to_vec()];
+            certs.extend(intermediates.iter().map(|cert| cert.to_vec()));
+            certs.push(test_pki_root().to_vec());
+            cache.insert(&VerifiedChain::new(certs).unwrap());
+        };
+
+        verify(&[chain[1]]);
+        verify(&[cross_signed]);
+        assert_eq!(cache.issuers(name), vec![cross_signed.to_vec(), chain[1].to_vec()]);
+        verify(&[chain[1]]);
+        assert_eq!(cache.issuers(name), vec![chain[1].to_vec(), cross_signed.to_vec()]);
+        verify(&[bridge]);
+        assert_eq!(cache.issuers(name), vec![chain[1].to_vec()]);
+
+        let cache = IntermediateCache::new(0);
+        cache.insert(&VerifiedChain::new(vec![chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]).unwrap());
+        assert!(cache.issuers(name).is_empty());
+    }
+}
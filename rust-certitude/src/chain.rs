@@ -0,0 +1,87 @@
+// The certificate chain built by a successful validation.
+
+use ocsp::OcspStatus;
+
+
+/// The chain of certificates that validation built, from the leaf to the trust anchor.
+///
+/// This is the path the platform actually used, which needn't be the chain it was
+/// given: intermediates may have been reordered or left out, and the trust anchor
+/// usually isn't in the supplied chain at all. If the leaf is itself a trust anchor,
+/// the chain holds just that one certificate.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct VerifiedChain {
+    certificates: Vec<Vec<u8>>,
+    ocsp_status: Option<OcspStatus>,
+}
+
+impl VerifiedChain {
+    // Returns `None` if there are no certificates, as there is then no chain.
+    pub(crate) fn new(certificates: Vec<Vec<u8>>) -> Option<VerifiedChain> {
+        if certificates.is_empty() {
+            None
+        } else {
+            Some(VerifiedChain { certificates, ocsp_status: None })
+        }
+    }
+
+    pub(crate) fn with_ocsp_status(self, ocsp_status: Option<OcspStatus>) -> VerifiedChain {
+        VerifiedChain { ocsp_status, ..self }
+    }
+
+    /// The DER-encoded certificates in the chain, leaf first and trust anchor last.
+    pub fn certificates(&self) -> &[Vec<u8>] {
+        &self.certificates
+    }
+
+    /// The DER-encoded leaf certificate.
+    pub fn leaf(&self) -> &[u8] {
+        &self.certificates[0]
+    }
+
+    /// The DER-encoded intermediate certificates, in order from the leaf's issuer
+    /// towards the trust anchor.
+    pub fn intermediates(&self) -> &[Vec<u8>] {
+        match self.certificates.len() {
+            1 => &[],
+            len => &self.certificates[1..len - 1],
+        }
+    }
+
+    /// The DER-encoded trust anchor the chain ends at.
+    pub fn anchor(&self) -> &[u8] {
+        &self.certificates[self.certificates.len() - 1]
+    }
+
+    /// The status of the leaf that the OCSP response stapled to the handshake gave,
+    /// if one was given to `Verifier::verify_stapled` and could be relied on.
+    pub fn ocsp_status(&self) -> Option<OcspStatus> {
+        self.ocsp_status
+    }
+
+    /// Consumes the chain, returning the DER-encoded certificates in it.
+    pub fn into_certificates(self) -> Vec<Vec<u8>> {
+        self.certificates
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use chain::VerifiedChain;
+
+    #[test]
+    fn splits_the_chain() {
+        let chain = VerifiedChain::new(vec![vec![1], vec![2], vec![3]]).unwrap();
+        assert_eq!(chain.leaf(), &[1]);
+        assert_eq!(chain.intermediates(), &[vec![2]]);
+        assert_eq!(chain.anchor(), &[3]);
+
+        let chain = VerifiedChain::new(vec![vec![1]]).unwrap();
+        assert_eq!(chain.leaf(), &[1]);
+        assert!(chain.intermediates().is_empty());
+        assert_eq!(chain.anchor(), &[1]);
+
+        assert_eq!(VerifiedChain::new(Vec::new()), None);
+    }
+}
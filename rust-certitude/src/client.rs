@@ -0,0 +1,155 @@
+// Checking the identity of a TLS client.
+//
+// A server has no hostname to check a client's certificate against, but it may
+// still want to know who the client is: a workload identified by a SPIFFE ID, a
+// user by their email address, and so on. The verifier can require the leaf to have
+// one of a list of names, which we check here.
+
+use x509::{Certificate, GeneralName};
+
+
+/// A name that a `Verifier` can require a client certificate to have.
+///
+/// Names are looked for only where each kind belongs: URIs, email addresses and DNS
+/// names in the subject alternative names, and common names in the subject. Email
+/// addresses in the subject count too, as some older certificates only have those.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ClientName {
+    /// A URI, such as the SPIFFE ID `spiffe://example.org/ns/prod/sa/gateway`. A URI
+    /// ending in `*` matches every URI that starts with the rest, so
+    /// `spiffe://example.org/ns/prod/*` matches every workload in that namespace.
+    Uri(String),
+    /// An email address. An address starting with `@` matches every address at that
+    /// domain, so `@example.org` matches `alice@example.org`. Domains are compared
+    /// without regard to case, but the part before the `@` is compared exactly.
+    Email(String),
+    /// A DNS name, compared without regard to case. Wildcards aren't expanded.
+    DnsName(String),
+    /// A common name in the subject. As with URIs, a name ending in `*` matches every
+    /// name that starts with the rest.
+    CommonName(String),
+}
+
+
+// Whether the leaf has one of the names. No names at all means any leaf will do.
+pub(crate) fn has_any_name(leaf: &Certificate, names: &[ClientName]) -> bool {
+    if names.is_empty() {
+        return true;
+    }
+
+    let alt_names = leaf.subject_alt_names().unwrap_or_default();
+    let mut uris = Vec::new();
+    let mut emails = leaf.email_addresses();
+    let mut dns_names = Vec::new();
+    for alt_name in alt_names {
+        match alt_name {
+            GeneralName::Uri(uri) => uris.push(uri),
+            GeneralName::Rfc822Name(email) => emails.push(email),
+            GeneralName::DnsName(dns_name) => dns_names.push(dns_name),
+            _ => {}
+        }
+    }
+    let common_names = leaf.common_names();
+    names.iter().any(|name| match *name {
+        ClientName::Uri(ref pattern) => uris.iter().any(|uri| matches_prefix(uri, pattern)),
+        ClientName::Email(ref pattern) => emails.iter().any(|email| matches_email(email, pattern)),
+        ClientName::DnsName(ref expected) => {
+            let expected = expected.trim_end_matches('.').as_bytes();
+            dns_names.iter().any(|dns_name| {
+                let dns_name = dns_name.strip_suffix(b".").unwrap_or(dns_name);
+                !dns_name.is_empty() && dns_name.eq_ignore_ascii_case(expected)
+            })
+        }
+        ClientName::CommonName(ref pattern) => common_names.iter().any(|cn| matches_prefix(cn, pattern)),
+    })
+}
+
+
+// Whether a name matches a pattern that is either exact or, ending in `*`, a prefix.
+fn matches_prefix(name: &[u8], pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix.as_bytes()),
+        None => name == pattern.as_bytes(),
+    }
+}
+
+// Whether an email address matches a pattern that is either an address or, starting
+// with `@`, a domain.
+fn matches_email(email: &[u8], pattern: &str) -> bool {
+    let (local, domain) = match split_email(email) {
+        Some((local, domain)) if !local.is_empty() => (local, domain),
+        _ => return false,
+    };
+    match split_email(pattern.as_bytes()) {
+        Some((b"", pattern_domain)) => domain.eq_ignore_ascii_case(pattern_domain),
+        Some((pattern_local, pattern_domain)) => local == pattern_local && domain.eq_ignore_ascii_case(pattern_domain),
+        None => false,
+    }
+}
+
+// Splits an email address into the parts before and after its last `@`.
+fn split_email(email: &[u8]) -> Option<(&[u8], &[u8])> {
+    let at = email.iter().rposition(|&b| b == b'@')?;
+    Some((&email[..at], &email[at + 1..]))
+}
+
+
+#[cfg(test)]
+mod test {
+    use client::{has_any_name, matches_email, ClientName};
+    use test::test_pki_chain;
+    use x509::Certificate;
+
+    fn gateway() -> Certificate<'static> {
+        Certificate::parse(include_bytes!("../fixtures/client/gateway.crt")).unwrap()
+    }
+
+    #[test]
+    fn matches_uris() {
+        let has = |uri: &str| has_any_name(&gateway(), &[ClientName::Uri(uri.to_owned())]);
+        assert!(has("spiffe://certitude.test/ns/prod/sa/gateway"));
+        assert!(has("spiffe://certitude.test/ns/prod/*"));
+        assert!(has("*"));
+        assert!(!has("spiffe://certitude.test/ns/prod/sa/gate"));
+        assert!(!has("spiffe://certitude.test/ns/dev/*"));
+        assert!(!has("SPIFFE://certitude.test/ns/prod/sa/gateway"));
+    }
+
+    #[test]
+    fn matches_email_addresses() {
+        let has = |email: &str| has_any_name(&gateway(), &[ClientName::Email(email.to_owned())]);
+        assert!(has("gateway@certitude.test"));
+        assert!(has("gateway@CERTITUDE.test"));
+        assert!(has("@certitude.test"));
+        assert!(!has("Gateway@certitude.test"));
+        assert!(!has("@test"));
+        assert!(!has("certitude.test"));
+
+        assert!(!matches_email(b"@certitude.test", "@certitude.test"));
+        assert!(matches_email(b"a@b@certitude.test", "a@b@certitude.test"));
+    }
+
+    #[test]
+    fn matches_dns_names_and_common_names() {
+        let leaf = gateway();
+        assert!(has_any_name(&leaf, &[ClientName::DnsName("Gateway.Certitude.Test.".to_owned())]));
+        assert!(!has_any_name(&leaf, &[ClientName::DnsName("*.certitude.test".to_owned())]));
+        assert!(has_any_name(&leaf, &[ClientName::CommonName("Certitude Test Gateway".to_owned())]));
+        assert!(has_any_name(&leaf, &[ClientName::CommonName("Certitude Test *".to_owned())]));
+        // The common name isn't a DNS name, and DNS names aren't common names.
+        assert!(!has_any_name(&leaf, &[ClientName::CommonName("gateway.certitude.test".to_owned())]));
+    }
+
+    #[test]
+    fn needs_only_one_name() {
+        let names = [
+            ClientName::Uri("spiffe://elsewhere.test/*".to_owned()),
+            ClientName::Email("gateway@certitude.test".to_owned()),
+        ];
+        assert!(has_any_name(&gateway(), &names));
+        assert!(has_any_name(&gateway(), &[]));
+
+        let leaf = Certificate::parse(test_pki_chain()[0]).unwrap();
+        assert!(!has_any_name(&leaf, &names));
+    }
+}
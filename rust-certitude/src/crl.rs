@@ -0,0 +1,352 @@
+// Checking a verified chain against certificate revocation lists, as profiled by
+// RFC 5280.
+//
+// The lists are the caller's, rather than fetched from the distribution points that
+// certificates give, so that revocation can be checked where nothing can be fetched.
+// A list is only used for a certificate if it was issued, and signed, by the
+// certificate's issuer in the chain, and is current at the time of validation.
+//
+// We don't support lists that only cover some of what their issuer issued, or that
+// cover what others issued: RFC 5280 marks the extensions that make them critical,
+// and a list with a critical extension we don't understand isn't used at all.
+
+use std::fmt;
+
+use der::{self, Reader};
+use error::ErrorKind;
+use signature;
+use x509::{self, AlgorithmIdentifier, Certificate};
+
+const OID_CRL_NUMBER: &[u8] = &[0x55, 0x1d, 0x14];
+const OID_CRL_REASON: &[u8] = &[0x55, 0x1d, 0x15];
+const OID_AUTHORITY_KEY_IDENTIFIER: &[u8] = &[0x55, 0x1d, 0x23];
+const OID_INVALIDITY_DATE: &[u8] = &[0x55, 0x1d, 0x18];
+
+
+/// Why a certificate was revoked, as its issuer gave it.
+///
+/// These are the reason codes of RFC 5280, which OCSP uses too.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum RevocationReason {
+    Unspecified,
+    KeyCompromise,
+    CaCompromise,
+    AffiliationChanged,
+    Superseded,
+    CessationOfOperation,
+    /// The certificate is suspended, and may yet be reinstated.
+    CertificateHold,
+    /// Only used in delta CRLs, to reinstate a certificate that was on hold.
+    RemoveFromCrl,
+    PrivilegeWithdrawn,
+    AaCompromise,
+}
+
+impl RevocationReason {
+    // The reason for a CRLReason code.
+    pub(crate) fn from_code(code: u8) -> Option<RevocationReason> {
+        match code {
+            0 => Some(RevocationReason::Unspecified),
+            1 => Some(RevocationReason::KeyCompromise),
+            2 => Some(RevocationReason::CaCompromise),
+            3 => Some(RevocationReason::AffiliationChanged),
+            4 => Some(RevocationReason::Superseded),
+            5 => Some(RevocationReason::CessationOfOperation),
+            6 => Some(RevocationReason::CertificateHold),
+            8 => Some(RevocationReason::RemoveFromCrl),
+            9 => Some(RevocationReason::PrivilegeWithdrawn),
+            10 => Some(RevocationReason::AaCompromise),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RevocationReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            RevocationReason::Unspecified => "unspecified",
+            RevocationReason::KeyCompromise => "key compromise",
+            RevocationReason::CaCompromise => "CA compromise",
+            RevocationReason::AffiliationChanged => "affiliation changed",
+            RevocationReason::Superseded => "superseded",
+            RevocationReason::CessationOfOperation => "cessation of operation",
+            RevocationReason::CertificateHold => "certificate hold",
+            RevocationReason::RemoveFromCrl => "remove from CRL",
+            RevocationReason::PrivilegeWithdrawn => "privilege withdrawn",
+            RevocationReason::AaCompromise => "AA compromise",
+        })
+    }
+}
+
+
+pub struct Crl<'a> {
+    pub tbs: &'a [u8],
+    pub signature_algorithm: AlgorithmIdentifier<'a>,
+    pub signature: &'a [u8],
+    // The complete encoding of the issuer's name.
+    pub issuer: &'a [u8],
+    // When the list was issued, and when the next will be, in seconds since the Unix
+    // epoch. A list without a next update is current for ever.
+    pub this_update: i64,
+    pub next_update: Option<i64>,
+    // The serial numbers of the revoked certificates, as the contents of their
+    // INTEGERs, with the reasons given for revoking them.
+    pub revoked: Vec<(&'a [u8], Option<RevocationReason>)>,
+    // Whether the list, or an entry in it, has a critical extension we don't
+    // understand.
+    pub unhandled_critical_extension: bool,
+}
+
+impl<'a> Crl<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Crl<'a>, der::Error> {
+        let mut outer = Reader::new(data);
+        let encoded = outer.read_element(der::SEQUENCE)?;
+        outer.finish()?;
+
+        let mut crl = Reader::new(der::parse(encoded, der::SEQUENCE)?);
+        let tbs = crl.read_element(der::SEQUENCE)?;
+        let signature_algorithm = x509::parse_algorithm(&mut crl)?;
+        let signature = crl.read_bit_string()?;
+        crl.finish()?;
+
+        let mut tbs_reader = Reader::new(der::parse(tbs, der::SEQUENCE)?);
+        // Only version 2 lists have a version, which is encoded as 1.
+        let version = if tbs_reader.peek_tag() == Some(der::INTEGER) {
+            tbs_reader.read_small_uint()?
+        } else {
+            0
+        };
+        if version > 1 {
+            return Err(der::Error);
+        }
+        let inner_algorithm = x509::parse_algorithm(&mut tbs_reader)?;
+        if inner_algorithm.oid != signature_algorithm.oid ||
+           inner_algorithm.parameters != signature_algorithm.parameters {
+            return Err(der::Error);
+        }
+        let issuer = tbs_reader.read_element(der::SEQUENCE)?;
+        let this_update = x509::parse_time(&mut tbs_reader)?;
+        let next_update = match tbs_reader.peek_tag() {
+            Some(der::UTC_TIME) | Some(der::GENERALIZED_TIME) => Some(x509::parse_time(&mut tbs_reader)?),
+            _ => None,
+        };
+
+        let mut list = Crl {
+            tbs,
+            signature_algorithm,
+            signature,
+            issuer,
+            this_update,
+            next_update,
+            revoked: Vec::new(),
+            unhandled_critical_extension: false,
+        };
+
+        if let Some(entries) = tbs_reader.read_optional(der::SEQUENCE)? {
+            let mut entries = Reader::new(entries);
+            while !entries.is_empty() {
+                list.parse_entry(entries.read(der::SEQUENCE)?)?;
+            }
+        }
+        if let Some(extensions) = tbs_reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)? {
+            if version != 1 {
+                return Err(der::Error);
+            }
+            for extension in parse_extensions(der::parse(extensions, der::SEQUENCE)?)? {
+                match extension.oid {
+                    OID_CRL_NUMBER | OID_AUTHORITY_KEY_IDENTIFIER => {}
+                    _ if extension.critical => list.unhandled_critical_extension = true,
+                    _ => {}
+                }
+            }
+        }
+        tbs_reader.finish()?;
+
+        Ok(list)
+    }
+
+    fn parse_entry(&mut self, entry: &'a [u8]) -> Result<(), der::Error> {
+        let mut reader = Reader::new(entry);
+        let serial = reader.read(der::INTEGER)?;
+        x509::parse_time(&mut reader)?;
+        let mut reason = None;
+        if let Some(extensions) = reader.read_optional(der::SEQUENCE)? {
+            for extension in parse_extensions(extensions)? {
+                match extension.oid {
+                    OID_CRL_REASON => {
+                        reason = match *der::parse(extension.value, der::ENUMERATED)? {
+                            [code] => Some(RevocationReason::from_code(code).ok_or(der::Error)?),
+                            _ => return Err(der::Error),
+                        };
+                    }
+                    OID_INVALIDITY_DATE => {}
+                    _ if extension.critical => self.unhandled_critical_extension = true,
+                    _ => {}
+                }
+            }
+        }
+        reader.finish()?;
+        self.revoked.push((serial, reason));
+        Ok(())
+    }
+
+    // Whether the list can be relied on for the certificates of the given issuer, at
+    // the given time.
+    fn covers(&self, issuer: &Certificate, time: i64) -> bool {
+        if self.unhandled_critical_extension || !x509::same_name(self.issuer, issuer.subject) {
+            return false;
+        }
+        if self.this_update > time || self.next_update.is_some_and(|next_update| next_update < time) {
+            return false;
+        }
+        if issuer.key_usage.is_some_and(|usage| usage & x509::KEY_USAGE_CRL_SIGN == 0) {
+            return false;
+        }
+        signature::verify(&issuer.spki, &self.signature_algorithm, self.tbs, self.signature).is_ok()
+    }
+
+    // Whether the list revokes the certificate, and if so, the reason it gives.
+    fn revokes(&self, cert: &Certificate) -> Option<Option<RevocationReason>> {
+        self.revoked.iter().find(|&&(serial, _)| serial == cert.serial).map(|&(_, reason)| reason)
+    }
+}
+
+// An extension of a list or an entry in it. OCSP responses have extensions of the
+// same form.
+pub(crate) struct Extension<'a> {
+    pub oid: &'a [u8],
+    pub critical: bool,
+    pub value: &'a [u8],
+}
+
+pub(crate) fn parse_extensions(extensions: &[u8]) -> Result<Vec<Extension<'_>>, der::Error> {
+    let mut reader = Reader::new(extensions);
+    let mut result = Vec::new();
+    while !reader.is_empty() {
+        let mut extension = Reader::new(reader.read(der::SEQUENCE)?);
+        let oid = extension.read(der::OID)?;
+        let critical = if extension.peek_tag() == Some(der::BOOLEAN) {
+            extension.read_bool()?
+        } else {
+            false
+        };
+        let value = extension.read(der::OCTET_STRING)?;
+        extension.finish()?;
+        result.push(Extension { oid, critical, value });
+    }
+    Ok(result)
+}
+
+
+// Checks the certificates of a verified chain, from the leaf to the trust anchor,
+// against the lists. The trust anchor isn't checked: it is trusted for what it is.
+// If `required` is set, a certificate that no usable list covers fails with
+// `ErrorKind::RevocationStatusUnknown`, unless it is the leaf and `leaf_covered` is
+// set because its status is known some other way. On failure, returns the position
+// in the chain of the first certificate that fails, with how, and for a revoked
+// certificate, the reason it was revoked.
+pub(crate) fn check_chain(certs: &[Certificate], crls: &[Crl], time: i64, required: bool, leaf_covered: bool)
+    -> Result<(), (usize, ErrorKind, Option<RevocationReason>)>
+{
+    for (index, pair) in certs.windows(2).enumerate() {
+        let (cert, issuer) = (&pair[0], &pair[1]);
+        let mut covered = index == 0 && leaf_covered;
+        for crl in crls.iter().filter(|crl| crl.covers(issuer, time)) {
+            covered = true;
+            if let Some(reason) = crl.revokes(cert) {
+                return Err((index, ErrorKind::Revoked, reason));
+            }
+        }
+        if required && !covered {
+            return Err((index, ErrorKind::RevocationStatusUnknown, None));
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+    use crl::{check_chain, Crl, RevocationReason};
+    use error::ErrorKind;
+    use test::{test_pki_chain, test_pki_root};
+    use x509::Certificate;
+
+    // 2027-01-15, when all but the stale and future lists are current.
+    const TIME: i64 = 1_800_000_000;
+
+    fn check(crls: &[&'static [u8]], required: bool) -> Result<(), (usize, ErrorKind, Option<RevocationReason>)> {
+        let chain = test_pki_chain();
+        let certs = [chain[0], chain[1], test_pki_root()].iter()
+            .map(|cert| Certificate::parse(cert).unwrap())
+            .collect::<Vec<_>>();
+        let crls = crls.iter().map(|crl| Crl::parse(crl).unwrap()).collect::<Vec<_>>();
+        check_chain(&certs, &crls, TIME, required, false)
+    }
+
+    #[test]
+    fn parses_crls() {
+        let crl = Crl::parse(include_bytes!("../fixtures/crl/intermediate-revoked.crl")).unwrap();
+        let leaf = Certificate::parse(test_pki_chain()[0]).unwrap();
+        assert_eq!(crl.issuer, leaf.issuer);
+        assert_eq!(crl.this_update, 1_735_689_600);
+        assert_eq!(crl.next_update, Some(2_208_988_800));
+        assert_eq!(crl.revoked.len(), 2);
+        assert_eq!(crl.revoked[0], (&[0x01, 0x23, 0x45, 0x67][..], None));
+        assert_eq!(crl.revokes(&leaf), Some(Some(RevocationReason::KeyCompromise)));
+        assert!(!crl.unhandled_critical_extension);
+
+        let crl = include_bytes!("../fixtures/crl/intermediate.crl");
+        assert!(Crl::parse(&crl[..crl.len() - 1]).is_err());
+        assert!(Crl::parse(test_pki_root()).is_err());
+    }
+
+    #[test]
+    fn finds_revoked_certificates() {
+        let intermediate = include_bytes!("../fixtures/crl/intermediate.crl");
+        let intermediate_revoked = include_bytes!("../fixtures/crl/intermediate-revoked.crl");
+        let root = include_bytes!("../fixtures/crl/root.crl");
+        let root_revoked = include_bytes!("../fixtures/crl/root-revoked.crl");
+        assert_eq!(check(&[intermediate, root], true), Ok(()));
+        assert_eq!(check(&[intermediate_revoked, root], true),
+                   Err((0, ErrorKind::Revoked, Some(RevocationReason::KeyCompromise))));
+        // A list that doesn't give a reason still revokes.
+        assert_eq!(check(&[intermediate, root_revoked], false), Err((1, ErrorKind::Revoked, None)));
+        // This one names its issuer in upper case and with PrintableStrings.
+        let reencoded = include_bytes!("../fixtures/crl/intermediate-reencoded.crl");
+        assert_eq!(check(&[reencoded, root], true),
+                   Err((0, ErrorKind::Revoked, Some(RevocationReason::KeyCompromise))));
+    }
+
+    #[test]
+    fn requires_a_usable_list_when_asked() {
+        let intermediate = include_bytes!("../fixtures/crl/intermediate.crl");
+        let root = include_bytes!("../fixtures/crl/root.crl");
+        assert_eq!(check(&[], false), Ok(()));
+        assert_eq!(check(&[], true), Err((0, ErrorKind::RevocationStatusUnknown, None)));
+        assert_eq!(check(&[intermediate], true), Err((1, ErrorKind::RevocationStatusUnknown, None)));
+        assert_eq!(check(&[root], true), Err((0, ErrorKind::RevocationStatusUnknown, None)));
+    }
+
+    #[test]
+    fn ignores_unusable_lists() {
+        // Each of these would revoke the leaf, if it could be relied on.
+        let lists: [&[u8]; 4] = [
+            include_bytes!("../fixtures/crl/intermediate-stale.crl"),
+            include_bytes!("../fixtures/crl/intermediate-future.crl"),
+            include_bytes!("../fixtures/crl/intermediate-forged.crl"),
+            include_bytes!("../fixtures/crl/intermediate-partial.crl"),
+        ];
+        for list in &lists {
+            assert_eq!(check(&[list], false), Ok(()));
+            assert_eq!(check(&[list], true), Err((0, ErrorKind::RevocationStatusUnknown, None)));
+        }
+    }
+
+    #[test]
+    fn describes_reasons() {
+        assert_eq!(RevocationReason::from_code(1), Some(RevocationReason::KeyCompromise));
+        assert_eq!(RevocationReason::from_code(7), None);
+        assert_eq!(RevocationReason::KeyCompromise.to_string(), "key compromise");
+    }
+}
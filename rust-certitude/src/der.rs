@@ -0,0 +1,304 @@
+// A minimal reader for the subset of DER used by X.509.
+//
+// This deliberately does not try to be a general ASN.1 library: it reads
+// tag-length-value triples out of a byte slice without copying, and leaves the
+// interpretation of the values to the caller.
+
+pub const BOOLEAN: u8 = 0x01;
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OID: u8 = 0x06;
+pub const ENUMERATED: u8 = 0x0a;
+pub const UTC_TIME: u8 = 0x17;
+pub const GENERALIZED_TIME: u8 = 0x18;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+// The string types that can appear in distinguished names.
+pub const UTF8_STRING: u8 = 0x0c;
+pub const PRINTABLE_STRING: u8 = 0x13;
+pub const T61_STRING: u8 = 0x14;
+pub const IA5_STRING: u8 = 0x16;
+pub const VISIBLE_STRING: u8 = 0x1a;
+pub const UNIVERSAL_STRING: u8 = 0x1c;
+pub const BMP_STRING: u8 = 0x1e;
+
+// Context-specific tags, as used by IMPLICIT and EXPLICIT tagging.
+pub const CONTEXT_SPECIFIC: u8 = 0x80;
+pub const CONSTRUCTED: u8 = 0x20;
+
+/// The input was not valid DER, or did not have the expected structure.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Error;
+
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().cloned()
+    }
+
+    // Reads the next element, returning its tag, its contents, and the complete
+    // encoding of the element (tag and length included).
+    pub fn read_any(&mut self) -> Result<(u8, &'a [u8], &'a [u8]), Error> {
+        let data = self.data;
+        if data.len() < 2 {
+            return Err(Error);
+        }
+
+        // We don't support high tag numbers: nothing in X.509 uses them.
+        let tag = data[0];
+        if tag & 0x1f == 0x1f {
+            return Err(Error);
+        }
+
+        let (length, header) = match data[1] {
+            n if n < 0x80 => (n as usize, 2),
+            0x81 => {
+                if data.len() < 3 || data[2] < 0x80 {
+                    return Err(Error);
+                }
+                (data[2] as usize, 3)
+            }
+            0x82 => {
+                if data.len() < 4 {
+                    return Err(Error);
+                }
+                let length = ((data[2] as usize) << 8) | data[3] as usize;
+                if length < 0x100 {
+                    return Err(Error);
+                }
+                (length, 4)
+            }
+            0x83 => {
+                if data.len() < 5 {
+                    return Err(Error);
+                }
+                let length = ((data[2] as usize) << 16) | ((data[3] as usize) << 8) | data[4] as usize;
+                if length < 0x10000 {
+                    return Err(Error);
+                }
+                (length, 5)
+            }
+            // Indefinite lengths are forbidden in DER, and nothing we parse is
+            // anywhere near 16MB long.
+            _ => return Err(Error),
+        };
+
+        if data.len() - header < length {
+            return Err(Error);
+        }
+        let (element, rest) = data.split_at(header + length);
+        self.data = rest;
+        Ok((tag, &element[header..], element))
+    }
+
+    // Reads the contents of the next element, which must have the given tag.
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        match self.read_any() {
+            Ok((t, contents, _)) if t == tag => Ok(contents),
+            _ => Err(Error),
+        }
+    }
+
+    // Reads the next element, which must have the given tag, returning the
+    // complete encoding rather than just the contents.
+    pub fn read_element(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        match self.read_any() {
+            Ok((t, _, element)) if t == tag => Ok(element),
+            _ => Err(Error),
+        }
+    }
+
+    // Reads the contents of the next element if, and only if, it has the given tag.
+    pub fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, Error> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        match self.read(BOOLEAN)? {
+            [0x00] => Ok(false),
+            [0xff] => Ok(true),
+            _ => Err(Error),
+        }
+    }
+
+    // Reads a non-negative INTEGER small enough to fit in a u32.
+    pub fn read_small_uint(&mut self) -> Result<u32, Error> {
+        let contents = positive_integer(self.read(INTEGER)?)?;
+        if contents.len() > 4 {
+            return Err(Error);
+        }
+        Ok(contents.iter().fold(0, |acc, &b| (acc << 8) | b as u32))
+    }
+
+    // Reads a BIT STRING, which must have no unused bits, returning the bits.
+    pub fn read_bit_string(&mut self) -> Result<&'a [u8], Error> {
+        match self.read(BIT_STRING)?.split_first() {
+            Some((&0, bits)) => Ok(bits),
+            _ => Err(Error),
+        }
+    }
+
+    pub fn finish(&self) -> Result<(), Error> {
+        if self.is_empty() { Ok(()) } else { Err(Error) }
+    }
+}
+
+
+// Parses a complete element with the given tag, returning its contents. Any
+// trailing data is an error.
+pub fn parse(data: &[u8], tag: u8) -> Result<&[u8], Error> {
+    let mut reader = Reader::new(data);
+    let contents = reader.read(tag)?;
+    reader.finish()?;
+    Ok(contents)
+}
+
+
+// Encodes an element with the given tag and contents.
+pub fn encode(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let length = contents.len();
+    let mut element = Vec::with_capacity(length + 5);
+    element.push(tag);
+    if length < 0x80 {
+        element.push(length as u8);
+    } else if length < 0x100 {
+        element.extend_from_slice(&[0x81, length as u8]);
+    } else if length < 0x10000 {
+        element.extend_from_slice(&[0x82, (length >> 8) as u8, length as u8]);
+    } else {
+        element.extend_from_slice(&[0x83, (length >> 16) as u8, (length >> 8) as u8, length as u8]);
+    }
+    element.extend_from_slice(contents);
+    element
+}
+
+
+// Encodes the contents of an OBJECT IDENTIFIER from its dotted-decimal form, such
+// as "1.3.6.1.5.5.7.3.1". Arcs may not have leading zeros, so each OID has only one
+// dotted form.
+pub fn encode_oid(dotted: &str) -> Result<Vec<u8>, Error> {
+    let mut arcs = Vec::new();
+    for arc in dotted.split('.') {
+        if arc.is_empty() || !arc.bytes().all(|b| b.is_ascii_digit()) || (arc.len() > 1 && arc.starts_with('0')) {
+            return Err(Error);
+        }
+        arcs.push(arc.parse::<u64>().map_err(|_| Error)?);
+    }
+    if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] >= 40) {
+        return Err(Error);
+    }
+
+    // The first two arcs share a subidentifier.
+    let first = arcs[0].checked_mul(40).and_then(|first| first.checked_add(arcs[1])).ok_or(Error)?;
+    let mut contents = Vec::new();
+    for &subidentifier in Some(&first).into_iter().chain(&arcs[2..]) {
+        let mut groups = vec![(subidentifier & 0x7f) as u8];
+        let mut rest = subidentifier >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        contents.extend(groups.iter().rev());
+    }
+    Ok(contents)
+}
+
+
+// Validates the contents of an INTEGER that must be non-negative, stripping the
+// leading zero byte used to keep the sign bit clear.
+pub fn positive_integer(contents: &[u8]) -> Result<&[u8], Error> {
+    match contents {
+        [] => Err(Error),
+        [0x00] => Ok(contents),
+        [0x00, next, ..] if next & 0x80 == 0 => Err(Error),
+        [0x00, rest @ ..] => Ok(rest),
+        [first, ..] if first & 0x80 != 0 => Err(Error),
+        _ => Ok(contents),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use der::{Reader, Error, SEQUENCE, INTEGER, encode, encode_oid, positive_integer};
+
+    #[test]
+    fn reads_nested_elements() {
+        let data = [0x30, 0x06, 0x02, 0x01, 0x05, 0x02, 0x01, 0x07];
+        let mut reader = Reader::new(&data);
+        let mut sequence = Reader::new(reader.read(SEQUENCE).unwrap());
+        assert!(reader.is_empty());
+        assert_eq!(sequence.read_small_uint(), Ok(5));
+        assert_eq!(sequence.read_small_uint(), Ok(7));
+        assert!(sequence.finish().is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_elements() {
+        let data = [0x30, 0x06, 0x02, 0x01, 0x05];
+        assert_eq!(Reader::new(&data).read(SEQUENCE), Err(Error));
+    }
+
+    #[test]
+    fn rejects_non_minimal_lengths() {
+        let data = [0x02, 0x81, 0x01, 0x05];
+        assert_eq!(Reader::new(&data).read(INTEGER), Err(Error));
+    }
+
+    #[test]
+    fn rejects_indefinite_lengths() {
+        let data = [0x30, 0x80, 0x00, 0x00];
+        assert_eq!(Reader::new(&data).read(SEQUENCE), Err(Error));
+    }
+
+    #[test]
+    fn encodes_what_it_reads() {
+        for &length in &[0, 0x7f, 0x80, 0xff, 0x100, 0x10000] {
+            let contents = vec![0x5a; length];
+            let element = encode(SEQUENCE, &contents);
+            assert_eq!(Reader::new(&element).read(SEQUENCE), Ok(&contents[..]));
+        }
+    }
+
+    #[test]
+    fn encodes_object_identifiers() {
+        assert_eq!(encode_oid("1.3.6.1.5.5.7.3.1"), Ok(vec![0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01]));
+        assert_eq!(encode_oid("2.5.29.37.0"), Ok(vec![0x55, 0x1d, 0x25, 0x00]));
+        assert_eq!(encode_oid("1.2.840.113549"), Ok(vec![0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d]));
+        assert_eq!(encode_oid("2.999.3"), Ok(vec![0x88, 0x37, 0x03]));
+        assert_eq!(encode_oid("1"), Err(Error));
+        assert_eq!(encode_oid("1.40"), Err(Error));
+        assert_eq!(encode_oid("3.1"), Err(Error));
+        assert_eq!(encode_oid("1.3.06"), Err(Error));
+        assert_eq!(encode_oid("1.3..6"), Err(Error));
+        assert_eq!(encode_oid("1.3.+6"), Err(Error));
+        assert_eq!(encode_oid("1.3.6."), Err(Error));
+    }
+
+    #[test]
+    fn strips_sign_byte_from_integers() {
+        assert_eq!(positive_integer(&[0x00, 0x80]), Ok(&[0x80][..]));
+        assert_eq!(positive_integer(&[0x7f]), Ok(&[0x7f][..]));
+        assert_eq!(positive_integer(&[0x00, 0x7f]), Err(Error));
+        assert_eq!(positive_integer(&[0x80]), Err(Error));
+    }
+}
@@ -0,0 +1,242 @@
+// Detailed reasons for failing to validate a certificate chain.
+use std::error;
+use std::fmt;
+
+use algorithms::Algorithm;
+use crl::RevocationReason;
+use ValidationResult;
+
+/// The ways in which validating a certificate chain can fail.
+///
+/// Most of these are reasons for not trusting the chain: they all correspond to
+/// `ValidationResult::NotTrusted`, apart from `Revoked`, which has a result of its
+/// own. The rest correspond to the other error values of `ValidationResult`.
+///
+/// Not every platform says why it rejected a chain. Where it doesn't, the failure
+/// is reported as `ErrorKind::NotTrusted`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ErrorKind {
+    /// A certificate has expired.
+    Expired,
+    /// A certificate is not valid yet.
+    NotYetValid,
+    /// The leaf certificate is not valid for the hostname.
+    HostnameMismatch,
+    /// The client certificate has none of the names the verifier requires.
+    ClientNameMismatch,
+    /// No issuer could be found for a certificate, so the chain could not be built
+    /// to a trust anchor.
+    UnknownIssuer,
+    /// The signature on a certificate is not valid.
+    BadSignature,
+    /// The signature on a certificate, or the key of its issuer, uses an algorithm
+    /// that isn't supported, so the signature could not be checked.
+    UnsupportedAlgorithm,
+    /// A certificate has been revoked. `ValidationError::revocation_reason` says
+    /// why, if its issuer did.
+    Revoked,
+    /// A certificate was used to issue another, but is not a certificate authority.
+    NotCertificateAuthority,
+    /// A certificate authority's path length constraint was exceeded.
+    PathLengthExceeded,
+    /// The leaf certificate's extended key usage doesn't allow the required purpose,
+    /// such as identifying a TLS server.
+    InvalidUsage,
+    /// The leaf certificate's key usage doesn't allow its key to be used as the
+    /// purpose needs, such as for a TLS key exchange.
+    InvalidKeyUsage,
+    /// A certificate authority's key usage doesn't allow it to sign certificates.
+    KeyCertSignNotAllowed,
+    /// A certificate has a critical extension that could not be processed.
+    UnhandledCriticalExtension,
+    /// A certificate has a name that the name constraints of a certificate authority
+    /// above it don't allow.
+    NameConstraintViolation,
+    /// A certificate's key is weaker than the policy allows.
+    WeakKey,
+    /// A certificate's signature or key uses an algorithm that the verifier's
+    /// `AlgorithmPolicy` doesn't allow. `ValidationError::algorithm` says which.
+    DisallowedAlgorithm,
+    /// Revocation checking was required, but whether a certificate has been revoked
+    /// could not be determined.
+    RevocationStatusUnknown,
+    /// The chain is not trusted, for some other or unknown reason.
+    NotTrusted,
+    /// See `ValidationResult::MalformedCertificateInChain`.
+    MalformedCertificateInChain,
+    /// See `ValidationResult::UnableToBuildTrustStore`.
+    UnableToBuildTrustStore,
+    /// See `ValidationResult::ErrorDuringValidation`.
+    ErrorDuringValidation,
+    /// See `ValidationResult::MissingFunctionality`.
+    MissingFunctionality,
+    /// See `ValidationResult::UserAuthenticationRequired`.
+    UserAuthenticationRequired,
+    /// See `ValidationResult::MalformedHostname`.
+    MalformedHostname,
+}
+
+impl ErrorKind {
+    fn description(&self) -> &'static str {
+        match *self {
+            ErrorKind::Expired => "certificate has expired",
+            ErrorKind::NotYetValid => "certificate is not yet valid",
+            ErrorKind::HostnameMismatch => "certificate is not valid for the hostname",
+            ErrorKind::ClientNameMismatch => "certificate does not have a required client name",
+            ErrorKind::UnknownIssuer => "certificate was issued by an unknown authority",
+            ErrorKind::BadSignature => "certificate signature is invalid",
+            ErrorKind::UnsupportedAlgorithm => "certificate signature algorithm is not supported",
+            ErrorKind::Revoked => "certificate has been revoked",
+            ErrorKind::NotCertificateAuthority => "issuer is not a certificate authority",
+            ErrorKind::PathLengthExceeded => "certificate authority path length exceeded",
+            ErrorKind::InvalidUsage => "certificate may not be used for this purpose",
+            ErrorKind::InvalidKeyUsage => "certificate key may not be used for this purpose",
+            ErrorKind::KeyCertSignNotAllowed => "issuer key may not sign certificates",
+            ErrorKind::UnhandledCriticalExtension => "certificate has an unhandled critical extension",
+            ErrorKind::NameConstraintViolation => "certificate name violates a name constraint",
+            ErrorKind::WeakKey => "certificate key is too weak",
+            ErrorKind::DisallowedAlgorithm => "certificate uses an algorithm the policy doesn't allow",
+            ErrorKind::RevocationStatusUnknown => "certificate revocation status is unknown",
+            ErrorKind::NotTrusted => "certificate chain is not trusted",
+            ErrorKind::MalformedCertificateInChain => "malformed certificate in chain",
+            ErrorKind::UnableToBuildTrustStore => "unable to build trust store",
+            ErrorKind::ErrorDuringValidation => "error during validation",
+            ErrorKind::MissingFunctionality => "validation functionality is missing",
+            ErrorKind::UserAuthenticationRequired => "user authentication required",
+            ErrorKind::MalformedHostname => "malformed hostname",
+        }
+    }
+}
+
+
+/// An error from validating a certificate chain, saying why it failed and, where
+/// possible, which certificate was responsible.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ValidationError {
+    kind: ErrorKind,
+    certificate: Option<usize>,
+    algorithm: Option<Algorithm>,
+    reason: Option<RevocationReason>,
+}
+
+impl ValidationError {
+    pub(crate) fn new(kind: ErrorKind, certificate: Option<usize>) -> ValidationError {
+        ValidationError { kind, certificate, algorithm: None, reason: None }
+    }
+
+    pub(crate) fn with_algorithm(self, algorithm: Algorithm) -> ValidationError {
+        ValidationError { algorithm: Some(algorithm), ..self }
+    }
+
+    pub(crate) fn with_reason(self, reason: Option<RevocationReason>) -> ValidationError {
+        ValidationError { reason, ..self }
+    }
+
+    pub(crate) fn at(kind: ErrorKind, certificate: usize) -> ValidationError {
+        ValidationError::new(kind, Some(certificate))
+    }
+
+    /// What went wrong.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The index of the offending certificate in the chain that was passed in, with
+    /// the leaf at index 0.
+    ///
+    /// This is `None` if the problem isn't with any one certificate, if it's with a
+    /// certificate that wasn't in the chain (such as a trust anchor), or if the
+    /// platform didn't say.
+    pub fn certificate(&self) -> Option<usize> {
+        self.certificate
+    }
+
+    /// The algorithm or key that broke the verifier's `AlgorithmPolicy`, for
+    /// `ErrorKind::DisallowedAlgorithm` and `ErrorKind::WeakKey`.
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        self.algorithm
+    }
+
+    /// Why the certificate was revoked, for `ErrorKind::Revoked`. This is `None` if
+    /// the issuer gave no reason, or the platform didn't say.
+    pub fn revocation_reason(&self) -> Option<RevocationReason> {
+        self.reason
+    }
+}
+
+impl From<ErrorKind> for ValidationError {
+    fn from(kind: ErrorKind) -> ValidationError {
+        ValidationError::new(kind, None)
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.kind.description())?;
+        if let Some(algorithm) = self.algorithm {
+            write!(f, ": {}", algorithm)?;
+        }
+        if let Some(reason) = self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        match self.certificate {
+            Some(index) => write!(f, " (certificate {})", index),
+            None => Ok(()),
+        }
+    }
+}
+
+impl error::Error for ValidationError {}
+
+
+impl<T> From<Result<T, ValidationError>> for ValidationResult {
+    fn from(result: Result<T, ValidationError>) -> ValidationResult {
+        let error = match result {
+            Ok(_) => return ValidationResult::Trusted,
+            Err(error) => error,
+        };
+        match error.kind {
+            ErrorKind::MalformedCertificateInChain => ValidationResult::MalformedCertificateInChain,
+            ErrorKind::UnableToBuildTrustStore => ValidationResult::UnableToBuildTrustStore,
+            ErrorKind::ErrorDuringValidation => ValidationResult::ErrorDuringValidation,
+            ErrorKind::MissingFunctionality => ValidationResult::MissingFunctionality,
+            ErrorKind::UserAuthenticationRequired => ValidationResult::UserAuthenticationRequired,
+            ErrorKind::MalformedHostname => ValidationResult::MalformedHostname,
+            ErrorKind::Revoked => ValidationResult::Revoked,
+            _ => ValidationResult::NotTrusted,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use algorithms::{Algorithm, HashAlgorithm};
+    use crl::RevocationReason;
+    use error::{ErrorKind, ValidationError};
+    use ValidationResult;
+
+    #[test]
+    fn converts_to_validation_result() {
+        let trusted: Result<(), ValidationError> = Ok(());
+        assert_eq!(ValidationResult::from(trusted), ValidationResult::Trusted);
+        let expired: Result<(), ValidationError> = Err(ValidationError::at(ErrorKind::Expired, 1));
+        assert_eq!(ValidationResult::from(expired), ValidationResult::NotTrusted);
+        let malformed: Result<(), ValidationError> = Err(ErrorKind::MalformedHostname.into());
+        assert_eq!(ValidationResult::from(malformed), ValidationResult::MalformedHostname);
+        let revoked: Result<(), ValidationError> = Err(ValidationError::at(ErrorKind::Revoked, 0));
+        assert_eq!(ValidationResult::from(revoked), ValidationResult::Revoked);
+    }
+
+    #[test]
+    fn describes_the_offending_certificate() {
+        let error = ValidationError::at(ErrorKind::Expired, 2);
+        assert_eq!(error.to_string(), "certificate has expired (certificate 2)");
+        let error = ValidationError::from(ErrorKind::UnknownIssuer);
+        assert_eq!(error.to_string(), "certificate was issued by an unknown authority");
+        let error = ValidationError::at(ErrorKind::DisallowedAlgorithm, 1).with_algorithm(Algorithm::Hash(HashAlgorithm::Sha1));
+        assert_eq!(error.to_string(), "certificate uses an algorithm the policy doesn't allow: SHA-1 (certificate 1)");
+        let error = ValidationError::at(ErrorKind::Revoked, 0).with_reason(Some(RevocationReason::KeyCompromise));
+        assert_eq!(error.to_string(), "certificate has been revoked: key compromise (certificate 0)");
+    }
+}
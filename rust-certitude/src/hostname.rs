@@ -0,0 +1,391 @@
+// Matching certificates against hostnames, following RFC 6125.
+//
+// Each platform has its own idea of which names a certificate is valid for, so
+// rather than leave the check to them we make it ourselves, the same way everywhere.
+
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str;
+
+use error::{ErrorKind, ValidationError};
+use idna;
+use psl;
+use x509::{Certificate, GeneralName};
+
+
+/// Check that a DER-encoded certificate is valid for the hostname.
+///
+/// This is the check that `validate_cert_chain` makes of the leaf certificate. It
+/// follows RFC 6125: the hostname must match one of the DNS names in the
+/// certificate's subject alternative name extension, ignoring ASCII case and any
+/// trailing dot. A wildcard is only allowed as the whole of a name's left-most label,
+/// where it matches exactly one label of the hostname, and never when the rest of
+/// the name is a public suffix on the Public Suffix List, such as `com`, `co.uk` or
+/// `github.io`.
+///
+/// Internationalised hostnames may be given in Unicode or in their ASCII form:
+/// Unicode labels are converted to Punycode following UTS #46 before matching, so
+/// `bücher.example` and `xn--bcher-kva.example` are the same. A hostname that isn't a
+/// valid internationalised domain name is `ErrorKind::MalformedHostname`.
+///
+/// Once in its ASCII form, a DNS name must follow RFC 1123: at most 253 characters,
+/// not counting one trailing dot, made of non-empty labels of at most 63 letters,
+/// digits and hyphens, none starting or ending with a hyphen. Anything else is
+/// `ErrorKind::MalformedHostname`, as is a name ending in an all-numeric label,
+/// which can only be a mistyped IPv4 address.
+///
+/// If the hostname is an IPv4 or IPv6 address it must instead match one of the
+/// certificate's IP addresses, and is never matched against a DNS name. IPv6
+/// addresses may be given in brackets and with a zone ID, as in a URL: both are
+/// ignored, as is the IPv6 form of an IPv4 address. Brackets around anything else,
+/// or a zone ID on anything else, are `ErrorKind::MalformedHostname`.
+///
+/// The certificate's common name is not used. To fall back to it for certificates
+/// without any DNS names, use a `Verifier`.
+///
+/// # Examples
+///
+/// ```
+/// match verify_hostname(leaf, "example.com") {
+///     Ok(()) => println!("the certificate is for example.com"),
+///     Err(e) => println!("the certificate is not for example.com: {}", e),
+/// }
+/// ```
+pub fn verify_hostname(encoded_cert: &[u8], hostname: &str) -> Result<(), ValidationError> {
+    let cert = match Certificate::parse(encoded_cert) {
+        Ok(cert) => cert,
+        Err(_) => return Err(ValidationError::at(ErrorKind::MalformedCertificateInChain, 0)),
+    };
+    check_hostname(&cert, &parse_reference(hostname)?, false)
+}
+
+
+// Checks that the certificate is valid for the parsed hostname. If the certificate
+// has no DNS names and `common_name_fallback` is set, its most specific common name
+// is used instead, as RFC 6125 permits. IP addresses never fall back.
+pub(crate) fn check_hostname(cert: &Certificate, reference: &Reference, common_name_fallback: bool) -> Result<(), ValidationError> {
+    let names = cert.subject_alt_names().unwrap_or_default();
+    let matched = match *reference {
+        Reference::IpAddress(reference) => {
+            names.iter().any(|name| match *name {
+                GeneralName::IpAddress(address) => parse_ip_address(address) == Some(reference),
+                _ => false,
+            })
+        }
+        Reference::DnsName(ref reference) => {
+            let mut presented = names.iter()
+                                     .filter_map(|name| match *name {
+                                         GeneralName::DnsName(name) => Some(name),
+                                         _ => None,
+                                     })
+                                     .collect::<Vec<_>>();
+            if presented.is_empty() && common_name_fallback {
+                presented.extend(cert.common_names().last());
+            }
+            presented.iter().any(|name| matches(name, reference))
+        }
+    };
+
+    if matched {
+        Ok(())
+    } else {
+        Err(ValidationError::at(ErrorKind::HostnameMismatch, 0))
+    }
+}
+
+
+// What the caller asked to connect to, normalised for comparison with the names in
+// a certificate.
+#[derive(PartialEq, Eq, Debug)]
+pub(crate) enum Reference {
+    DnsName(String),
+    IpAddress(IpAddr),
+}
+
+// Works out whether the hostname is a DNS name or an IP address, failing if it is
+// neither. DNS names are converted to their ASCII form, lower-cased, and lose any
+// trailing dot.
+pub(crate) fn parse_reference(hostname: &str) -> Result<Reference, ValidationError> {
+    let malformed = || ValidationError::new(ErrorKind::MalformedHostname, None);
+
+    // Brackets are only used around IPv6 addresses.
+    let (address, bracketed) = match hostname.strip_prefix('[') {
+        Some(rest) => (rest.strip_suffix(']').ok_or_else(malformed)?, true),
+        None => (hostname, false),
+    };
+
+    // The zone ID only says which interface to use, so it is no part of the address
+    // a certificate could name.
+    let (address, zone) = match address.split_once('%') {
+        Some((address, zone)) => (address, Some(zone)),
+        None => (address, None),
+    };
+    if bracketed || zone.is_some() {
+        return match address.parse::<Ipv6Addr>() {
+            Ok(address) if zone != Some("") => Ok(Reference::IpAddress(normalise(IpAddr::V6(address)))),
+            _ => Err(malformed()),
+        };
+    }
+
+    if let Ok(address) = address.parse::<IpAddr>() {
+        return Ok(Reference::IpAddress(normalise(address)));
+    }
+
+    // Certificates hold internationalised names in their ASCII form.
+    let hostname = idna::domain_to_ascii(hostname).map_err(|_| malformed())?;
+    let hostname = hostname.strip_suffix('.').unwrap_or(&hostname);
+    if !is_valid_dns_name(hostname) {
+        return Err(malformed());
+    }
+    Ok(Reference::DnsName(hostname.to_owned()))
+}
+
+// Checks the syntax of a lower-case ASCII DNS name without its trailing dot,
+// following RFC 1123: at most 253 characters, in labels of 1 to 63 letters, digits
+// and hyphens that neither start nor end with a hyphen. The last label can't be all
+// digits, because no top-level domain is, so such a name is a mistyped IPv4 address.
+fn is_valid_dns_name(hostname: &str) -> bool {
+    if hostname.len() > 253 {
+        return false;
+    }
+    let valid_labels = hostname.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63 &&
+        !label.starts_with('-') && !label.ends_with('-') &&
+        label.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+    });
+    let numeric_tld = hostname.rsplit('.').next().is_some_and(|tld| tld.bytes().all(|b| b.is_ascii_digit()));
+    valid_labels && !numeric_tld
+}
+
+// Parses an IP address from a subject alternative name, which holds the address in
+// network byte order.
+fn parse_ip_address(address: &[u8]) -> Option<IpAddr> {
+    if let Ok(octets) = <[u8; 4]>::try_from(address) {
+        Some(IpAddr::V4(Ipv4Addr::from(octets)))
+    } else if let Ok(octets) = <[u8; 16]>::try_from(address) {
+        Some(normalise(IpAddr::V6(Ipv6Addr::from(octets))))
+    } else {
+        None
+    }
+}
+
+// An IPv4 address written as IPv6 is still the IPv4 address.
+fn normalise(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(address, IpAddr::V4),
+        IpAddr::V4(_) => address,
+    }
+}
+
+
+// Matches a DNS name from a certificate against the normalised hostname.
+fn matches(presented: &[u8], reference: &str) -> bool {
+    let presented = match str::from_utf8(presented) {
+        Ok(presented) if presented.is_ascii() => presented.to_ascii_lowercase(),
+        _ => return false,
+    };
+    let presented = presented.strip_suffix('.').unwrap_or(&presented);
+
+    match presented.strip_prefix("*.") {
+        Some(suffix) => {
+            if suffix.contains('*') || !may_be_wildcarded(suffix) {
+                return false;
+            }
+            match reference.split_once('.') {
+                Some((label, rest)) => !label.is_empty() && rest == suffix,
+                None => false,
+            }
+        }
+        None => !presented.is_empty() && !presented.contains('*') && presented == reference,
+    }
+}
+
+
+// Whether a wildcard may cover the names directly under this suffix. It may not if
+// the suffix is on the Public Suffix List, private suffixes such as `github.io`
+// included, because then the wildcard would cover names registered by anyone. The
+// list's default rule makes every top-level domain a public suffix.
+fn may_be_wildcarded(suffix: &str) -> bool {
+    suffix.contains('.') &&
+    !suffix.split('.').any(|label| label.is_empty()) &&
+    psl::suffix(suffix.as_bytes()).is_some_and(|public| public.as_bytes() != suffix.as_bytes())
+}
+
+
+#[cfg(test)]
+mod test {
+    use error::{ErrorKind, ValidationError};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use hostname::{check_hostname, matches, parse_reference, verify_hostname, Reference};
+    use test::test_pki_chain;
+    use x509::Certificate;
+
+    fn check(cert: &Certificate, hostname: &str, common_name_fallback: bool) -> Result<(), ValidationError> {
+        check_hostname(cert, &parse_reference(hostname).unwrap(), common_name_fallback)
+    }
+
+    #[test]
+    fn matches_dns_names() {
+        let leaf = test_pki_chain()[0];
+        assert_eq!(verify_hostname(leaf, "certitude.test"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "Certitude.TEST."), Ok(()));
+        assert_eq!(verify_hostname(leaf, "www.certitude.test"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "lukasa.co.uk"), Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+    }
+
+    #[test]
+    fn rejects_malformed_hostnames() {
+        let malformed = Err(ValidationError::new(ErrorKind::MalformedHostname, None));
+        let long_label = format!("{}.test", "a".repeat(64));
+        let long_name = ["a".repeat(63), "a".repeat(63), "a".repeat(63), "a".repeat(62)].join(".");
+        for &hostname in &["", ".", "certitude..test", ".certitude.test", "certitude.test..",
+                           "-certitude.test", "certitude-.test", "certi_tude.test", "certi tude.test",
+                           "*.certitude.test", "certitude.test/", "10.0.0.256", "1.2.3", &long_label, &long_name] {
+            assert_eq!(parse_reference(hostname), malformed, "{:?}", hostname);
+        }
+    }
+
+    #[test]
+    fn accepts_well_formed_hostnames() {
+        let longest_label = format!("{}.test", "a".repeat(63));
+        let longest_name = ["a".repeat(63), "a".repeat(63), "a".repeat(63), "a".repeat(61)].join(".");
+        for &hostname in &["localhost", "certitude.test.", "x-1.certitude.test", "3com.com", "a.b.c.d.e",
+                           "1.2.3.example", &longest_label, &longest_name] {
+            assert!(parse_reference(hostname).is_ok(), "{:?}", hostname);
+        }
+    }
+
+    #[test]
+    fn fails_on_malformed_certificate() {
+        let leaf = test_pki_chain()[0];
+        let error = verify_hostname(&leaf[..50], "certitude.test");
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::MalformedCertificateInChain, 0)));
+    }
+
+    #[test]
+    fn wildcard_covers_exactly_one_label() {
+        assert!(matches(b"*.example.com", "www.example.com"));
+        assert!(matches(b"*.EXAMPLE.com.", "www.example.com"));
+        assert!(!matches(b"*.example.com", "example.com"));
+        assert!(!matches(b"*.example.com", "a.b.example.com"));
+        assert!(!matches(b"*.example.com", ".example.com"));
+    }
+
+    #[test]
+    fn wildcard_must_be_whole_left_most_label() {
+        assert!(!matches(b"w*.example.com", "www.example.com"));
+        assert!(!matches(b"*w.example.com", "www.example.com"));
+        assert!(!matches(b"www.*.com", "www.example.com"));
+        assert!(!matches(b"*.*.com", "www.example.com"));
+        assert!(!matches(b"*", "localhost"));
+    }
+
+    #[test]
+    fn wildcard_never_covers_public_suffix() {
+        assert!(!matches(b"*.com", "example.com"));
+        assert!(!matches(b"*.co.uk", "example.co.uk"));
+        assert!(!matches(b"*.github.io", "example.github.io"));
+        assert!(!matches(b"*.blogspot.com", "example.blogspot.com"));
+        assert!(!matches(b"*.s3.amazonaws.com", "bucket.s3.amazonaws.com"));
+        assert!(!matches(b"*.example.ck", "www.example.ck"));
+        assert!(matches(b"*.certitude.test", "www.certitude.test"));
+        assert!(matches(b"*.example.co.uk", "www.example.co.uk"));
+    }
+
+    #[test]
+    fn ignores_non_ascii_names() {
+        assert!(!matches("bücher.example".as_bytes(), "bücher.example"));
+        assert!(!matches(b"", ""));
+    }
+
+    #[test]
+    fn common_name_fallback_is_off_by_default() {
+        let leaf = include_bytes!("../fixtures/hostname/common-name-only.crt");
+        let error = verify_hostname(leaf, "legacy.certitude.test");
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+
+        let cert = Certificate::parse(leaf).unwrap();
+        assert_eq!(check(&cert, "legacy.certitude.test", true), Ok(()));
+        assert_eq!(check(&cert, "other.certitude.test", true), Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+    }
+
+    #[test]
+    fn common_name_is_ignored_when_there_are_dns_names() {
+        let leaf = include_bytes!("../fixtures/hostname/common-name-and-dns-name.crt");
+        let cert = Certificate::parse(leaf).unwrap();
+        assert_eq!(check(&cert, "modern.certitude.test", true), Ok(()));
+        assert_eq!(check(&cert, "legacy.certitude.test", true), Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+    }
+
+    #[test]
+    fn matches_internationalised_names() {
+        // The certificate is for xn--bcher-kva.certitude.test and *.xn--mnchen-3ya.certitude.test.
+        let leaf = include_bytes!("../fixtures/hostname/internationalised.crt");
+        assert_eq!(verify_hostname(leaf, "bücher.certitude.test"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "BÜCHER.certitude.test"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "xn--bcher-kva.certitude.test"), Ok(()));
+        // Hostnames are mapped and normalised to NFC first, as the resolver does.
+        assert_eq!(verify_hostname(leaf, "bu\u{0308}cher.certitude.test"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "ｂücher。certitude.test"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "straße.münchen.certitude.test"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "buecher.certitude.test"), Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_internationalised_names() {
+        let leaf = include_bytes!("../fixtures/hostname/internationalised.crt");
+        let malformed = Err(ValidationError::new(ErrorKind::MalformedHostname, None));
+        assert_eq!(verify_hostname(leaf, "xn--bcher-kv.certitude.test"), malformed);
+        assert_eq!(verify_hostname(leaf, "bü_cher.certitude.test"), malformed);
+        // A joiner out of context, and right-to-left mixed with left-to-right.
+        assert_eq!(verify_hostname(leaf, "bü\u{200D}cher.certitude.test"), malformed);
+        assert_eq!(verify_hostname(leaf, "a\u{05D0}.certitude.test"), malformed);
+    }
+
+    #[test]
+    fn recognises_ip_addresses() {
+        let v4 = |a, b, c, d| Ok(Reference::IpAddress(IpAddr::V4(Ipv4Addr::new(a, b, c, d))));
+        let v6 = |address: &str| Ok(Reference::IpAddress(IpAddr::V6(address.parse::<Ipv6Addr>().unwrap())));
+        assert_eq!(parse_reference("10.0.0.1"), v4(10, 0, 0, 1));
+        assert_eq!(parse_reference("fd00::1"), v6("fd00::1"));
+        assert_eq!(parse_reference("[FD00:0:0:0:0:0:0:1]"), v6("fd00::1"));
+        assert_eq!(parse_reference("fe80::1%eth0"), v6("fe80::1"));
+        assert_eq!(parse_reference("[fe80::1%25eth0]"), v6("fe80::1"));
+        assert_eq!(parse_reference("::ffff:10.0.0.1"), v4(10, 0, 0, 1));
+        assert_eq!(parse_reference("10.0.0.1.example"), Ok(Reference::DnsName("10.0.0.1.example".to_owned())));
+    }
+
+    #[test]
+    fn rejects_malformed_ip_addresses() {
+        let malformed = Err(ValidationError::new(ErrorKind::MalformedHostname, None));
+        assert_eq!(parse_reference("[10.0.0.1]"), malformed);
+        assert_eq!(parse_reference("[example.com]"), malformed);
+        assert_eq!(parse_reference("[fd00::1"), malformed);
+        assert_eq!(parse_reference("10.0.0.1%eth0"), malformed);
+        assert_eq!(parse_reference("fe80::1%"), malformed);
+    }
+
+    #[test]
+    fn matches_ip_addresses() {
+        let leaf = include_bytes!("../fixtures/hostname/ip-addresses.crt");
+        assert_eq!(verify_hostname(leaf, "10.0.0.1"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "::ffff:10.0.0.1"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "fd00::1"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "[fd00:0::1%en0]"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "internal.certitude.test"), Ok(()));
+        assert_eq!(verify_hostname(leaf, "fd00::2"), Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+    }
+
+    #[test]
+    fn never_matches_ip_addresses_against_dns_names() {
+        // The certificate has 10.0.0.2 as a DNS name, and 10.0.0.3 as its common name.
+        let leaf = include_bytes!("../fixtures/hostname/ip-addresses.crt");
+        let cert = Certificate::parse(leaf).unwrap();
+        assert_eq!(check(&cert, "10.0.0.2", true), Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+        assert_eq!(check(&cert, "10.0.0.3", true), Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+
+        let leaf = test_pki_chain()[0];
+        assert_eq!(verify_hostname(leaf, "10.0.0.1"), Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+    }
+}
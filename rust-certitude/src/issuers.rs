@@ -0,0 +1,149 @@
+// Checking that each certificate authority in a chain may issue the certificates
+// below it, following RFC 5280: it must be a CA by its basic constraints, its path
+// length constraint must allow the certificates below it, and its key usage, if it
+// has one, must allow signing certificates.
+//
+// Platforms check these, but not all of them say which rule was broken, and some
+// fold a missing key usage into not being a CA at all. We check the chain ourselves
+// so that each is reported as its own error everywhere.
+
+use error::ErrorKind;
+use x509::{self, Certificate};
+
+
+// Checks that the issuer may issue certificates, given the number of non-self-issued
+// intermediates below it.
+pub(crate) fn check_issuer(issuer: &Certificate, below: usize) -> Result<(), ErrorKind> {
+    match issuer.basic_constraints {
+        Some(ref constraints) if constraints.ca => {
+            if let Some(path_len) = constraints.path_len {
+                if below > path_len as usize {
+                    return Err(ErrorKind::PathLengthExceeded);
+                }
+            }
+        }
+        _ => return Err(ErrorKind::NotCertificateAuthority),
+    }
+    if let Some(usage) = issuer.key_usage {
+        if usage & x509::KEY_USAGE_KEY_CERT_SIGN == 0 {
+            return Err(ErrorKind::KeyCertSignNotAllowed);
+        }
+    }
+    Ok(())
+}
+
+
+// Checks each issuer in a path, leaf first. The last certificate in a verified chain
+// is the trust anchor, which RFC 5280 trusts for what it is, so it's only checked
+// if `check_last` is set. On failure, returns the position in the path of the first
+// issuer that can't issue what's below it.
+pub(crate) fn check_path(path: &[&Certificate], check_last: bool) -> Result<(), (usize, ErrorKind)> {
+    let end = if check_last { path.len() } else { path.len().saturating_sub(1) };
+    let mut below = 0;
+    for (index, issuer) in path.iter().enumerate().take(end).skip(1) {
+        check_issuer(issuer, below).map_err(|kind| (index, kind))?;
+        if !issuer.is_self_issued() {
+            below += 1;
+        }
+    }
+    Ok(())
+}
+
+
+// The path that the certificates we were given make, by following issuer names from
+// the leaf, as positions in `certs`. Signatures aren't checked, so this is only good
+// for finding out why a platform rejected the chain. The path stops at a self-issued
+// certificate, or when no certificate we have issued the last one.
+pub(crate) fn given_path(certs: &[Certificate]) -> Vec<usize> {
+    let mut path = Vec::new();
+    if certs.is_empty() {
+        return path;
+    }
+    path.push(0);
+    let mut current = &certs[0];
+    while !current.is_self_issued() {
+        let next = (1..certs.len()).find(|&i| !path.contains(&i) && x509::same_name(certs[i].subject, current.issuer));
+        match next {
+            Some(next) => {
+                path.push(next);
+                current = &certs[next];
+            }
+            None => break,
+        }
+    }
+    path
+}
+
+
+// The positions in `certs` of the certificates that aren't self-issued and weren't
+// issued by any other certificate we have, going by names. Their issuers are missing,
+// unless they are trust anchors.
+pub(crate) fn missing_issuers(certs: &[Certificate]) -> Vec<usize> {
+    (0..certs.len()).filter(|&i| {
+        !certs[i].is_self_issued() && !certs.iter().any(|issuer| x509::same_name(issuer.subject, certs[i].issuer))
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use error::ErrorKind;
+    use issuers::{check_path, given_path, missing_issuers};
+    use test::{test_pki_chain, test_pki_root};
+    use x509::Certificate;
+
+    fn parse(certs: &[&'static [u8]]) -> Vec<Certificate<'static>> {
+        certs.iter().map(|cert| Certificate::parse(cert).unwrap()).collect()
+    }
+
+    fn check(certs: &[&'static [u8]], check_last: bool) -> Result<(), (usize, ErrorKind)> {
+        let certs = parse(certs);
+        check_path(&certs.iter().collect::<Vec<_>>(), check_last)
+    }
+
+    #[test]
+    fn accepts_good_issuers() {
+        let chain = test_pki_chain();
+        assert_eq!(check(&[chain[0], chain[1], test_pki_root()], false), Ok(()));
+        assert_eq!(check(&[chain[0], chain[1], test_pki_root()], true), Ok(()));
+    }
+
+    #[test]
+    fn reports_each_violation() {
+        let leaf = include_bytes!("../fixtures/issuers/leaf.crt");
+        let cases: [(&[u8], ErrorKind); 3] = [
+            (include_bytes!("../fixtures/issuers/not-ca.crt"), ErrorKind::NotCertificateAuthority),
+            (include_bytes!("../fixtures/issuers/no-basic-constraints.crt"), ErrorKind::NotCertificateAuthority),
+            (include_bytes!("../fixtures/issuers/no-key-cert-sign.crt"), ErrorKind::KeyCertSignNotAllowed),
+        ];
+        for &(intermediate, kind) in &cases {
+            assert_eq!(check(&[leaf, intermediate, test_pki_root()], false), Err((1, kind)));
+        }
+    }
+
+    #[test]
+    fn enforces_path_length() {
+        // The test intermediate has a path length of zero, so can't issue another.
+        let leaf = include_bytes!("../fixtures/issuers/leaf-of-sub-ca.crt");
+        let sub_ca = include_bytes!("../fixtures/issuers/sub-ca.crt");
+        let path = [&leaf[..], sub_ca, test_pki_chain()[1], test_pki_root()];
+        assert_eq!(check(&path, false), Err((2, ErrorKind::PathLengthExceeded)));
+    }
+
+    #[test]
+    fn follows_issuer_names() {
+        let chain = test_pki_chain();
+        let certs = parse(&[chain[0], test_pki_root(), chain[1]]);
+        assert_eq!(given_path(&certs), vec![0, 2, 1]);
+        assert_eq!(given_path(&certs[..2]), vec![0]);
+        assert_eq!(given_path(&[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn finds_missing_issuers() {
+        let chain = test_pki_chain();
+        assert_eq!(missing_issuers(&parse(&[chain[0], chain[1], test_pki_root()])), Vec::<usize>::new());
+        assert_eq!(missing_issuers(&parse(&[chain[0], chain[1]])), vec![1]);
+        assert_eq!(missing_issuers(&parse(&[chain[0], test_pki_root()])), vec![0]);
+    }
+}
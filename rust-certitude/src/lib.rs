@@ -7,13 +7,27 @@
 //! for libraries that want to use OpenSSL build TLS connections on Windows and OS X, but
 //! that want to exhibit "platform-native" behaviour on those systems.
 //!
-//! Currently Certitude *only* supports Windows and OS X: it explicitly does not support
-//! Linux or any other Unix, where it is expected that the verification logic provided by
-//! OpenSSL (or the appropriate TLS library) used on those systems will be used instead.
-//! As that library is likely the one responsible for actually handling the TLS logic, it
-//! is likely pretty easy to use the built-in validation logic.
+//! Currently Certitude supports Windows, OS X and Linux. On Linux there is no single
+//! platform verifier, so Certitude uses the system's OpenSSL to build a path from the
+//! supplied chain to the trust anchors in the distribution's CA bundle (see `TrustStore`).
+//! Other Unixes are not supported.
+//!
+//! For those who would rather have identical behaviour everywhere than platform-native
+//! behaviour, the `portable` module contains a validator written entirely in Rust. Enabling
+//! the `portable` feature makes `validate_cert_chain` use it on every platform.
 
+extern crate ed25519_dalek;
+extern crate ed448_goldilocks;
+extern crate idna;
 extern crate libc;
+extern crate p256;
+extern crate p384;
+extern crate p521;
+extern crate psl;
+extern crate rsa;
+extern crate sha1;
+extern crate sha2;
+extern crate sha3;
 
 #[cfg(target_os = "macos")]
 extern crate core_foundation;
@@ -23,11 +37,11 @@ extern crate security_framework;
 extern crate crypt32;
 #[cfg(windows)]
 extern crate winapi;
+#[cfg(target_os = "linux")]
+extern crate openssl;
 
 
 
-// TODO: Widen "NotTrusted".
-
 /// Possible results from attempting to validate a certificate chain.
 ///
 /// When attempting to validate a certificate chain, in addition to the two "successful"
@@ -35,11 +49,18 @@ extern crate winapi;
 /// numerous possible error conditions. This enum allows for expressing those error
 /// conditions.
 ///
+/// `ValidationResult::MalformedHostname` means the hostname is neither a valid DNS name
+/// nor an IP address, whatever the chain: see `verify_hostname` for what is accepted. It
+/// is checked before any certificate is looked at.
+///
 /// Note that due to the vagaries of the system libraries, it is possible that a
-/// misleading error may be generated: for example, the hostname may be malformed
-/// but in a manner that does not immediate generate a `ValidationResult::MalformedHostname`
-/// result. That's unfortunate, but there is relatively little that can be done about that
-/// in the absence of clearer system APIs.
+/// misleading error may be generated for a chain. That's unfortunate, but there is
+/// relatively little that can be done about that in the absence of clearer system APIs.
+///
+/// `ValidationResult::Revoked` means a certificate in the chain has been revoked.
+/// `ValidationResult::NotTrusted` covers every other reason for not trusting a chain.
+/// To find out which, or why a certificate was revoked, use `verify_cert_chain`,
+/// which reports a `ValidationError`.
 #[derive(PartialEq, Debug)]
 pub enum ValidationResult {
     Trusted = 1,
@@ -50,20 +71,64 @@ pub enum ValidationResult {
     MissingFunctionality,
     UserAuthenticationRequired,
     MalformedHostname,
+    Revoked,
 }
 
-pub use self::platform::validate_cert_chain;
+pub use self::aia::IssuerFetcher;
+pub use self::algorithms::{Algorithm, AlgorithmPolicy, Curve, HashAlgorithm, SignatureAlgorithm};
+pub use self::chain::VerifiedChain;
+pub use self::client::ClientName;
+pub use self::crl::RevocationReason;
+pub use self::error::{ErrorKind, ValidationError};
+pub use self::hostname::verify_hostname;
+pub use self::ocsp::OcspStatus;
+pub use self::platform::{validate_cert_chain, validate_cert_chain_with_anchors,
+                         validate_cert_chain_with_additional_anchors, verify_cert_chain,
+                         verify_client_cert_chain};
+pub use self::truststore::{TrustAnchors, TrustStore};
+pub use self::verifier::{KeyPurpose, RevocationMode, Verifier, VerifierBuilder};
 
+pub mod portable;
+
+mod aia;
+mod algorithms;
+mod cache;
+mod chain;
+mod client;
+mod crl;
+mod der;
+mod error;
+mod hostname;
+mod issuers;
+mod name_constraints;
+mod ocsp;
+mod pem;
 mod platform;
-#[cfg(windows)]
+mod signature;
+mod truststore;
+mod verifier;
+mod x509;
+#[cfg(all(windows, not(feature = "portable")))]
 mod windows;
-#[cfg(target_os = "macos")]
+#[cfg(all(target_os = "macos", not(feature = "portable")))]
 mod osx;
+#[cfg(all(target_os = "linux", not(feature = "portable")))]
+mod linux;
 
 #[cfg(test)]
 mod test {
-    use validate_cert_chain;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use aia::IssuerFetcher;
+    use portable;
+    use {ErrorKind, ValidationError};
+    use {validate_cert_chain, validate_cert_chain_with_anchors, validate_cert_chain_with_additional_anchors,
+         verify_cert_chain, verify_client_cert_chain};
+    use TrustAnchors;
     use ValidationResult;
+    use Verifier;
 
     pub fn certifi_chain() -> Vec<&'static[u8]> {
         let leaf = include_bytes!("../fixtures/certifi/leaf.crt");
@@ -73,6 +138,11 @@ mod test {
         vec![leaf, first_inter, second_inter]
     }
 
+    // A time at which the certifi.io chain was valid: the start of June 2016.
+    pub fn certifi_time() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_464_739_200)
+    }
+
     pub fn expired_chain() -> Vec<&'static[u8]> {
         let leaf = include_bytes!("../fixtures/expired/leaf.crt");
         let first_inter = include_bytes!("../fixtures/expired/first-intermediate.crt");
@@ -87,13 +157,69 @@ mod test {
         vec![leaf]
     }
 
+    // A chain issued by our own test root, valid from 2024 until 2044.
+    pub fn test_pki_chain() -> Vec<&'static[u8]> {
+        let leaf = include_bytes!("../fixtures/test-pki/leaf.crt");
+        let intermediate = include_bytes!("../fixtures/test-pki/intermediate.crt");
+
+        vec![leaf, intermediate]
+    }
+
+    pub fn test_pki_root() -> &'static[u8] {
+        include_bytes!("../fixtures/test-pki/root.crt")
+    }
+
+    // An issuer fetcher that serves fixtures from memory, remembering the URLs it
+    // was asked for.
+    pub struct MemoryFetcher {
+        resources: HashMap<String, Vec<u8>>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl MemoryFetcher {
+        pub fn new(resources: &[(&str, &[u8])]) -> MemoryFetcher {
+            MemoryFetcher {
+                resources: resources.iter().map(|&(url, body)| (url.to_string(), body.to_vec())).collect(),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        // Serves the test intermediate at the URLs the leaves in fixtures/aia give
+        // for it: as a certificate, and in a PKCS#7 bundle.
+        pub fn with_intermediate() -> MemoryFetcher {
+            MemoryFetcher::new(&[
+                ("http://certitude.test/intermediate.crt", test_pki_chain()[1]),
+                ("http://certitude.test/intermediate.p7c", include_bytes!("../fixtures/aia/intermediate.p7c")),
+            ])
+        }
+
+        pub fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl IssuerFetcher for MemoryFetcher {
+        fn fetch(&self, url: &str) -> Option<Vec<u8>> {
+            self.requests.lock().unwrap().push(url.to_string());
+            self.resources.get(url).cloned()
+        }
+    }
+
     #[test]
     fn can_validate_good_chain() {
         let chain = certifi_chain();
-        let valid = validate_cert_chain(&chain, "certifi.io");
+        let verifier = Verifier::builder().time(certifi_time()).build();
+        let valid = verifier.validate(&chain, "certifi.io");
         assert_eq!(valid, ValidationResult::Trusted);
     }
 
+    #[test]
+    fn good_chain_has_since_expired() {
+        let chain = certifi_chain();
+        let valid = validate_cert_chain(&chain, "certifi.io");
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
     #[test]
     fn fails_on_bad_hostname() {
         let chain = certifi_chain();
@@ -131,4 +257,111 @@ mod test {
         let valid = validate_cert_chain(&chain, "self-signed.badssl.com");
         assert_eq!(valid, ValidationResult::NotTrusted);
     }
+
+    #[test]
+    fn can_validate_with_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn fails_without_matching_anchor() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[self_signed_chain()[0]]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn can_validate_with_additional_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn portable_agrees_with_platform() {
+        let cases = vec![
+            (certifi_chain(), "certifi.io"),
+            (certifi_chain(), "lukasa.co.uk"),
+            (expired_chain(), "expired.badssl.com"),
+            (self_signed_chain(), "self-signed.badssl.com"),
+        ];
+        for (chain, hostname) in cases {
+            let native = validate_cert_chain(&chain, hostname);
+            let portable = portable::validate_cert_chain(&chain, hostname);
+            assert_eq!(native, portable, "disagreement validating {}", hostname);
+        }
+    }
+
+    #[test]
+    fn portable_agrees_with_platform_on_anchors() {
+        let cases = vec![
+            (test_pki_chain(), "certitude.test", vec![test_pki_root()]),
+            (test_pki_chain(), "lukasa.co.uk", vec![test_pki_root()]),
+            (test_pki_chain(), "certitude.test", vec![]),
+            (self_signed_chain(), "self-signed.badssl.com", vec![self_signed_chain()[0]]),
+        ];
+        for (chain, hostname, anchors) in cases {
+            let native = validate_cert_chain_with_anchors(&chain, hostname, &anchors);
+            let portable = portable::validate_cert_chain_with_anchors(&chain, hostname, &anchors);
+            assert_eq!(native, portable, "disagreement validating {}", hostname);
+        }
+    }
+
+    #[test]
+    fn portable_agrees_with_platform_on_client_chains() {
+        let client = include_bytes!("../fixtures/client/gateway.crt");
+        let anchors = [test_pki_root()];
+        let cases = vec![
+            vec![&client[..], test_pki_chain()[1]],
+            vec![&client[..]],
+            test_pki_chain(),
+        ];
+        for chain in cases {
+            let native = verify_client_cert_chain(&chain, TrustAnchors::Only(&anchors));
+            let portable = portable::verify_client_cert_chain(&chain, TrustAnchors::Only(&anchors));
+            assert_eq!(native.is_ok(), portable.is_ok());
+            assert_eq!(native.map(|chain| chain.certificates().len()), portable.map(|chain| chain.certificates().len()));
+        }
+    }
+
+    #[test]
+    fn portable_builds_the_same_chain_as_platform() {
+        let chain = test_pki_chain();
+        let anchors = [test_pki_root()];
+        let native = verify_cert_chain(&chain, "certitude.test", TrustAnchors::Only(&anchors));
+        let portable = portable::verify_cert_chain(&chain, "certitude.test", TrustAnchors::Only(&anchors));
+        assert_eq!(native.unwrap(), portable.unwrap());
+    }
+
+    #[test]
+    fn enforces_name_constraints_everywhere() {
+        let intermediate = include_bytes!("../fixtures/name-constraints/intermediate.crt");
+        let anchors = [test_pki_root()];
+        let permitted = include_bytes!("../fixtures/name-constraints/permitted.crt");
+        let chain = [&permitted[..], &intermediate[..]];
+        assert!(verify_cert_chain(&chain, "www.corp.example", TrustAnchors::Only(&anchors)).is_ok());
+        assert!(portable::verify_cert_chain(&chain, "www.corp.example", TrustAnchors::Only(&anchors)).is_ok());
+
+        // Each of these has a name of a different kind that the intermediate doesn't allow.
+        let violations: &[&[u8]] = &[
+            include_bytes!("../fixtures/name-constraints/dns-not-permitted.crt"),
+            include_bytes!("../fixtures/name-constraints/dns-excluded.crt"),
+            include_bytes!("../fixtures/name-constraints/ip-not-permitted.crt"),
+            include_bytes!("../fixtures/name-constraints/ip-excluded.crt"),
+            include_bytes!("../fixtures/name-constraints/email-not-permitted.crt"),
+            include_bytes!("../fixtures/name-constraints/uri-not-permitted.crt"),
+            include_bytes!("../fixtures/name-constraints/directory-name-not-permitted.crt"),
+        ];
+        let violation = Err(ValidationError::at(ErrorKind::NameConstraintViolation, 0));
+        for leaf in violations {
+            let chain = [*leaf, &intermediate[..]];
+            let verifier = Verifier::builder().anchors(&anchors).check_hostname(false).build();
+            assert_eq!(verifier.verify(&chain, ""), violation);
+            assert_eq!(verifier.verify_with(&chain, "", portable::verify_chain), violation);
+        }
+    }
 }
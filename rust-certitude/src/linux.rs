@@ -0,0 +1,410 @@
+use libc::time_t;
+use openssl::error::ErrorStack;
+use openssl::ssl::SslFiletype;
+use openssl::stack::Stack;
+use openssl::x509::{X509, X509StoreContext, X509StoreContextRef, X509VerifyResult};
+use openssl::x509::store::{X509Lookup, X509Store, X509StoreBuilder};
+use openssl::x509::verify::X509VerifyParam;
+use openssl::x509::X509PurposeId;
+
+use chain::VerifiedChain;
+use error::{ErrorKind, ValidationError};
+use verifier::{KeyPurpose, Policy, RevocationMode};
+use truststore::SystemTrustStore;
+use TrustStore;
+use TrustAnchors;
+
+pub fn verify_chain(encoded_certs: &[&[u8]], policy: &Policy) -> Result<VerifiedChain, ValidationError> {
+    // OpenSSL can check revocation, but only against CRLs that it's given, and we
+    // have none to give it. The status of every certificate is unknown, which is only
+    // acceptable on a best effort.
+    if policy.revocation == RevocationMode::Required {
+        return Err(ErrorKind::MissingFunctionality.into());
+    }
+
+    let (leaf, intermediates) = parse_certs(encoded_certs)?;
+    let store = build_trust_store(policy)?;
+
+    let mut context = match X509StoreContext::new() {
+        Ok(context) => context,
+        Err(_) => return Err(ErrorKind::ErrorDuringValidation.into()),
+    };
+
+    // OpenSSL only reports hard failures through the ErrorStack: the actual
+    // verdict is reported through the context's error code.
+    let verified = context.init(&store, &leaf, &intermediates, |ctx| {
+        ctx.verify_cert().map(|_| (ctx.error(), failed_cert(ctx), verified_chain(ctx)))
+    });
+    match verified {
+        Ok((X509VerifyResult::OK, _, chain)) => chain.ok_or_else(|| ErrorKind::ErrorDuringValidation.into()),
+        Ok((result, cert, _)) => {
+            let index = cert.and_then(|cert| encoded_certs.iter().position(|&encoded| encoded == &cert[..]));
+            Err(ValidationError::new(verify_result_to_error_kind(result), index))
+        }
+        Err(_) => Err(ErrorKind::ErrorDuringValidation.into()),
+    }
+}
+
+
+// The DER encoding of the certificate that verification failed at, if there was one.
+fn failed_cert(ctx: &X509StoreContextRef) -> Option<Vec<u8>> {
+    let depth = ctx.error_depth() as usize;
+    ctx.chain().and_then(|chain| chain.get(depth)).and_then(|cert| cert.to_der().ok())
+}
+
+
+// The DER encoding of the chain OpenSSL built, which ends at the trust anchor.
+fn verified_chain(ctx: &X509StoreContextRef) -> Option<VerifiedChain> {
+    let chain = ctx.chain()?;
+    let certificates = chain.iter().map(|cert| cert.to_der().ok()).collect::<Option<Vec<_>>>()?;
+    VerifiedChain::new(certificates)
+}
+
+
+// Parses the DER-encoded chain into the leaf and a stack of intermediates.
+fn parse_certs(encoded_certs: &[&[u8]]) -> Result<(X509, Stack<X509>), ValidationError> {
+    let mut certs = Vec::new();
+    for encoded_cert in encoded_certs {
+        match X509::from_der(encoded_cert) {
+            Ok(cert) => certs.push(cert),
+            Err(_) => return Err(ErrorKind::MalformedCertificateInChain.into()),
+        }
+    }
+    if certs.is_empty() {
+        return Err(ErrorKind::MalformedCertificateInChain.into());
+    }
+
+    let leaf = certs.remove(0);
+    let mut intermediates = match Stack::new() {
+        Ok(stack) => stack,
+        Err(_) => return Err(ErrorKind::ErrorDuringValidation.into()),
+    };
+    for cert in certs {
+        if intermediates.push(cert).is_err() {
+            return Err(ErrorKind::ErrorDuringValidation.into());
+        }
+    }
+    Ok((leaf, intermediates))
+}
+
+
+// Builds the store of trusted roots, configured with the policy we want: suitable
+// for the purpose, at this time.
+fn build_trust_store(policy: &Policy) -> Result<X509Store, ValidationError> {
+    let mut builder = match X509StoreBuilder::new() {
+        Ok(builder) => builder,
+        Err(_) => return Err(ErrorKind::UnableToBuildTrustStore.into()),
+    };
+
+    match policy.anchors {
+        TrustAnchors::System => add_system_anchors(&mut builder, &policy.system_store)?,
+        TrustAnchors::Only(anchors) => add_caller_anchors(&mut builder, anchors)?,
+        TrustAnchors::SystemAnd(anchors) => {
+            add_system_anchors(&mut builder, &policy.system_store)?;
+            add_caller_anchors(&mut builder, anchors)?;
+        }
+    }
+
+    let mut param = match X509VerifyParam::new() {
+        Ok(param) => param,
+        Err(_) => return Err(ErrorKind::ErrorDuringValidation.into()),
+    };
+    // OpenSSL's purposes for anything but TLS add requirements of their own, such as
+    // a critical extended key usage for time-stamping, that no other platform has.
+    // For those, we only ask for a valid chain: the verifier checks the leaf.
+    let purpose = match policy.purpose {
+        KeyPurpose::ServerAuth => X509PurposeId::SSL_SERVER,
+        KeyPurpose::ClientAuth => X509PurposeId::SSL_CLIENT,
+        _ => X509PurposeId::ANY,
+    };
+    if param.set_purpose(purpose).is_err() {
+        return Err(ErrorKind::ErrorDuringValidation.into());
+    }
+    if policy.time.is_some() {
+        param.set_time(policy.unix_time() as time_t);
+    }
+    if builder.set_param(&param).is_err() {
+        return Err(ErrorKind::ErrorDuringValidation.into());
+    }
+
+    Ok(builder.build())
+}
+
+
+fn add_system_anchors(builder: &mut X509StoreBuilder, system_store: &SystemTrustStore) -> Result<(), ValidationError> {
+    // We'd rather find the distribution's trust anchors ourselves: OpenSSL only
+    // knows about the location it was built with, which is frequently wrong for
+    // statically-linked or vendored builds. If we can't find any, though, OpenSSL
+    // may still know better.
+    let added = match system_store.get() {
+        Some(store) => add_anchors(builder, store),
+        None => builder.set_default_paths(),
+    };
+    added.map_err(|_| ErrorKind::UnableToBuildTrustStore.into())
+}
+
+
+fn add_caller_anchors(builder: &mut X509StoreBuilder, anchors: &[&[u8]]) -> Result<(), ValidationError> {
+    add_anchors(builder, &TrustStore::from_anchors(anchors)).map_err(|_| ErrorKind::UnableToBuildTrustStore.into())
+}
+
+
+fn add_anchors(builder: &mut X509StoreBuilder, store: &TrustStore) -> Result<(), ErrorStack> {
+    for anchor in store.anchors() {
+        // Skip anything OpenSSL won't accept, as the portable validator does.
+        if let Ok(cert) = X509::from_der(anchor) {
+            builder.add_cert(cert)?;
+        }
+    }
+
+    // OpenSSL can look anchors up in hashed directories itself, and does so lazily.
+    if !store.hashed_directories().is_empty() {
+        let lookup = builder.add_lookup(X509Lookup::hash_dir())?;
+        for directory in store.hashed_directories() {
+            if let Some(directory) = directory.to_str() {
+                lookup.add_dir(directory, SslFiletype::PEM)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+
+// Convert the OpenSSL verification result to the reason validation failed.
+fn verify_result_to_error_kind(result: X509VerifyResult) -> ErrorKind {
+    match result.as_raw() {
+        X509_V_ERR_CERT_HAS_EXPIRED => ErrorKind::Expired,
+        X509_V_ERR_CERT_NOT_YET_VALID => ErrorKind::NotYetValid,
+        X509_V_ERR_HOSTNAME_MISMATCH => ErrorKind::HostnameMismatch,
+        X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT |
+        X509_V_ERR_DEPTH_ZERO_SELF_SIGNED_CERT |
+        X509_V_ERR_SELF_SIGNED_CERT_IN_CHAIN |
+        X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT_LOCALLY |
+        X509_V_ERR_UNABLE_TO_VERIFY_LEAF_SIGNATURE => ErrorKind::UnknownIssuer,
+        X509_V_ERR_UNABLE_TO_DECRYPT_CERT_SIGNATURE |
+        X509_V_ERR_CERT_SIGNATURE_FAILURE => ErrorKind::BadSignature,
+        X509_V_ERR_CERT_REVOKED => ErrorKind::Revoked,
+        X509_V_ERR_INVALID_CA => ErrorKind::NotCertificateAuthority,
+        X509_V_ERR_KEYUSAGE_NO_CERTSIGN => ErrorKind::KeyCertSignNotAllowed,
+        X509_V_ERR_PATH_LENGTH_EXCEEDED => ErrorKind::PathLengthExceeded,
+        X509_V_ERR_INVALID_PURPOSE => ErrorKind::InvalidUsage,
+        X509_V_ERR_UNHANDLED_CRITICAL_EXTENSION => ErrorKind::UnhandledCriticalExtension,
+        X509_V_ERR_PERMITTED_VIOLATION |
+        X509_V_ERR_EXCLUDED_VIOLATION |
+        X509_V_ERR_SUBTREE_MINMAX |
+        X509_V_ERR_UNSUPPORTED_CONSTRAINT_TYPE |
+        X509_V_ERR_UNSUPPORTED_CONSTRAINT_SYNTAX |
+        X509_V_ERR_UNSUPPORTED_NAME_SYNTAX => ErrorKind::NameConstraintViolation,
+        X509_V_ERR_ERROR_IN_CERT_NOT_BEFORE_FIELD |
+        X509_V_ERR_ERROR_IN_CERT_NOT_AFTER_FIELD => ErrorKind::MalformedCertificateInChain,
+        _ => ErrorKind::NotTrusted,
+    }
+}
+
+
+// Define the verification error codes we care about, because rust-openssl doesn't.
+const X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT: i32 = 2;
+const X509_V_ERR_UNABLE_TO_DECRYPT_CERT_SIGNATURE: i32 = 4;
+const X509_V_ERR_CERT_SIGNATURE_FAILURE: i32 = 7;
+const X509_V_ERR_CERT_NOT_YET_VALID: i32 = 9;
+const X509_V_ERR_CERT_HAS_EXPIRED: i32 = 10;
+const X509_V_ERR_ERROR_IN_CERT_NOT_BEFORE_FIELD: i32 = 13;
+const X509_V_ERR_ERROR_IN_CERT_NOT_AFTER_FIELD: i32 = 14;
+const X509_V_ERR_DEPTH_ZERO_SELF_SIGNED_CERT: i32 = 18;
+const X509_V_ERR_SELF_SIGNED_CERT_IN_CHAIN: i32 = 19;
+const X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT_LOCALLY: i32 = 20;
+const X509_V_ERR_UNABLE_TO_VERIFY_LEAF_SIGNATURE: i32 = 21;
+const X509_V_ERR_CERT_REVOKED: i32 = 23;
+const X509_V_ERR_INVALID_CA: i32 = 24;
+const X509_V_ERR_PATH_LENGTH_EXCEEDED: i32 = 25;
+const X509_V_ERR_INVALID_PURPOSE: i32 = 26;
+const X509_V_ERR_KEYUSAGE_NO_CERTSIGN: i32 = 32;
+const X509_V_ERR_UNHANDLED_CRITICAL_EXTENSION: i32 = 34;
+const X509_V_ERR_PERMITTED_VIOLATION: i32 = 47;
+const X509_V_ERR_EXCLUDED_VIOLATION: i32 = 48;
+const X509_V_ERR_SUBTREE_MINMAX: i32 = 49;
+const X509_V_ERR_UNSUPPORTED_CONSTRAINT_TYPE: i32 = 51;
+const X509_V_ERR_UNSUPPORTED_CONSTRAINT_SYNTAX: i32 = 52;
+const X509_V_ERR_UNSUPPORTED_NAME_SYNTAX: i32 = 53;
+const X509_V_ERR_HOSTNAME_MISMATCH: i32 = 62;
+
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use chain::VerifiedChain;
+    use error::{ErrorKind, ValidationError};
+    use linux::verify_chain;
+    use test::{expired_chain, certifi_chain, self_signed_chain, test_pki_chain, test_pki_root};
+    use verifier::{KeyPurpose, Policy, RevocationMode, Verifier};
+    use TrustAnchors;
+    use ValidationResult;
+
+    fn verify_cert_chain(encoded_certs: &[&[u8]], hostname: &str, anchors: TrustAnchors) -> Result<VerifiedChain, ValidationError> {
+        Verifier::builder().trust_anchors(anchors).build().verify_with(encoded_certs, hostname, verify_chain)
+    }
+
+    fn validate_cert_chain(encoded_certs: &[&[u8]], hostname: &str) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::System).into()
+    }
+
+    fn validate_cert_chain_with_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::Only(anchors)).into()
+    }
+
+    fn validate_cert_chain_with_additional_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::SystemAnd(anchors)).into()
+    }
+
+    #[test]
+    fn fails_on_bad_hostname() {
+        let chain = certifi_chain();
+        let valid = validate_cert_chain(&chain, "lukasa.co.uk");
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn fails_on_bad_cert() {
+        let mut good_chain = certifi_chain();
+        let originals = good_chain.split_first_mut().unwrap();
+        let leaf = originals.0;
+        let intermediates = originals.1;
+
+        // Deliberately truncate the leaf cert.
+        let mut certs = vec![&leaf[1..50]];
+        certs.extend(intermediates.iter());
+        let valid = validate_cert_chain(&certs, "certifi.io");
+        assert_eq!(valid, ValidationResult::MalformedCertificateInChain);
+    }
+
+    #[test]
+    fn fails_on_expired_cert() {
+        let chain = expired_chain();
+        let valid = validate_cert_chain(&chain, "expired.badssl.com");
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn test_fails_on_self_signed() {
+        let chain = self_signed_chain();
+        let valid = validate_cert_chain(&chain, "self-signed.badssl.com");
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn test_fails_on_invalid_asn1() {
+        // We need to mutate the first byte of the original cert.
+        let chain = certifi_chain();
+        let mut first_chain = chain[0].to_vec();
+        first_chain[0] = 0xff;
+        let mut chain_builder = vec![first_chain.as_slice()];
+        chain_builder.append(&mut chain[1..].to_vec());
+        let new_chain = chain_builder.as_slice();
+
+        let valid = validate_cert_chain(new_chain, "certifi.io");
+        assert_eq!(valid, ValidationResult::MalformedCertificateInChain);
+    }
+
+    #[test]
+    fn can_validate_with_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn returns_the_verified_chain() {
+        let chain = test_pki_chain();
+        let verified = verify_cert_chain(&chain, "certitude.test", TrustAnchors::Only(&[test_pki_root()])).unwrap();
+        assert_eq!(verified.certificates(), &[chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]);
+    }
+
+    #[test]
+    fn fails_without_matching_anchor() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[self_signed_chain()[0]]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn checks_hostname_with_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "lukasa.co.uk", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn can_validate_with_additional_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "lukasa.co.uk", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn fails_on_empty_chain() {
+        let valid = validate_cert_chain(&[], "certifi.io");
+        assert_eq!(valid, ValidationResult::MalformedCertificateInChain);
+    }
+
+    #[test]
+    fn reports_hostname_mismatch() {
+        let chain = test_pki_chain();
+        let error = verify_cert_chain(&chain, "lukasa.co.uk", TrustAnchors::Only(&[test_pki_root()]));
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+    }
+
+    #[test]
+    fn reports_expiry() {
+        let chain = expired_chain();
+        let error = verify_cert_chain(&chain, "expired.badssl.com", TrustAnchors::System).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Expired);
+    }
+
+    #[test]
+    fn validates_at_the_given_time() {
+        let chain = test_pki_chain();
+        let anchors = [test_pki_root()];
+        let policy = Policy {
+            time: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),  // November 2023
+            ..Policy::new(TrustAnchors::Only(&anchors))
+        };
+        let error = verify_chain(&chain, &policy);
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::NotYetValid, 0)));
+    }
+
+    #[test]
+    fn applies_the_policy() {
+        let chain = test_pki_chain();
+        let anchors = [test_pki_root()];
+        assert!(verify_chain(&chain, &Policy::new(TrustAnchors::Only(&anchors))).is_ok());
+
+        let policy = Policy { purpose: KeyPurpose::ClientAuth, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert_eq!(verify_chain(&chain, &policy), Err(ValidationError::at(ErrorKind::InvalidUsage, 0)));
+        let leaf = include_bytes!("../fixtures/key-purpose/client-auth.crt");
+        assert!(verify_chain(&[&leaf[..], chain[1]], &policy).is_ok());
+        let leaf = include_bytes!("../fixtures/key-purpose/code-signing.crt");
+        let policy = Policy { purpose: KeyPurpose::CodeSigning, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert!(verify_chain(&[&leaf[..], chain[1]], &policy).is_ok());
+
+        let policy = Policy { revocation: RevocationMode::BestEffort, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert!(verify_chain(&chain, &policy).is_ok());
+        let policy = Policy { revocation: RevocationMode::Required, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert_eq!(verify_chain(&chain, &policy), Err(ErrorKind::MissingFunctionality.into()));
+    }
+
+    #[test]
+    fn reports_unknown_issuer() {
+        let chain = test_pki_chain();
+        let error = verify_cert_chain(&chain, "certitude.test", TrustAnchors::Only(&[]));
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::UnknownIssuer, 1)));
+        let error = verify_cert_chain(&chain[..1], "certitude.test", TrustAnchors::Only(&[test_pki_root()]));
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::UnknownIssuer, 0)));
+    }
+}
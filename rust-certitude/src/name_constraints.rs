@@ -0,0 +1,246 @@
+// Enforcement of the name constraints extension, following RFC 5280.
+//
+// Platforms disagree about which kinds of name they constrain, and some don't check
+// name constraints at all, so we check the chain the platform built ourselves. Each
+// certificate authority's constraints apply to every certificate below it in the
+// chain, so a name must satisfy the constraints of all of them.
+
+use std::mem;
+
+use x509::{self, Certificate, GeneralName, NameConstraints};
+
+
+// Checks the names of each certificate in the chain, leaf first, against the name
+// constraints of the certificate authorities above it. On failure, returns the
+// position in the chain of the first certificate with a name that isn't allowed.
+pub(crate) fn check_chain(chain: &[Certificate]) -> Result<(), usize> {
+    for (index, cert) in chain.iter().enumerate() {
+        // Self-issued certificates are exempt, unless they are the leaf.
+        if index > 0 && cert.is_self_issued() {
+            continue;
+        }
+        let names = names(cert, index == 0);
+        for authority in &chain[index + 1..] {
+            if let Some(ref constraints) = authority.name_constraints {
+                if !names.iter().all(|name| permits(constraints, name)) {
+                    return Err(index);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+
+// The names of a certificate that name constraints apply to: its subject alternative
+// names, its subject, and any email addresses in its subject. A leaf without DNS
+// names may be matched by its common name, so that is constrained too.
+fn names<'a>(cert: &Certificate<'a>, is_leaf: bool) -> Vec<GeneralName<'a>> {
+    let mut names = cert.subject_alt_names().unwrap_or_default();
+    let has_dns_names = names.iter().any(|name| matches!(*name, GeneralName::DnsName(_)));
+
+    // An empty subject is no name at all.
+    if cert.subject != [0x30, 0x00] {
+        names.push(GeneralName::DirectoryName(cert.subject));
+    }
+    names.extend(cert.email_addresses().into_iter().map(GeneralName::Rfc822Name));
+    if is_leaf && !has_dns_names {
+        names.extend(cert.common_names()
+                         .into_iter()
+                         .filter(|name| looks_like_dns_name(name))
+                         .map(GeneralName::DnsName));
+    }
+    names
+}
+
+fn looks_like_dns_name(name: &[u8]) -> bool {
+    name.contains(&b'.') &&
+    name.iter().all(|&b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'*')
+}
+
+
+// Whether the constraints allow the name. Names of a kind with no permitted subtrees
+// are allowed unless they are excluded. Names of a kind we don't understand are only
+// refused if the constraints include a kind we don't understand.
+fn permits(constraints: &NameConstraints, name: &GeneralName) -> bool {
+    let same_kind = |base: &&GeneralName| mem::discriminant(*base) == mem::discriminant(name);
+
+    if let GeneralName::Unsupported = *name {
+        let subtrees = constraints.permitted.iter().chain(constraints.excluded.iter()).flatten();
+        return !subtrees.into_iter().any(|base| same_kind(&base));
+    }
+
+    if let Some(ref excluded) = constraints.excluded {
+        if excluded.iter().filter(same_kind).any(|base| within(name, base, true)) {
+            return false;
+        }
+    }
+    if let Some(ref permitted) = constraints.permitted {
+        let mut bases = permitted.iter().filter(same_kind).peekable();
+        if bases.peek().is_some() && !bases.any(|base| within(name, base, false)) {
+            return false;
+        }
+    }
+    true
+}
+
+
+// Whether the name is within the subtree. When checking exclusions, a wildcard DNS
+// name is within a subtree if any name it covers is.
+fn within(name: &GeneralName, base: &GeneralName, excluding: bool) -> bool {
+    match (name, base) {
+        (&GeneralName::DnsName(name), &GeneralName::DnsName(base)) => {
+            let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+            let base = String::from_utf8_lossy(base).to_ascii_lowercase();
+            let name = name.trim_end_matches('.');
+            let base = base.trim_end_matches('.');
+            let covered = excluding && match name.strip_prefix("*.") {
+                Some(suffix) => base.trim_start_matches('.').split_once('.').map(|(_, rest)| rest) == Some(suffix),
+                None => false,
+            };
+            covered || dns_name_within(name, base)
+        }
+        (&GeneralName::Rfc822Name(name), &GeneralName::Rfc822Name(base)) => {
+            let (local, host) = match name.iter().rposition(|&b| b == b'@') {
+                Some(at) => (&name[..at], &name[at + 1..]),
+                None => return false,
+            };
+            match base.iter().rposition(|&b| b == b'@') {
+                // A particular mailbox.
+                Some(at) => local == &base[..at] && host.eq_ignore_ascii_case(&base[at + 1..]),
+                None => host_within(host, base),
+            }
+        }
+        (&GeneralName::Uri(name), &GeneralName::Uri(base)) => {
+            match uri_host(name) {
+                Some(host) => host_within(host, base),
+                None => false,
+            }
+        }
+        (&GeneralName::IpAddress(address), &GeneralName::IpAddress(base)) => {
+            if base.len() != address.len() * 2 {
+                return false;
+            }
+            let (network, mask) = base.split_at(address.len());
+            address.iter().zip(network).zip(mask).all(|((a, n), m)| a & m == n & m)
+        }
+        (&GeneralName::DirectoryName(name), &GeneralName::DirectoryName(base)) => {
+            match (x509::canonical_rdns(name), x509::canonical_rdns(base)) {
+                (Ok(name), Ok(base)) => name.starts_with(&base),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+// A DNS name is within a subtree if it can be made by adding labels to the left of
+// the subtree's name. A subtree written with a leading dot only holds the names below
+// it, and an empty one holds every name.
+fn dns_name_within(name: &str, base: &str) -> bool {
+    if base.is_empty() {
+        true
+    } else if base.starts_with('.') {
+        name.ends_with(base)
+    } else {
+        name == base || name.strip_suffix(base).is_some_and(|rest| rest.ends_with('.'))
+    }
+}
+
+// For email addresses and URIs, a subtree is a host, which holds only that host, or a
+// domain with a leading dot, which holds every host below it.
+fn host_within(host: &[u8], base: &[u8]) -> bool {
+    if base.starts_with(b".") {
+        host.len() > base.len() && host[host.len() - base.len()..].eq_ignore_ascii_case(base)
+    } else {
+        host.eq_ignore_ascii_case(base)
+    }
+}
+
+// The host of a URI, if it has a host that is a DNS name.
+fn uri_host(uri: &[u8]) -> Option<&[u8]> {
+    let colon = uri.iter().position(|&b| b == b':')?;
+    let rest = uri[colon + 1..].strip_prefix(b"//")?;
+    let end = rest.iter().position(|&b| b == b'/' || b == b'?' || b == b'#').unwrap_or(rest.len());
+    let authority = &rest[..end];
+    let host_and_port = match authority.iter().rposition(|&b| b == b'@') {
+        Some(at) => &authority[at + 1..],
+        None => authority,
+    };
+    // An IP literal is no DNS name.
+    if host_and_port.starts_with(b"[") {
+        return None;
+    }
+    let host = match host_and_port.iter().position(|&b| b == b':') {
+        Some(colon) => &host_and_port[..colon],
+        None => host_and_port,
+    };
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use name_constraints::{check_chain, dns_name_within, uri_host};
+    use test::{test_pki_chain, test_pki_root};
+    use x509::Certificate;
+
+    fn check(leaf: &[u8]) -> Result<(), usize> {
+        let intermediate = include_bytes!("../fixtures/name-constraints/intermediate.crt");
+        let chain = [leaf, &intermediate[..], test_pki_root()];
+        check_chain(&chain.iter().map(|cert| Certificate::parse(cert).unwrap()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn allows_permitted_names() {
+        // DNS names, an IP address, an email address, a URI and a subject, all in the
+        // permitted subtrees of the intermediate.
+        assert_eq!(check(include_bytes!("../fixtures/name-constraints/permitted.crt")), Ok(()));
+    }
+
+    #[test]
+    fn refuses_names_outside_permitted_subtrees() {
+        assert_eq!(check(include_bytes!("../fixtures/name-constraints/dns-not-permitted.crt")), Err(0));
+        assert_eq!(check(include_bytes!("../fixtures/name-constraints/ip-not-permitted.crt")), Err(0));
+        assert_eq!(check(include_bytes!("../fixtures/name-constraints/email-not-permitted.crt")), Err(0));
+        assert_eq!(check(include_bytes!("../fixtures/name-constraints/uri-not-permitted.crt")), Err(0));
+        assert_eq!(check(include_bytes!("../fixtures/name-constraints/directory-name-not-permitted.crt")), Err(0));
+    }
+
+    #[test]
+    fn refuses_excluded_names() {
+        assert_eq!(check(include_bytes!("../fixtures/name-constraints/dns-excluded.crt")), Err(0));
+        assert_eq!(check(include_bytes!("../fixtures/name-constraints/ip-excluded.crt")), Err(0));
+    }
+
+    #[test]
+    fn ignores_unconstrained_chains() {
+        let chain = test_pki_chain();
+        let certs = [chain[0], chain[1], test_pki_root()];
+        assert_eq!(check_chain(&certs.iter().map(|cert| Certificate::parse(cert).unwrap()).collect::<Vec<_>>()), Ok(()));
+    }
+
+    #[test]
+    fn matches_dns_subtrees() {
+        assert!(dns_name_within("corp.example", "corp.example"));
+        assert!(dns_name_within("www.corp.example", "corp.example"));
+        assert!(dns_name_within("*.corp.example", "corp.example"));
+        assert!(!dns_name_within("evilcorp.example", "corp.example"));
+        assert!(!dns_name_within("corp.example", ".corp.example"));
+        assert!(dns_name_within("www.corp.example", ".corp.example"));
+        assert!(dns_name_within("anything.example", ""));
+    }
+
+    #[test]
+    fn finds_uri_hosts() {
+        assert_eq!(uri_host(b"https://api.corp.example/v1"), Some(&b"api.corp.example"[..]));
+        assert_eq!(uri_host(b"https://user@api.corp.example:8443?q"), Some(&b"api.corp.example"[..]));
+        assert_eq!(uri_host(b"https://[fd00::1]/"), None);
+        assert_eq!(uri_host(b"urn:uuid:1234"), None);
+        assert_eq!(uri_host(b"file:///etc/passwd"), None);
+    }
+}
@@ -0,0 +1,359 @@
+// Checking the OCSP response a server stapled to its handshake, as profiled by RFC
+// 6960.
+//
+// A stapled response is only about the leaf, and only relied on if it was signed by
+// the leaf's issuer in the chain, or by a responder the issuer delegated to with the
+// OCSPSigning extended key usage, and is current at the time of validation. A
+// response that can't be relied on says nothing at all: it's as if none was stapled.
+
+use algorithms::HashAlgorithm;
+use crl::{self, RevocationReason};
+use der::{self, Reader};
+use signature;
+use x509::{self, AlgorithmIdentifier, Certificate};
+
+const OID_PKIX_OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+const OID_PKIX_OCSP_NONCE: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x02];
+const OID_KP_OCSP_SIGNING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x09];
+
+// How long a response without a next update is relied on after it was issued, in
+// seconds. RFC 6960 leaves this to the client; a day is what browsers allow.
+const MAX_AGE_WITHOUT_NEXT_UPDATE: i64 = 24 * 60 * 60;
+
+// The tags of the choices of ResponderID and CertStatus.
+const RESPONDER_BY_NAME: u8 = der::CONTEXT_SPECIFIC | der::CONSTRUCTED | 1;
+const RESPONDER_BY_KEY: u8 = der::CONTEXT_SPECIFIC | der::CONSTRUCTED | 2;
+const STATUS_GOOD: u8 = der::CONTEXT_SPECIFIC;
+const STATUS_REVOKED: u8 = der::CONTEXT_SPECIFIC | der::CONSTRUCTED | 1;
+const STATUS_UNKNOWN: u8 = der::CONTEXT_SPECIFIC | 2;
+
+
+/// The status of the leaf certificate, as the OCSP response stapled to the handshake
+/// gave it.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OcspStatus {
+    /// The certificate hasn't been revoked.
+    Good,
+    /// The certificate has been revoked, for the reason given, if any.
+    Revoked(Option<RevocationReason>),
+    /// The responder doesn't know of the certificate.
+    Unknown,
+}
+
+
+// Whom a response says signed it.
+enum ResponderId<'a> {
+    // The complete encoding of the responder's name.
+    ByName(&'a [u8]),
+    // The SHA-1 hash of the responder's public key.
+    ByKey(&'a [u8]),
+}
+
+impl<'a> ResponderId<'a> {
+    fn matches(&self, cert: &Certificate) -> bool {
+        match *self {
+            ResponderId::ByName(name) => x509::same_name(name, cert.subject),
+            ResponderId::ByKey(hash) => hash == &signature::digest(HashAlgorithm::Sha1, cert.spki.public_key)[..],
+        }
+    }
+}
+
+// The status a response gives for one certificate.
+struct SingleResponse<'a> {
+    // The complete encoding of the hash algorithm the certificate is identified with.
+    hash_algorithm: &'a [u8],
+    issuer_name_hash: &'a [u8],
+    issuer_key_hash: &'a [u8],
+    serial: &'a [u8],
+    status: OcspStatus,
+    // When the status was known to be correct, and when it next will be, in seconds
+    // since the Unix epoch.
+    this_update: i64,
+    next_update: Option<i64>,
+    unhandled_critical_extension: bool,
+}
+
+impl<'a> SingleResponse<'a> {
+    fn parse(data: &'a [u8]) -> Result<SingleResponse<'a>, der::Error> {
+        let mut reader = Reader::new(data);
+        let mut cert_id = Reader::new(reader.read(der::SEQUENCE)?);
+        let hash_algorithm = cert_id.read_element(der::SEQUENCE)?;
+        let issuer_name_hash = cert_id.read(der::OCTET_STRING)?;
+        let issuer_key_hash = cert_id.read(der::OCTET_STRING)?;
+        let serial = cert_id.read(der::INTEGER)?;
+        cert_id.finish()?;
+
+        let status = match reader.read_any()? {
+            (STATUS_GOOD, [], _) => OcspStatus::Good,
+            (STATUS_REVOKED, info, _) => {
+                let mut info = Reader::new(info);
+                x509::parse_time(&mut info)?;
+                let reason = match info.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)? {
+                    Some(reason) => match *der::parse(reason, der::ENUMERATED)? {
+                        [code] => Some(RevocationReason::from_code(code).ok_or(der::Error)?),
+                        _ => return Err(der::Error),
+                    },
+                    None => None,
+                };
+                info.finish()?;
+                OcspStatus::Revoked(reason)
+            }
+            (STATUS_UNKNOWN, [], _) => OcspStatus::Unknown,
+            _ => return Err(der::Error),
+        };
+
+        let this_update = x509::parse_time(&mut reader)?;
+        let next_update = match reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)? {
+            Some(next_update) => {
+                let mut next_update = Reader::new(next_update);
+                let time = x509::parse_time(&mut next_update)?;
+                next_update.finish()?;
+                Some(time)
+            }
+            None => None,
+        };
+        let mut unhandled_critical_extension = false;
+        if let Some(extensions) = reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED | 1)? {
+            let extensions = crl::parse_extensions(der::parse(extensions, der::SEQUENCE)?)?;
+            unhandled_critical_extension = extensions.iter().any(|extension| extension.critical);
+        }
+        reader.finish()?;
+
+        Ok(SingleResponse {
+            hash_algorithm,
+            issuer_name_hash,
+            issuer_key_hash,
+            serial,
+            status,
+            this_update,
+            next_update,
+            unhandled_critical_extension,
+        })
+    }
+
+    // Whether this is the status of the certificate, which the issuer issued.
+    fn is_for(&self, cert: &Certificate, issuer: &Certificate) -> bool {
+        let hash = match signature::hash_algorithm(self.hash_algorithm) {
+            Ok(hash) => hash,
+            Err(_) => return false,
+        };
+        self.serial == cert.serial &&
+            self.issuer_name_hash == &signature::digest(hash, issuer.subject)[..] &&
+            self.issuer_key_hash == &signature::digest(hash, issuer.spki.public_key)[..]
+    }
+
+    // Whether the status is current at the given time.
+    fn is_current(&self, time: i64) -> bool {
+        let expires = self.next_update.unwrap_or(self.this_update + MAX_AGE_WITHOUT_NEXT_UPDATE);
+        self.this_update <= time && time <= expires
+    }
+}
+
+
+pub struct Response<'a> {
+    pub tbs: &'a [u8],
+    pub signature_algorithm: AlgorithmIdentifier<'a>,
+    pub signature: &'a [u8],
+    responder: ResponderId<'a>,
+    // The complete encodings of the certificates the responder sent to help verify
+    // its signature.
+    pub certs: Vec<&'a [u8]>,
+    responses: Vec<SingleResponse<'a>>,
+    // Whether the response has a critical extension we don't understand.
+    pub unhandled_critical_extension: bool,
+}
+
+impl<'a> Response<'a> {
+    // Parses a DER-encoded OCSPResponse. Only successful responses of the basic type
+    // can be parsed: anything else has no status to give.
+    pub fn parse(data: &'a [u8]) -> Result<Response<'a>, der::Error> {
+        let mut outer = Reader::new(der::parse(data, der::SEQUENCE)?);
+        if outer.read(der::ENUMERATED)? != [0] {
+            return Err(der::Error);
+        }
+        let mut response_bytes = Reader::new(der::parse(
+            outer.read(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)?, der::SEQUENCE)?);
+        outer.finish()?;
+        if response_bytes.read(der::OID)? != OID_PKIX_OCSP_BASIC {
+            return Err(der::Error);
+        }
+        let basic = response_bytes.read(der::OCTET_STRING)?;
+        response_bytes.finish()?;
+
+        let mut basic = Reader::new(der::parse(basic, der::SEQUENCE)?);
+        let tbs = basic.read_element(der::SEQUENCE)?;
+        let signature_algorithm = x509::parse_algorithm(&mut basic)?;
+        let signature = basic.read_bit_string()?;
+        let mut certs = Vec::new();
+        if let Some(encoded_certs) = basic.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)? {
+            let mut reader = Reader::new(der::parse(encoded_certs, der::SEQUENCE)?);
+            while !reader.is_empty() {
+                certs.push(reader.read_element(der::SEQUENCE)?);
+            }
+        }
+        basic.finish()?;
+
+        let mut tbs_reader = Reader::new(der::parse(tbs, der::SEQUENCE)?);
+        // Only version 1 is defined, which is encoded by leaving the version out.
+        if tbs_reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)?.is_some() {
+            return Err(der::Error);
+        }
+        let responder = match tbs_reader.read_any()? {
+            (RESPONDER_BY_NAME, name, _) => ResponderId::ByName(name),
+            (RESPONDER_BY_KEY, hash, _) => ResponderId::ByKey(der::parse(hash, der::OCTET_STRING)?),
+            _ => return Err(der::Error),
+        };
+        if let ResponderId::ByName(name) = responder {
+            der::parse(name, der::SEQUENCE)?;
+        }
+        x509::parse_time(&mut tbs_reader)?;
+        let mut responses = Vec::new();
+        let mut reader = Reader::new(tbs_reader.read(der::SEQUENCE)?);
+        while !reader.is_empty() {
+            responses.push(SingleResponse::parse(reader.read(der::SEQUENCE)?)?);
+        }
+        let mut unhandled_critical_extension = false;
+        if let Some(extensions) = tbs_reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED | 1)? {
+            for extension in crl::parse_extensions(der::parse(extensions, der::SEQUENCE)?)? {
+                match extension.oid {
+                    OID_PKIX_OCSP_NONCE => {}
+                    _ if extension.critical => unhandled_critical_extension = true,
+                    _ => {}
+                }
+            }
+        }
+        tbs_reader.finish()?;
+
+        Ok(Response {
+            tbs,
+            signature_algorithm,
+            signature,
+            responder,
+            certs,
+            responses,
+            unhandled_critical_extension,
+        })
+    }
+
+    // The status the response gives for the certificate, which the issuer issued, if
+    // it can be relied on at the given time.
+    pub fn status(&self, cert: &Certificate, issuer: &Certificate, time: i64) -> Option<OcspStatus> {
+        if self.unhandled_critical_extension || !self.is_signed_for(issuer, time) {
+            return None;
+        }
+        self.responses.iter()
+            .find(|response| response.is_for(cert, issuer))
+            .filter(|response| !response.unhandled_critical_extension && response.is_current(time))
+            .map(|response| response.status)
+    }
+
+    // Whether the response was signed by the issuer, or by a responder the issuer
+    // delegated to that is valid at the given time.
+    fn is_signed_for(&self, issuer: &Certificate, time: i64) -> bool {
+        if self.responder.matches(issuer) {
+            return self.is_signed_by(issuer);
+        }
+        self.certs.iter()
+            .filter_map(|cert| Certificate::parse(cert).ok())
+            .filter(|responder| self.responder.matches(responder))
+            .any(|responder| is_delegated_by(&responder, issuer, time) && self.is_signed_by(&responder))
+    }
+
+    fn is_signed_by(&self, signer: &Certificate) -> bool {
+        signature::verify(&signer.spki, &self.signature_algorithm, self.tbs, self.signature).is_ok()
+    }
+}
+
+// Whether the issuer delegated signing responses about the certificates it issued to
+// the responder, which must be valid at the given time.
+fn is_delegated_by(responder: &Certificate, issuer: &Certificate, time: i64) -> bool {
+    if !x509::same_name(responder.issuer, issuer.subject) || responder.unhandled_critical_extension {
+        return false;
+    }
+    if responder.not_before > time || responder.not_after < time {
+        return false;
+    }
+    if responder.key_usage.is_some_and(|usage| usage & x509::KEY_USAGE_DIGITAL_SIGNATURE == 0) {
+        return false;
+    }
+    // Unlike for other purposes, anyExtendedKeyUsage doesn't allow signing responses.
+    let delegated = responder.extended_key_usage.as_ref()
+        .is_some_and(|purposes| purposes.contains(&OID_KP_OCSP_SIGNING));
+    delegated && signature::verify(&issuer.spki, &responder.signature_algorithm, responder.tbs, responder.signature).is_ok()
+}
+
+
+// Returns the status that a stapled response gives for the leaf, which the issuer
+// issued, if the response can be parsed and relied on at the given time.
+pub(crate) fn leaf_status(response: &[u8], leaf: &Certificate, issuer: &Certificate, time: i64) -> Option<OcspStatus> {
+    Response::parse(response).ok()?.status(leaf, issuer, time)
+}
+
+
+#[cfg(test)]
+mod test {
+    use crl::RevocationReason;
+    use ocsp::{leaf_status, OcspStatus, Response};
+    use test::test_pki_chain;
+    use x509::Certificate;
+
+    // 2027-01-15, when all but the stale response are current.
+    const TIME: i64 = 1_800_000_000;
+
+    fn status(response: &[u8]) -> Option<OcspStatus> {
+        let chain = test_pki_chain();
+        let leaf = Certificate::parse(chain[0]).unwrap();
+        let issuer = Certificate::parse(chain[1]).unwrap();
+        leaf_status(response, &leaf, &issuer, TIME)
+    }
+
+    #[test]
+    fn parses_responses() {
+        let response = include_bytes!("../fixtures/ocsp/delegated.der");
+        let parsed = Response::parse(response).unwrap();
+        assert_eq!(parsed.certs, vec![&include_bytes!("../fixtures/ocsp/responder.crt")[..]]);
+        assert_eq!(parsed.responses.len(), 1);
+        assert_eq!(parsed.responses[0].serial, Certificate::parse(test_pki_chain()[0]).unwrap().serial);
+        assert_eq!(parsed.responses[0].this_update, 1_799_539_200);
+        assert_eq!(parsed.responses[0].next_update, Some(1_800_403_200));
+        assert!(!parsed.unhandled_critical_extension);
+
+        assert!(Response::parse(&response[..response.len() - 1]).is_err());
+        assert!(Response::parse(include_bytes!("../fixtures/ocsp/try-later.der")).is_err());
+        assert!(Response::parse(test_pki_chain()[0]).is_err());
+    }
+
+    #[test]
+    fn reports_the_status_of_the_leaf() {
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/good.der")), Some(OcspStatus::Good));
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/good-sha256.der")), Some(OcspStatus::Good));
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/revoked.der")),
+                   Some(OcspStatus::Revoked(Some(RevocationReason::KeyCompromise))));
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/unknown.der")), Some(OcspStatus::Unknown));
+        // The responder's name is in upper case and uses PrintableStrings.
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/good-reencoded-name.der")), Some(OcspStatus::Good));
+    }
+
+    #[test]
+    fn accepts_delegated_responders() {
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/delegated.der")), Some(OcspStatus::Revoked(None)));
+        // This responder's certificate names the issuer differently.
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/delegated-reencoded.der")), Some(OcspStatus::Revoked(None)));
+        // A certificate from the issuer that isn't for signing responses isn't enough.
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/undelegated.der")), None);
+    }
+
+    #[test]
+    fn ignores_unusable_responses() {
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/stale.der")), None);
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/forged.der")), None);
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/other-cert.der")), None);
+        assert_eq!(status(include_bytes!("../fixtures/ocsp/try-later.der")), None);
+        assert_eq!(status(b""), None);
+
+        // A response is only about the certificates its signer issued.
+        let chain = test_pki_chain();
+        let leaf = Certificate::parse(chain[0]).unwrap();
+        assert_eq!(leaf_status(include_bytes!("../fixtures/ocsp/good.der"), &leaf, &leaf, TIME), None);
+    }
+}
@@ -1,58 +1,185 @@
-use core_foundation::base::OSStatus;
+use std::os::raw::{c_ulong, c_void};
+
+use core_foundation::base::{kCFAllocatorDefault, CFAllocatorRef, CFRelease, OSStatus, TCFType};
+use core_foundation::data::CFData;
+use core_foundation::date::CFAbsoluteTime;
 use security_framework::certificate::SecCertificate;
 use security_framework::policy::SecPolicy;
 use security_framework::secure_transport::ProtocolSide;
 use security_framework::trust::{SecTrust, TrustResult};
 
-use ValidationResult;
+use chain::VerifiedChain;
+use error::{ErrorKind, ValidationError};
+use verifier::{KeyPurpose, Policy, RevocationMode};
+use TrustAnchors;
 
-pub fn validate_cert_chain(encoded_certs: &[&[u8]], hostname: &str) -> ValidationResult {
+// Validates the chain against the policy. Security.framework doesn't tell us why it
+// rejected a chain, so neither can we.
+pub fn verify_chain(encoded_certs: &[&[u8]], policy: &Policy) -> Result<VerifiedChain, ValidationError> {
     let mut certs = Vec::new();
-    for encoded_cert in encoded_certs {
+    for (index, encoded_cert) in encoded_certs.iter().enumerate() {
         let cert = SecCertificate::from_der(encoded_cert);
         match cert {
             Ok(cert) => certs.push(cert),
             // This is remarkably difficult to hit: OS X mostly parses the cert
             // lazily. Still possible though.
-            Err(_) => return ValidationResult::MalformedCertificateInChain,
+            Err(_) => return Err(ValidationError::at(ErrorKind::MalformedCertificateInChain, index)),
         };
     }
+    if certs.is_empty() {
+        return Err(ErrorKind::MalformedCertificateInChain.into());
+    }
 
-    let ssl_policy = SecPolicy::create_ssl(ProtocolSide::Client, Some(hostname));
-    let trust = match SecTrust::create_with_certificates(&certs[..], &[ssl_policy]) {
+    let mut policies = vec![purpose_policy(&policy.purpose)];
+    policies.extend(revocation_policy(policy.revocation));
+    let mut trust = match SecTrust::create_with_certificates(&certs[..], &policies) {
         Ok(trust) => trust,
-        Err(status) => return os_status_to_validation_result(status.code())
+        Err(status) => return Err(os_status_to_error_kind(status.code()).into())
     };
 
+    let (anchors, only) = match policy.anchors {
+        TrustAnchors::System => (None, false),
+        TrustAnchors::Only(anchors) => (Some(anchors), true),
+        TrustAnchors::SystemAnd(anchors) => (Some(anchors), false),
+    };
+    if let Some(anchors) = anchors {
+        // As elsewhere, an anchor we can't parse can never be used, so just skip it.
+        let anchors = anchors.iter()
+                             .filter_map(|anchor| SecCertificate::from_der(anchor).ok())
+                             .collect::<Vec<_>>();
+        let anchored = trust.set_anchor_certificates(&anchors)
+                            .and_then(|_| trust.set_trust_anchor_certificates_only(only));
+        if let Err(status) = anchored {
+            return Err(os_status_to_error_kind(status.code()).into());
+        }
+    }
+
+    if policy.time.is_some() {
+        set_verify_date(&trust, policy.unix_time())?;
+    }
+    if let Some(response) = policy.ocsp_response {
+        set_ocsp_response(&trust, response)?;
+    }
+
     // Errors here are really unexpected.
     match trust.evaluate() {
-        Ok(result) => trust_result_to_validation_result(result),
-        Err(status) => os_status_to_validation_result(status.code()),
+        Ok(result) => trust_result_to_result(result)?,
+        Err(status) => return Err(os_status_to_error_kind(status.code()).into()),
     }
+
+    // Once evaluated, the trust holds the chain it built, ending at the anchor.
+    let certificates = (0..trust.certificate_count()).filter_map(|index| trust.certificate_at_index(index))
+                                                     .map(|cert| cert.to_der())
+                                                     .collect();
+    VerifiedChain::new(certificates).ok_or_else(|| ErrorKind::ErrorDuringValidation.into())
 }
 
 
-// Convert a TrustResult to a ValidationResult.
-fn trust_result_to_validation_result(trust_result: TrustResult) -> ValidationResult {
+// The Security.framework policy for the purpose. For TLS, that's the SSL policy for
+// the certificates of the given side of the connection: we check the hostname
+// ourselves, so it isn't given one. Anything else gets the basic X.509 policy, and
+// the verifier checks the leaf's purpose.
+fn purpose_policy(purpose: &KeyPurpose) -> SecPolicy {
+    match *purpose {
+        KeyPurpose::ServerAuth => SecPolicy::create_ssl(ProtocolSide::Server, None),
+        KeyPurpose::ClientAuth => SecPolicy::create_ssl(ProtocolSide::Client, None),
+        _ => unsafe { SecPolicy::wrap_under_create_rule(SecPolicyCreateBasicX509() as _) },
+    }
+}
+
+
+// The Security.framework policy for checking revocation in the given mode, if it is
+// to be checked at all. Each certificate is checked by OCSP or against CRLs, whichever
+// it supports, and only with `Required` must one of them say it's good.
+fn revocation_policy(revocation: RevocationMode) -> Option<SecPolicy> {
+    let flags = match revocation {
+        RevocationMode::Disabled => return None,
+        RevocationMode::BestEffort => kSecRevocationUseAnyAvailableMethod,
+        RevocationMode::Required => kSecRevocationUseAnyAvailableMethod | kSecRevocationRequirePositiveResponse,
+    };
+    Some(unsafe { SecPolicy::wrap_under_create_rule(SecPolicyCreateRevocation(flags) as _) })
+}
+
+
+// Tells the trust to evaluate the chain as though it were the given time, in
+// seconds since the Unix epoch.
+fn set_verify_date(trust: &SecTrust, unix_time: i64) -> Result<(), ValidationError> {
+    unsafe {
+        let date = CFDateCreate(kCFAllocatorDefault, (unix_time - CF_ABSOLUTE_TIME_EPOCH) as CFAbsoluteTime);
+        if date.is_null() {
+            return Err(ErrorKind::ErrorDuringValidation.into());
+        }
+        let status = SecTrustSetVerifyDate(trust.as_concrete_TypeRef() as *mut c_void, date);
+        CFRelease(date);
+        match status {
+            0 => Ok(()),
+            status => Err(os_status_to_error_kind(status).into()),
+        }
+    }
+}
+
+// Gives the trust the stapled OCSP response, so that the revocation policy checks the
+// leaf with it rather than asking the responder. The rest of the chain is checked as
+// ever.
+fn set_ocsp_response(trust: &SecTrust, response: &[u8]) -> Result<(), ValidationError> {
+    let data = CFData::from_buffer(response);
+    let status = unsafe {
+        SecTrustSetOCSPResponse(trust.as_concrete_TypeRef() as *mut c_void, data.as_concrete_TypeRef() as *const c_void)
+    };
+    match status {
+        0 => Ok(()),
+        status => Err(os_status_to_error_kind(status).into()),
+    }
+}
+
+
+// Convert a TrustResult to the outcome of validation.
+fn trust_result_to_result(trust_result: TrustResult) -> Result<(), ValidationError> {
     match trust_result {
-        TrustResult::Proceed | TrustResult::Unspecified => ValidationResult::Trusted,
-        _ => ValidationResult::NotTrusted,
+        TrustResult::Proceed | TrustResult::Unspecified => Ok(()),
+        _ => Err(ErrorKind::NotTrusted.into()),
     }
 }
 
 
-// Convert OSStatus codes to validation results. Used to handle errors.
+// Convert OSStatus codes to the kind of error. Used to handle errors.
 #[allow(non_upper_case_globals)]
-fn os_status_to_validation_result(status: OSStatus) -> ValidationResult {
+fn os_status_to_error_kind(status: OSStatus) -> ErrorKind {
     match status {
         errSecNotAvailable | errSecNoSecurityModule | errSecNoPolicyModule =>
-            ValidationResult::MissingFunctionality,
-        errSecAuthFailed => ValidationResult::UserAuthenticationRequired,
-        _ => ValidationResult::ErrorDuringValidation,
+            ErrorKind::MissingFunctionality,
+        errSecAuthFailed => ErrorKind::UserAuthenticationRequired,
+        _ => ErrorKind::ErrorDuringValidation,
     }
 }
 
 
+// Neither rust-core-foundation nor rust-security-framework lets us set the time a
+// chain is evaluated at or its stapled OCSP response, or make the basic X.509 and
+// revocation policies, so declare the functions we need for them here.
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFDateCreate(allocator: CFAllocatorRef, at: CFAbsoluteTime) -> *const c_void;
+}
+
+#[link(name = "Security", kind = "framework")]
+extern "C" {
+    fn SecTrustSetVerifyDate(trust: *mut c_void, verify_date: *const c_void) -> OSStatus;
+    fn SecTrustSetOCSPResponse(trust: *mut c_void, response_data: *const c_void) -> OSStatus;
+    fn SecPolicyCreateBasicX509() -> *const c_void;
+    fn SecPolicyCreateRevocation(revocation_flags: c_ulong) -> *const c_void;
+}
+
+// Flags for SecPolicyCreateRevocation.
+#[allow(non_upper_case_globals)]
+const kSecRevocationUseAnyAvailableMethod: c_ulong = 3;
+#[allow(non_upper_case_globals)]
+const kSecRevocationRequirePositiveResponse: c_ulong = 1 << 3;
+
+// Core Foundation counts time from the start of 2001, in seconds since the Unix epoch.
+const CF_ABSOLUTE_TIME_EPOCH: i64 = 978_307_200;
+
+
 // Define some error constants, because rust-security-framework doesn't.
 #[allow(non_upper_case_globals)]
 const errSecNotAvailable: OSStatus = -25291;
@@ -66,15 +193,35 @@ const errSecAuthFailed: OSStatus = -25293;
 
 #[cfg(test)]
 mod test {
-    use osx::validate_cert_chain;
-    use test::{expired_chain, certifi_chain, self_signed_chain};
+    use chain::VerifiedChain;
+    use error::{ErrorKind, ValidationError};
+    use osx::verify_chain;
+    use test::{expired_chain, certifi_chain, certifi_time, self_signed_chain, test_pki_chain, test_pki_root};
+    use verifier::{KeyPurpose, Policy, RevocationMode, Verifier};
+    use TrustAnchors;
     use ValidationResult;
 
+    fn verify_cert_chain(encoded_certs: &[&[u8]], hostname: &str, anchors: TrustAnchors) -> Result<VerifiedChain, ValidationError> {
+        Verifier::builder().trust_anchors(anchors).build().verify_with(encoded_certs, hostname, verify_chain)
+    }
+
+    fn validate_cert_chain(encoded_certs: &[&[u8]], hostname: &str) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::System).into()
+    }
+
+    fn validate_cert_chain_with_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::Only(anchors)).into()
+    }
+
+    fn validate_cert_chain_with_additional_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::SystemAnd(anchors)).into()
+    }
+
     #[test]
     fn can_validate_good_chain() {
         let chain = certifi_chain();
-        let valid = validate_cert_chain(&chain, "certifi.io");
-        assert_eq!(valid, ValidationResult::Trusted);
+        let policy = Policy { time: Some(certifi_time()), ..Policy::new(TrustAnchors::System) };
+        assert!(verify_chain(&chain, &policy).is_ok());
     }
 
     #[test]
@@ -118,17 +265,82 @@ mod test {
     #[test]
     fn test_fails_on_invalid_asn1() {
         // We need to mutate the first byte of the original cert.
-        let mut chain = certifi_chain();
+        let chain = certifi_chain();
         let mut first_chain = chain[0].to_vec();
         first_chain[0] = 0xff;
         let mut chain_builder = vec![first_chain.as_slice()];
         chain_builder.append(&mut chain[1..].to_vec());
         let new_chain = chain_builder.as_slice();
 
-        let valid = validate_cert_chain(&new_chain, "certifi.io");
+        let valid = validate_cert_chain(new_chain, "certifi.io");
         assert!(
             (valid == ValidationResult::MalformedCertificateInChain) ||
             (valid == ValidationResult::NotTrusted)
         );
     }
+
+    #[test]
+    fn can_validate_with_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn returns_the_verified_chain() {
+        let chain = test_pki_chain();
+        let verified = verify_cert_chain(&chain, "certitude.test", TrustAnchors::Only(&[test_pki_root()])).unwrap();
+        assert_eq!(verified.certificates(), &[chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]);
+    }
+
+    #[test]
+    fn applies_the_policy() {
+        let chain = test_pki_chain();
+        let anchors = [test_pki_root()];
+        assert!(verify_chain(&chain, &Policy::new(TrustAnchors::Only(&anchors))).is_ok());
+
+        // The test leaf is only for TLS servers.
+        let policy = Policy { purpose: KeyPurpose::ClientAuth, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert_eq!(verify_chain(&chain, &policy), Err(ErrorKind::NotTrusted.into()));
+
+        let leaf = include_bytes!("../fixtures/key-purpose/client-auth.crt");
+        assert!(verify_chain(&[&leaf[..], chain[1]], &policy).is_ok());
+        let leaf = include_bytes!("../fixtures/key-purpose/code-signing.crt");
+        let policy = Policy { purpose: KeyPurpose::CodeSigning, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert!(verify_chain(&[&leaf[..], chain[1]], &policy).is_ok());
+
+        // The test PKI says nowhere to check revocation, which is only acceptable on a
+        // best effort.
+        let policy = Policy { revocation: RevocationMode::BestEffort, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert!(verify_chain(&chain, &policy).is_ok());
+        let policy = Policy { revocation: RevocationMode::Required, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert_eq!(verify_chain(&chain, &policy), Err(ErrorKind::NotTrusted.into()));
+    }
+
+    #[test]
+    fn fails_without_matching_anchor() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[self_signed_chain()[0]]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn checks_hostname_with_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "lukasa.co.uk", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn can_validate_with_additional_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "lukasa.co.uk", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
 }
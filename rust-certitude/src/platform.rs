@@ -1,21 +1,36 @@
 // Provides the abstraction layer: calls into the appropriate platform-native functions.
+use chain::VerifiedChain;
+use error::ValidationError;
+use verifier::{Policy, Verifier};
+use TrustAnchors;
 use ValidationResult;
 
-#[cfg(target_os = "macos")]
-use osx::validate_cert_chain as backend;
-#[cfg(windows)]
-use windows::validate_cert_chain as backend;
+#[cfg(all(target_os = "macos", not(feature = "portable")))]
+use osx as backend;
+#[cfg(all(windows, not(feature = "portable")))]
+use windows as backend;
+#[cfg(all(target_os = "linux", not(feature = "portable")))]
+use linux as backend;
+#[cfg(feature = "portable")]
+use portable as backend;
 
 /// Validate a chain of certificates.
 ///
 /// Given a chain of DER-encoded X.509 certificates and the hostname that you're
 /// contacting, validates that the system considers that certificate chain valid for
-/// the connection.
+/// the connection. The hostname is matched against the leaf certificate by certitude
+/// itself, as `verify_hostname` describes, so that it is checked the same way on
+/// every platform.
 ///
 /// The `encoded_certs` should be in order of specificity: the "leaf" certificate first,
-/// then each intermediate certificate in order. For the intermediate certificates, order
-/// *may not* be important, but it is *extremely important* that the leaf come first.
-/// If at all possible, preserve the order.
+/// then the intermediate certificates. It is *extremely important* that the leaf come
+/// first, but the intermediates may come in any order, and may include duplicates and
+/// certificates that aren't needed: a path to a trust anchor is searched for among them.
+/// The platforms each search in their own way. The portable validator tries every
+/// issuer of each certificate, including cross-signed ones, backtracking from any that
+/// lead nowhere, so it finds a valid path of up to eight intermediates whenever there
+/// is one, giving up only after checking a hundred signatures. Missing intermediates
+/// aren't fetched, unless a `Verifier` is given an `IssuerFetcher` to fetch them.
 ///
 /// # Examples
 ///
@@ -29,6 +44,114 @@ use windows::validate_cert_chain as backend;
 /// }
 /// ```
 pub fn validate_cert_chain(encoded_certs: &[&[u8]], hostname: &str) -> ValidationResult {
-    backend(encoded_certs, hostname)
+    verify_cert_chain(encoded_certs, hostname, TrustAnchors::System).into()
 }
 
+
+
+/// Validate a chain of certificates against a specific set of trust anchors.
+///
+/// This behaves exactly like `validate_cert_chain`, except that the certificate chain
+/// must lead to one of the given DER-encoded `anchors`: the system's own trust anchors
+/// are not used at all. This is useful for validating certificates issued by a private
+/// PKI without having to install its root certificate system-wide.
+///
+/// Anchors that cannot be parsed are ignored. If none of the anchors can be used, no
+/// certificate chain will be trusted.
+///
+/// # Examples
+///
+/// ```
+/// match validate_cert_chain_with_anchors(certs, "service.internal", &[internal_root]) {
+///     ValidationResult::Trusted => Ok("success!"),
+///     ValidationResult::NotTrusted => Ok("not issued by our PKI!"),
+///     _ => Err("an internal error occurred!"),
+/// }
+/// ```
+pub fn validate_cert_chain_with_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+    verify_cert_chain(encoded_certs, hostname, TrustAnchors::Only(anchors)).into()
+}
+
+
+/// Validate a chain of certificates against the system's trust anchors and some
+/// additional ones.
+///
+/// This behaves exactly like `validate_cert_chain`, except that a certificate chain
+/// leading to one of the given DER-encoded `anchors` is also trusted. This is useful
+/// when some extra certificate authority must be accepted alongside the public ones:
+/// for example, that of a TLS-inspecting corporate proxy.
+///
+/// Anchors that cannot be parsed are ignored.
+///
+/// # Examples
+///
+/// ```
+/// match validate_cert_chain_with_additional_anchors(certs, "example.com", &[proxy_root]) {
+///     ValidationResult::Trusted => Ok("success!"),
+///     ValidationResult::NotTrusted => Ok("man in the middle!"),
+///     _ => Err("an internal error occurred!"),
+/// }
+/// ```
+pub fn validate_cert_chain_with_additional_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+    verify_cert_chain(encoded_certs, hostname, TrustAnchors::SystemAnd(anchors)).into()
+}
+
+
+/// Validate a chain of certificates, returning the chain that was built or reporting
+/// why it is not trusted.
+///
+/// This is the same validation as `validate_cert_chain` and its variants, with the
+/// trust anchors to use given by `anchors`. For more control over the policy, use a
+/// `Verifier`. On success, the `VerifiedChain` holds the
+/// path the platform built from the leaf to a trust anchor. Failures are reported as
+/// a `ValidationError`, which says what was wrong with the chain and, where possible,
+/// which certificate was at fault.
+///
+/// How much detail is available depends on the platform: OS X, in particular, does
+/// not say why it rejected a chain, so every rejection is reported there as
+/// `ErrorKind::NotTrusted`.
+///
+/// # Examples
+///
+/// ```
+/// match verify_cert_chain(certs, "example.com", TrustAnchors::System) {
+///     Ok(chain) => println!("trusted through {} certificates", chain.certificates().len()),
+///     Err(e) => println!("not trusted: {}", e),
+/// }
+/// ```
+pub fn verify_cert_chain(encoded_certs: &[&[u8]], hostname: &str, anchors: TrustAnchors) -> Result<VerifiedChain, ValidationError> {
+    Verifier::builder().trust_anchors(anchors).build().verify(encoded_certs, hostname)
+}
+
+
+/// Validate a TLS client's chain of certificates, as a server does, returning the
+/// chain the platform built or reporting why it is not trusted.
+///
+/// This is the same as `verify_cert_chain`, but the leaf must be usable by a TLS
+/// client rather than a server, and there is no hostname to check. To require the
+/// client to have a particular name, such as a SPIFFE ID, use a `Verifier` made with
+/// `Verifier::client_builder`.
+///
+/// # Examples
+///
+/// ```
+/// match verify_client_cert_chain(certs, TrustAnchors::Only(&[client_ca])) {
+///     Ok(chain) => println!("client trusted through {}", chain.certificates().len()),
+///     Err(e) => println!("refusing client: {}", e),
+/// }
+/// ```
+pub fn verify_client_cert_chain(encoded_certs: &[&[u8]], anchors: TrustAnchors) -> Result<VerifiedChain, ValidationError> {
+    Verifier::client_builder().trust_anchors(anchors).build().verify_client(encoded_certs)
+}
+
+
+// Whether the platform's backend says when a chain was rejected for want of an
+// issuer. Security.framework doesn't say why it rejects a chain at all, so there a
+// missing issuer is only ever reported as `ErrorKind::NotTrusted`.
+pub(crate) const REPORTS_UNKNOWN_ISSUER: bool = !cfg!(all(target_os = "macos", not(feature = "portable")));
+
+
+// Validates the chain against the policy using the platform's backend.
+pub(crate) fn verify_chain(encoded_certs: &[&[u8]], policy: &Policy) -> Result<VerifiedChain, ValidationError> {
+    backend::verify_chain(encoded_certs, policy)
+}
@@ -0,0 +1,516 @@
+//! A portable certificate validator, written entirely in Rust.
+//!
+//! The platform backends hand certificate validation to the operating system,
+//! which means the same chain can get different answers on different systems.
+//! This module implements RFC 5280 path validation directly, so that the answer
+//! is the same everywhere. It can be used directly, or selected as the backend
+//! for `validate_cert_chain` on every platform by enabling the `portable` feature.
+//!
+//! The portable validator takes its trust anchors from `TrustStore::system`, unless
+//! the caller supplies their own.
+
+use chain::VerifiedChain;
+use error::{ErrorKind, ValidationError};
+use issuers;
+use signature;
+use verifier::{self, KeyPurpose, Policy, RevocationMode, Verifier};
+use x509::{self, Certificate};
+use TrustAnchors;
+use TrustStore;
+use ValidationResult;
+
+// The longest chain of intermediates we're prepared to build.
+const MAX_INTERMEDIATES: usize = 8;
+
+// The most signatures we're prepared to check while building a path.
+const MAX_SIGNATURE_CHECKS: usize = 100;
+
+
+/// Validate a chain of certificates using the portable validator.
+///
+/// This has exactly the same interface as `certitude::validate_cert_chain`, but
+/// always uses the portable validator rather than the platform's own.
+pub fn validate_cert_chain(encoded_certs: &[&[u8]], hostname: &str) -> ValidationResult {
+    verify_cert_chain(encoded_certs, hostname, TrustAnchors::System).into()
+}
+
+
+/// Validate a chain of certificates against the given trust anchors using the
+/// portable validator.
+///
+/// This has exactly the same interface as `certitude::validate_cert_chain_with_anchors`,
+/// but always uses the portable validator rather than the platform's own.
+pub fn validate_cert_chain_with_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+    verify_cert_chain(encoded_certs, hostname, TrustAnchors::Only(anchors)).into()
+}
+
+
+/// Validate a chain of certificates against the system's trust anchors and the
+/// given ones using the portable validator.
+///
+/// This has exactly the same interface as
+/// `certitude::validate_cert_chain_with_additional_anchors`, but always uses the
+/// portable validator rather than the platform's own.
+pub fn validate_cert_chain_with_additional_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+    verify_cert_chain(encoded_certs, hostname, TrustAnchors::SystemAnd(anchors)).into()
+}
+
+
+/// Validate a chain of certificates using the portable validator, returning the
+/// chain it built or reporting why it is not trusted.
+///
+/// This has exactly the same interface as `certitude::verify_cert_chain`, but
+/// always uses the portable validator rather than the platform's own.
+pub fn verify_cert_chain(encoded_certs: &[&[u8]], hostname: &str, anchors: TrustAnchors) -> Result<VerifiedChain, ValidationError> {
+    Verifier::builder().trust_anchors(anchors).build().verify_with(encoded_certs, hostname, verify_chain)
+}
+
+/// Validate a TLS client's chain of certificates using the portable validator,
+/// returning the chain it built or reporting why it is not trusted.
+///
+/// This has exactly the same interface as `certitude::verify_client_cert_chain`, but
+/// always uses the portable validator rather than the platform's own.
+pub fn verify_client_cert_chain(encoded_certs: &[&[u8]], anchors: TrustAnchors) -> Result<VerifiedChain, ValidationError> {
+    Verifier::client_builder().trust_anchors(anchors).build().verify_client_with(encoded_certs, verify_chain)
+}
+
+
+// Validates the chain against the policy. This is the entry point used when the
+// portable validator is the backend for a `Verifier`.
+pub(crate) fn verify_chain(encoded_certs: &[&[u8]], policy: &Policy) -> Result<VerifiedChain, ValidationError> {
+    // We have nowhere to get revocation information from, so the status of every
+    // certificate is unknown, which is only acceptable on a best effort.
+    if policy.revocation == RevocationMode::Required {
+        return Err(ErrorKind::MissingFunctionality.into());
+    }
+
+    let caller_store;
+    let (store, additional_anchors) = match policy.anchors {
+        TrustAnchors::System => (policy.system_store.get(), &[][..]),
+        TrustAnchors::Only(anchors) => {
+            caller_store = TrustStore::from_anchors(anchors);
+            (Some(&caller_store), &[][..])
+        }
+        // The system's store is shared, so the caller's anchors are kept apart from it.
+        TrustAnchors::SystemAnd(anchors) => (policy.system_store.get(), anchors),
+    };
+    match store {
+        Some(store) => verify(encoded_certs, &policy.purpose, store, additional_anchors, policy.unix_time()),
+        None => Err(ErrorKind::UnableToBuildTrustStore.into()),
+    }
+}
+
+
+// Validates the chain against the trust anchors in the store and the additional
+// DER-encoded ones, at the given time in seconds since the Unix epoch.
+fn verify(encoded_certs: &[&[u8]],
+          purpose: &KeyPurpose,
+          store: &TrustStore,
+          additional_anchors: &[&[u8]],
+          time: i64) -> Result<VerifiedChain, ValidationError> {
+    let mut certs = Vec::new();
+    for (index, encoded_cert) in encoded_certs.iter().enumerate() {
+        match Certificate::parse(encoded_cert) {
+            Ok(cert) => certs.push(cert),
+            Err(_) => return Err(ValidationError::at(ErrorKind::MalformedCertificateInChain, index)),
+        }
+    }
+    if certs.is_empty() {
+        return Err(ErrorKind::MalformedCertificateInChain.into());
+    }
+
+    // An anchor we can't parse can never be used, so just skip it.
+    let encoded_anchors = store.anchors();
+    let anchors = encoded_anchors.iter()
+                                 .chain(additional_anchors)
+                                 .filter_map(|anchor| Certificate::parse(anchor).ok())
+                                 .collect::<Vec<_>>();
+
+    let (leaf, intermediates) = certs.split_first().unwrap();
+    check_leaf(leaf, purpose, time).map_err(|kind| ValidationError::at(kind, 0))?;
+    build_path(leaf, intermediates, &anchors, store, time)
+}
+
+
+// Checks that the leaf is acceptable for the purpose.
+fn check_leaf(leaf: &Certificate, purpose: &KeyPurpose, time: i64) -> Result<(), ErrorKind> {
+    check_validity(leaf, time)?;
+    verifier::check_purpose(leaf, purpose)
+}
+
+
+// Builds a path from the leaf to one of the trust anchors, using the supplied
+// intermediates in whatever order they came, and checks each certificate along the
+// way. `anchors` are the store's anchors, already parsed; any in hashed directories
+// are looked up as we go. Returns the path, ending at the anchor.
+//
+// The search is depth first. From each certificate we first try to finish the path
+// at an anchor that issued it, then try each intermediate that did, backtracking
+// when one leads nowhere: an intermediate cross-signed by an issuer that has expired
+// doesn't stop us finding the path through its other issuer. A certificate with the
+// same subject and key as one already in the path is never added again, so
+// duplicates and cross-signing loops can't send us round in circles.
+//
+// If no path can be built, the error describes the first certificate we found that
+// had the right name to extend the path but couldn't be used: that's usually more
+// helpful than just saying the issuer is unknown.
+fn build_path(leaf: &Certificate,
+              intermediates: &[Certificate],
+              anchors: &[Certificate],
+              store: &TrustStore,
+              time: i64) -> Result<VerifiedChain, ValidationError> {
+    let mut search = PathSearch {
+        intermediates,
+        anchors,
+        store,
+        time,
+        signature_checks: 0,
+        failure: None,
+        unknown_issuer: None,
+    };
+    match search.extend(&mut vec![(leaf, 0)]) {
+        Some(chain) => Ok(chain),
+        None => Err(search.failure
+                          .or(search.unknown_issuer)
+                          .unwrap_or_else(|| ValidationError::at(ErrorKind::UnknownIssuer, 0))),
+    }
+}
+
+
+// The state of a search for a path, as `build_path` describes.
+struct PathSearch<'a> {
+    intermediates: &'a [Certificate<'a>],
+    anchors: &'a [Certificate<'a>],
+    store: &'a TrustStore,
+    time: i64,
+    // How many signatures we've checked, which bounds how long the search can take
+    // however many certificates we're given.
+    signature_checks: usize,
+    // The first candidate issuer that couldn't be used, and the first certificate
+    // that no certificate we have could have issued.
+    failure: Option<ValidationError>,
+    unknown_issuer: Option<ValidationError>,
+}
+
+impl<'a> PathSearch<'a> {
+    // Extends the path, made of certificates and their positions in the chain we
+    // were given, until it ends at an anchor.
+    fn extend(&mut self, path: &mut Vec<(&'a Certificate<'a>, usize)>) -> Option<VerifiedChain> {
+        let (current, current_index) = *path.last().expect("the path always starts at the leaf");
+        let mut issuer_named = false;
+
+        // Prefer finishing the path at an anchor to extending it further.
+        let encoded_hashed_anchors = self.store.hashed_anchors(current.issuer);
+        let hashed_anchors = encoded_hashed_anchors.iter()
+                                                   .filter_map(|anchor| Certificate::parse(anchor).ok())
+                                                   .collect::<Vec<_>>();
+        let anchors = self.anchors;
+        for anchor in anchors.iter().chain(hashed_anchors.iter()).filter(|anchor| x509::same_name(anchor.subject, current.issuer)) {
+            issuer_named = true;
+            if self.signature_checks == MAX_SIGNATURE_CHECKS {
+                return None;
+            }
+            let checked = check_validity(anchor, self.time).map_err(ValidationError::from)
+                                                           .and_then(|_| self.check_signature(current, current_index, anchor));
+            match checked {
+                Ok(()) => return Some(verified_chain(path, anchor)),
+                Err(e) => self.failure = self.failure.or(Some(e)),
+            }
+        }
+
+        // RFC 5280 path length constraints count the non-self-issued intermediates
+        // below a certificate: that's everything in the path so far, apart from the leaf.
+        let below = path[1..].iter().filter(|&&(cert, _)| !cert.is_self_issued()).count();
+        let intermediates = self.intermediates;
+        for (i, candidate) in intermediates.iter().enumerate() {
+            if !x509::same_name(candidate.subject, current.issuer) {
+                continue;
+            }
+            issuer_named = true;
+            if path.len() > MAX_INTERMEDIATES {
+                break;
+            }
+            // Skip certificates already in the path, and copies of ones already tried.
+            let in_path = path.iter().any(|&(cert, _)| {
+                x509::same_name(cert.subject, candidate.subject) && cert.spki.public_key == candidate.spki.public_key
+            });
+            let tried = intermediates[..i].iter().any(|earlier| earlier.encoded == candidate.encoded);
+            if in_path || tried {
+                continue;
+            }
+            if self.signature_checks == MAX_SIGNATURE_CHECKS {
+                return None;
+            }
+
+            let checked = check_issuer(candidate, below, self.time).map_err(|kind| ValidationError::at(kind, i + 1))
+                                                                   .and_then(|_| self.check_signature(current, current_index, candidate));
+            match checked {
+                Ok(()) => {
+                    path.push((candidate, i + 1));
+                    if let Some(chain) = self.extend(path) {
+                        return Some(chain);
+                    }
+                    path.pop();
+                }
+                Err(e) => self.failure = self.failure.or(Some(e)),
+            }
+        }
+
+        if !issuer_named || path.len() > MAX_INTERMEDIATES {
+            let unknown_issuer = ValidationError::at(ErrorKind::UnknownIssuer, current_index);
+            self.unknown_issuer = self.unknown_issuer.or(Some(unknown_issuer));
+        }
+        None
+    }
+
+    fn check_signature(&mut self, cert: &Certificate, index: usize, issuer: &Certificate) -> Result<(), ValidationError> {
+        self.signature_checks += 1;
+        check_signature(cert, index, issuer)
+    }
+}
+
+
+// The chain made by finishing the path at the anchor. A self-signed certificate
+// that is itself an anchor ends the path at itself, so isn't repeated.
+fn verified_chain(path: &[(&Certificate, usize)], anchor: &Certificate) -> VerifiedChain {
+    let mut certificates = path.iter().map(|&(cert, _)| cert.encoded.to_vec()).collect::<Vec<_>>();
+    if certificates.last().map(|last| &last[..]) != Some(anchor.encoded) {
+        certificates.push(anchor.encoded.to_vec());
+    }
+    VerifiedChain::new(certificates).expect("the path always starts at the leaf")
+}
+
+
+// Checks that a certificate may be used to issue other certificates.
+fn check_issuer(issuer: &Certificate, below: usize, time: i64) -> Result<(), ErrorKind> {
+    check_validity(issuer, time)?;
+    issuers::check_issuer(issuer, below)
+}
+
+
+// Checks the properties every certificate in the path must have.
+fn check_validity(cert: &Certificate, time: i64) -> Result<(), ErrorKind> {
+    if time < cert.not_before {
+        return Err(ErrorKind::NotYetValid);
+    }
+    if time > cert.not_after {
+        return Err(ErrorKind::Expired);
+    }
+    if cert.unhandled_critical_extension {
+        return Err(ErrorKind::UnhandledCriticalExtension);
+    }
+    Ok(())
+}
+
+
+// Checks that `cert`, at `index` in the chain, was signed by `issuer`.
+fn check_signature(cert: &Certificate, index: usize, issuer: &Certificate) -> Result<(), ValidationError> {
+    match signature::verify(&issuer.spki, &cert.signature_algorithm, cert.tbs, cert.signature) {
+        Ok(()) => Ok(()),
+        Err(signature::Error::BadSignature) => Err(ValidationError::at(ErrorKind::BadSignature, index)),
+        Err(signature::Error::UnsupportedAlgorithm) => Err(ValidationError::at(ErrorKind::UnsupportedAlgorithm, index)),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use chain::VerifiedChain;
+    use error::{ErrorKind, ValidationError};
+    use portable::{self, validate_cert_chain_with_anchors, validate_cert_chain_with_additional_anchors};
+    use test::{certifi_chain, self_signed_chain, test_pki_chain, test_pki_root};
+    use verifier::{KeyPurpose, Policy, RevocationMode};
+    use x509::days_from_civil;
+    use TrustAnchors;
+    use TrustStore;
+    use ValidationResult;
+
+    fn at(year: u32) -> i64 {
+        days_from_civil(year, 1, 1) * 86400
+    }
+
+    fn verify(encoded_certs: &[&[u8]], store: &TrustStore, time: i64) -> Result<VerifiedChain, ValidationError> {
+        portable::verify(encoded_certs, &KeyPurpose::ServerAuth, store, &[], time)
+    }
+
+    fn test_pki_store() -> TrustStore {
+        TrustStore::from_anchors(&[test_pki_root()])
+    }
+
+    #[test]
+    fn can_validate_good_chain() {
+        let chain = test_pki_chain();
+        let verified = verify(&chain, &test_pki_store(), at(2025)).unwrap();
+        assert_eq!(verified.certificates(), &[chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]);
+    }
+
+    #[test]
+    fn returns_the_path_it_built() {
+        // Certificates that aren't needed are left out, whatever order they came in.
+        let chain = test_pki_chain();
+        let certs = vec![chain[0], self_signed_chain()[0], chain[1]];
+        let verified = verify(&certs, &test_pki_store(), at(2025)).unwrap();
+        assert_eq!(verified.leaf(), chain[0]);
+        assert_eq!(verified.intermediates(), &[chain[1].to_vec()]);
+        assert_eq!(verified.anchor(), test_pki_root());
+    }
+
+    #[test]
+    fn checks_purpose() {
+        // The test leaf is only for TLS servers.
+        let chain = test_pki_chain();
+        let valid = portable::verify(&chain, &KeyPurpose::ClientAuth, &test_pki_store(), &[], at(2025));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::InvalidUsage, 0)));
+    }
+
+    #[test]
+    fn cannot_check_revocation() {
+        let chain = test_pki_chain();
+        let anchors = [test_pki_root()];
+        let policy = Policy { revocation: RevocationMode::BestEffort, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert!(portable::verify_chain(&chain, &policy).is_ok());
+        let policy = Policy { revocation: RevocationMode::Required, ..Policy::new(TrustAnchors::Only(&anchors)) };
+        assert_eq!(portable::verify_chain(&chain, &policy), Err(ErrorKind::MissingFunctionality.into()));
+    }
+
+    #[test]
+    fn fails_on_expired_leaf() {
+        let chain = test_pki_chain();
+        let valid = verify(&chain, &test_pki_store(), at(2046));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::Expired, 0)));
+    }
+
+    #[test]
+    fn fails_on_not_yet_valid_leaf() {
+        let chain = test_pki_chain();
+        let valid = verify(&chain, &test_pki_store(), at(2023));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::NotYetValid, 0)));
+    }
+
+    #[test]
+    fn fails_on_missing_intermediate() {
+        let chain = test_pki_chain();
+        let valid = verify(&chain[..1], &test_pki_store(), at(2025));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::UnknownIssuer, 0)));
+    }
+
+    #[test]
+    fn fails_on_unknown_root() {
+        let chain = test_pki_chain();
+        let valid = verify(&chain, &TrustStore::default(), at(2025));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::UnknownIssuer, 1)));
+    }
+
+    #[test]
+    fn can_validate_against_hashed_directory() {
+        let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/truststore/hashed");
+        let store = TrustStore::from_hashed_directory(directory).unwrap();
+        let chain = test_pki_chain();
+        let verified = verify(&chain, &store, at(2025)).unwrap();
+        assert_eq!(verified.anchor(), test_pki_root());
+    }
+
+    #[test]
+    fn can_validate_with_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn can_validate_with_additional_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn fails_on_bad_cert() {
+        let chain = certifi_chain();
+        let certs = vec![&chain[0][1..50], chain[1], chain[2]];
+        let valid = verify(&certs, &test_pki_store(), at(2016));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::MalformedCertificateInChain, 0)));
+    }
+
+    #[test]
+    fn fails_on_empty_chain() {
+        let valid = verify(&[], &test_pki_store(), at(2025));
+        assert_eq!(valid, Err(ErrorKind::MalformedCertificateInChain.into()));
+    }
+
+    #[test]
+    fn fails_on_bad_signature() {
+        // The intermediate has the right name, but didn't sign the certificate.
+        let chain = test_pki_chain();
+        let leaf = chain[0];
+        let mut tampered = leaf.to_vec();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0x01;
+        let valid = verify(&[&tampered, chain[1]], &test_pki_store(), at(2025));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::BadSignature, 0)));
+    }
+
+    #[test]
+    fn copes_with_shuffled_and_duplicated_certificates() {
+        let chain = test_pki_chain();
+        let unrelated = certifi_chain();
+        let certs = vec![chain[0], unrelated[2], chain[1], unrelated[1], chain[1], test_pki_root(), chain[1]];
+        let verified = verify(&certs, &test_pki_store(), at(2025)).unwrap();
+        assert_eq!(verified.certificates(), &[chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]);
+    }
+
+    #[test]
+    fn backtracks_from_expired_cross_signs() {
+        // The intermediate cross-signed by the bridge CA comes first, and is valid, but
+        // the bridge's own certificate has expired, so only the other path is good.
+        let chain = test_pki_chain();
+        let by_bridge = include_bytes!("../fixtures/cross-signed/intermediate-by-bridge.crt");
+        let bridge = include_bytes!("../fixtures/cross-signed/expired-bridge.crt");
+        let certs = vec![chain[0], by_bridge, bridge, chain[1]];
+        let verified = verify(&certs, &test_pki_store(), at(2025)).unwrap();
+        assert_eq!(verified.certificates(), &[chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]);
+
+        // Without it, the expired bridge is the reason there's no path.
+        let valid = verify(&certs[..3], &test_pki_store(), at(2025));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::Expired, 2)));
+    }
+
+    #[test]
+    fn chains_names_encoded_differently() {
+        // Each certificate names its issuer in upper case and with PrintableStrings,
+        // where the issuer's own certificate has UTF8Strings.
+        let leaf = include_bytes!("../fixtures/reencoded-names/leaf.crt");
+        let intermediate = include_bytes!("../fixtures/reencoded-names/intermediate.crt");
+        let verified = verify(&[leaf, intermediate], &test_pki_store(), at(2025)).unwrap();
+        assert_eq!(verified.certificates(), &[leaf.to_vec(), intermediate.to_vec(), test_pki_root().to_vec()]);
+        assert!(verify(&[leaf, test_pki_chain()[1]], &test_pki_store(), at(2025)).is_ok());
+    }
+
+    #[test]
+    fn verifies_each_signature_algorithm() {
+        let chains: [[&[u8]; 2]; 4] = [
+            [include_bytes!("../fixtures/signatures/rsa-pss.crt"), include_bytes!("../fixtures/signatures/rsa-pss-ca.crt")],
+            [include_bytes!("../fixtures/signatures/p521.crt"), include_bytes!("../fixtures/signatures/p521-ca.crt")],
+            [include_bytes!("../fixtures/signatures/ed25519.crt"), include_bytes!("../fixtures/signatures/ed25519-ca.crt")],
+            [include_bytes!("../fixtures/signatures/ed448.crt"), include_bytes!("../fixtures/signatures/ed448-ca.crt")],
+        ];
+        for chain in &chains {
+            let verified = verify(chain, &test_pki_store(), at(2025)).unwrap();
+            assert_eq!(verified.anchor(), test_pki_root());
+        }
+    }
+
+    #[test]
+    fn fails_on_unsupported_signature_algorithm() {
+        let chain: [&[u8]; 2] = [include_bytes!("../fixtures/signatures/secp256k1.crt"),
+                                 include_bytes!("../fixtures/signatures/secp256k1-ca.crt")];
+        let valid = verify(&chain, &test_pki_store(), at(2025));
+        assert_eq!(valid, Err(ValidationError::at(ErrorKind::UnsupportedAlgorithm, 0)));
+    }
+}
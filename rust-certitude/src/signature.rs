@@ -0,0 +1,662 @@
+// Verification of the signatures on certificates.
+//
+// The cryptography itself comes from the RustCrypto and dalek crates: this module is
+// only responsible for working out which algorithm a signature uses and turning the
+// DER-encoded keys and signatures into the forms those crates expect. The exception
+// is Ed448, where the crate we use only provides the curve, so the verification
+// equation from RFC 8032 is here.
+
+use ed25519_dalek;
+use ed448_goldilocks::curve::edwards::{CompressedEdwardsY, ExtendedPoint};
+use ed448_goldilocks::Scalar;
+use p256;
+use p384;
+use p521;
+use rsa::{BigUint, Pkcs1v15Sign, Pss, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use sha3::Shake256;
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+
+use algorithms::{Curve, HashAlgorithm, SignatureAlgorithm};
+use der::{self, Reader};
+use x509::{self, AlgorithmIdentifier, SubjectPublicKeyInfo};
+
+// Public key algorithms.
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+
+// Named curves.
+const OID_SECP192R1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x01];
+const OID_SECP224R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x21];
+const OID_SECP256R1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_SECP384R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_SECP521R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x23];
+
+// Signature algorithms. RSASSA-PSS, Ed25519 and Ed448 use the same identifier for
+// their keys as for their signatures.
+const OID_SHA1_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x05];
+const OID_SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const OID_RSASSA_PSS: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0a];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_ECDSA_WITH_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+const OID_ED448: &[u8] = &[0x2b, 0x65, 0x71];
+
+// Hash algorithms and the mask generation function, for RSASSA-PSS parameters.
+const OID_SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const OID_SHA384: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+const OID_SHA512: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
+const OID_MGF1: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x08];
+
+// The largest RSA modulus we're prepared to work with, in bits.
+const MAX_RSA_MODULUS_BITS: usize = 8192;
+
+// The sizes of Ed448 keys and signatures, in bytes.
+const ED448_KEY_LENGTH: usize = 57;
+const ED448_SIGNATURE_LENGTH: usize = 114;
+
+/// Why a signature could not be verified.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Error {
+    /// The signature is wrong, is malformed, or doesn't suit the key.
+    BadSignature,
+    /// The signature, or the key that made it, uses an algorithm we don't support.
+    UnsupportedAlgorithm,
+}
+
+impl From<der::Error> for Error {
+    fn from(_: der::Error) -> Error {
+        Error::BadSignature
+    }
+}
+
+
+pub fn digest(hash: HashAlgorithm, message: &[u8]) -> Vec<u8> {
+    match hash {
+        HashAlgorithm::Sha1 => Sha1::digest(message).to_vec(),
+        HashAlgorithm::Sha256 => Sha256::digest(message).to_vec(),
+        HashAlgorithm::Sha384 => Sha384::digest(message).to_vec(),
+        HashAlgorithm::Sha512 => Sha512::digest(message).to_vec(),
+    }
+}
+
+
+enum Scheme {
+    RsaPkcs1(HashAlgorithm),
+    // The hash, and the length of the salt in bytes.
+    RsaPss(HashAlgorithm, usize),
+    Ecdsa(HashAlgorithm),
+    Ed25519,
+    Ed448,
+}
+
+
+// Verifies that `signature` is a valid signature over `message`, made using the
+// given algorithm by the private key corresponding to `spki`.
+pub fn verify(spki: &SubjectPublicKeyInfo,
+              algorithm: &AlgorithmIdentifier,
+              message: &[u8],
+              signature: &[u8]) -> Result<(), Error> {
+    match signature_scheme(algorithm)? {
+        Scheme::RsaPkcs1(hash) => verify_rsa_pkcs1(spki, hash, message, signature),
+        Scheme::RsaPss(hash, salt_length) => verify_rsa_pss(spki, hash, salt_length, message, signature),
+        Scheme::Ecdsa(hash) => verify_ecdsa(spki, hash, message, signature),
+        Scheme::Ed25519 => verify_ed25519(spki, message, signature),
+        Scheme::Ed448 => verify_ed448(spki, message, signature),
+    }
+}
+
+
+// The kind of signature an algorithm makes and the hash it uses, if it's one we
+// support. EdDSA signatures don't have a separate hash.
+pub fn signature_algorithm(algorithm: &AlgorithmIdentifier) -> Option<(SignatureAlgorithm, Option<HashAlgorithm>)> {
+    match signature_scheme(algorithm).ok()? {
+        Scheme::RsaPkcs1(hash) => Some((SignatureAlgorithm::RsaPkcs1, Some(hash))),
+        Scheme::RsaPss(hash, _) => Some((SignatureAlgorithm::RsaPss, Some(hash))),
+        Scheme::Ecdsa(hash) => Some((SignatureAlgorithm::Ecdsa, Some(hash))),
+        Scheme::Ed25519 => Some((SignatureAlgorithm::Ed25519, None)),
+        Scheme::Ed448 => Some((SignatureAlgorithm::Ed448, None)),
+    }
+}
+
+
+fn signature_scheme(algorithm: &AlgorithmIdentifier) -> Result<Scheme, Error> {
+    // RFC 4055 requires NULL parameters for the PKCS#1 algorithms, but plenty of
+    // certificates omit them entirely. RFC 5758 and RFC 8410 require the ECDSA and
+    // EdDSA ones to be absent.
+    let null_parameters = match algorithm.parameters {
+        None | Some([der::NULL, 0x00]) => true,
+        Some(_) => false,
+    };
+    let no_parameters = algorithm.parameters.is_none();
+
+    let (scheme, good_parameters) = match algorithm.oid {
+        OID_SHA1_WITH_RSA => (Scheme::RsaPkcs1(HashAlgorithm::Sha1), null_parameters),
+        OID_SHA256_WITH_RSA => (Scheme::RsaPkcs1(HashAlgorithm::Sha256), null_parameters),
+        OID_SHA384_WITH_RSA => (Scheme::RsaPkcs1(HashAlgorithm::Sha384), null_parameters),
+        OID_SHA512_WITH_RSA => (Scheme::RsaPkcs1(HashAlgorithm::Sha512), null_parameters),
+        OID_RSASSA_PSS => return rsa_pss_scheme(algorithm.parameters),
+        OID_ECDSA_WITH_SHA256 => (Scheme::Ecdsa(HashAlgorithm::Sha256), no_parameters),
+        OID_ECDSA_WITH_SHA384 => (Scheme::Ecdsa(HashAlgorithm::Sha384), no_parameters),
+        OID_ECDSA_WITH_SHA512 => (Scheme::Ecdsa(HashAlgorithm::Sha512), no_parameters),
+        OID_ED25519 => (Scheme::Ed25519, no_parameters),
+        OID_ED448 => (Scheme::Ed448, no_parameters),
+        _ => return Err(Error::UnsupportedAlgorithm),
+    };
+    if good_parameters { Ok(scheme) } else { Err(Error::BadSignature) }
+}
+
+
+// Works out the RSASSA-PSS scheme from its parameters, which RFC 4055 defines as:
+//
+//     RSASSA-PSS-params ::= SEQUENCE {
+//         hashAlgorithm     [0] HashAlgorithm DEFAULT sha1,
+//         maskGenAlgorithm  [1] MaskGenAlgorithm DEFAULT mgf1SHA1,
+//         saltLength        [2] INTEGER DEFAULT 20,
+//         trailerField      [3] TrailerField DEFAULT trailerFieldBC }
+//
+// The only mask generation function is MGF1, and we can only verify signatures that
+// use the same hash for it as for the message, which is all anyone does.
+fn rsa_pss_scheme(parameters: Option<&[u8]>) -> Result<Scheme, Error> {
+    let parameters = parameters.ok_or(Error::BadSignature)?;
+    let mut reader = Reader::new(der::parse(parameters, der::SEQUENCE)?);
+    let tag = |number| der::CONTEXT_SPECIFIC | der::CONSTRUCTED | number;
+
+    let hash = match reader.read_optional(tag(0))? {
+        Some(hash) => hash_algorithm(hash)?,
+        None => HashAlgorithm::Sha1,
+    };
+    let mask_hash = match reader.read_optional(tag(1))? {
+        Some(mask) => {
+            let mut mask = Reader::new(mask);
+            let algorithm = x509::parse_algorithm(&mut mask)?;
+            mask.finish()?;
+            if algorithm.oid != OID_MGF1 {
+                return Err(Error::UnsupportedAlgorithm);
+            }
+            hash_algorithm(algorithm.parameters.ok_or(Error::BadSignature)?)?
+        }
+        None => HashAlgorithm::Sha1,
+    };
+    let salt_length = match reader.read_optional(tag(2))? {
+        Some(salt_length) => {
+            let mut salt_length = Reader::new(salt_length);
+            let length = salt_length.read_small_uint()?;
+            salt_length.finish()?;
+            length as usize
+        }
+        None => 20,
+    };
+    if let Some(trailer) = reader.read_optional(tag(3))? {
+        let mut trailer = Reader::new(trailer);
+        if trailer.read_small_uint()? != 1 {
+            return Err(Error::BadSignature);
+        }
+        trailer.finish()?;
+    }
+    reader.finish()?;
+
+    if mask_hash != hash {
+        return Err(Error::UnsupportedAlgorithm);
+    }
+    Ok(Scheme::RsaPss(hash, salt_length))
+}
+
+// The hash named by an encoded AlgorithmIdentifier, whose parameters may be NULL or
+// absent.
+pub fn hash_algorithm(encoded: &[u8]) -> Result<HashAlgorithm, Error> {
+    let mut reader = Reader::new(encoded);
+    let algorithm = x509::parse_algorithm(&mut reader)?;
+    reader.finish()?;
+    match algorithm.parameters {
+        None | Some([der::NULL, 0x00]) => {}
+        Some(_) => return Err(Error::BadSignature),
+    }
+    match algorithm.oid {
+        OID_SHA1 => Ok(HashAlgorithm::Sha1),
+        OID_SHA256 => Ok(HashAlgorithm::Sha256),
+        OID_SHA384 => Ok(HashAlgorithm::Sha384),
+        OID_SHA512 => Ok(HashAlgorithm::Sha512),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+
+fn verify_rsa_pkcs1(spki: &SubjectPublicKeyInfo, hash: HashAlgorithm, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+    // A key marked as being for RSASSA-PSS may only be used for that.
+    if spki.algorithm.oid != OID_RSA_ENCRYPTION {
+        return Err(Error::BadSignature);
+    }
+    let key = rsa_public_key(spki)?;
+    let scheme = match hash {
+        HashAlgorithm::Sha1 => Pkcs1v15Sign::new::<Sha1>(),
+        HashAlgorithm::Sha256 => Pkcs1v15Sign::new::<Sha256>(),
+        HashAlgorithm::Sha384 => Pkcs1v15Sign::new::<Sha384>(),
+        HashAlgorithm::Sha512 => Pkcs1v15Sign::new::<Sha512>(),
+    };
+    key.verify(scheme, &digest(hash, message), signature).map_err(|_| Error::BadSignature)
+}
+
+
+fn verify_rsa_pss(spki: &SubjectPublicKeyInfo,
+                  hash: HashAlgorithm,
+                  salt_length: usize,
+                  message: &[u8],
+                  signature: &[u8]) -> Result<(), Error> {
+    let key = rsa_public_key(spki)?;
+    let scheme = match hash {
+        HashAlgorithm::Sha1 => Pss::new_with_salt::<Sha1>(salt_length),
+        HashAlgorithm::Sha256 => Pss::new_with_salt::<Sha256>(salt_length),
+        HashAlgorithm::Sha384 => Pss::new_with_salt::<Sha384>(salt_length),
+        HashAlgorithm::Sha512 => Pss::new_with_salt::<Sha512>(salt_length),
+    };
+    key.verify(scheme, &digest(hash, message), signature).map_err(|_| Error::BadSignature)
+}
+
+
+// Whether the key is an RSA key, for any RSA signature scheme. The parameters that
+// RFC 4055 lets an RSASSA-PSS key have, restricting the signatures it makes, are
+// ignored.
+fn is_rsa_key(spki: &SubjectPublicKeyInfo) -> bool {
+    spki.algorithm.oid == OID_RSA_ENCRYPTION || spki.algorithm.oid == OID_RSASSA_PSS
+}
+
+fn rsa_public_key(spki: &SubjectPublicKeyInfo) -> Result<RsaPublicKey, Error> {
+    if !is_rsa_key(spki) {
+        return Err(Error::BadSignature);
+    }
+
+    // RSAPublicKey ::= SEQUENCE { modulus INTEGER, publicExponent INTEGER }
+    let key = der::parse(spki.public_key, der::SEQUENCE)?;
+    let mut reader = Reader::new(key);
+    let modulus = reader.read(der::INTEGER).and_then(der::positive_integer)?;
+    let exponent = reader.read(der::INTEGER).and_then(der::positive_integer)?;
+    reader.finish()?;
+
+    RsaPublicKey::new_with_max_size(BigUint::from_bytes_be(modulus),
+                                    BigUint::from_bytes_be(exponent),
+                                    MAX_RSA_MODULUS_BITS).map_err(|_| Error::BadSignature)
+}
+
+
+// The kinds of public key, as far as what a key may be used for goes.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum KeyType {
+    Rsa,
+    Ec,
+    Other,
+}
+
+pub fn key_type(spki: &SubjectPublicKeyInfo) -> KeyType {
+    match spki.algorithm.oid {
+        OID_RSA_ENCRYPTION | OID_RSASSA_PSS => KeyType::Rsa,
+        OID_EC_PUBLIC_KEY => KeyType::Ec,
+        _ => KeyType::Other,
+    }
+}
+
+
+// The size of an RSA key's modulus in bits, or `None` if the key isn't one we can
+// parse as RSA.
+pub fn rsa_modulus_bits(spki: &SubjectPublicKeyInfo) -> Option<usize> {
+    if !is_rsa_key(spki) {
+        return None;
+    }
+    let key = der::parse(spki.public_key, der::SEQUENCE).ok()?;
+    let modulus = Reader::new(key).read(der::INTEGER).and_then(der::positive_integer).ok()?;
+    let leading_zeros = modulus.first().map_or(0, |first| first.leading_zeros() as usize);
+    Some(modulus.len() * 8 - leading_zeros)
+}
+
+
+// The named curve of an elliptic curve key, if it's one we know of, whether or not
+// we can verify signatures made on it.
+pub fn curve(spki: &SubjectPublicKeyInfo) -> Option<Curve> {
+    if spki.algorithm.oid != OID_EC_PUBLIC_KEY {
+        return None;
+    }
+    match der::parse(spki.algorithm.parameters?, der::OID).ok()? {
+        OID_SECP192R1 => Some(Curve::P192),
+        OID_SECP224R1 => Some(Curve::P224),
+        OID_SECP256R1 => Some(Curve::P256),
+        OID_SECP384R1 => Some(Curve::P384),
+        OID_SECP521R1 => Some(Curve::P521),
+        _ => None,
+    }
+}
+
+
+fn verify_ecdsa(spki: &SubjectPublicKeyInfo, hash: HashAlgorithm, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+    use p256::ecdsa::signature::hazmat::PrehashVerifier;
+
+    if spki.algorithm.oid != OID_EC_PUBLIC_KEY {
+        return Err(Error::BadSignature);
+    }
+    let curve = match spki.algorithm.parameters {
+        Some(parameters) => der::parse(parameters, der::OID)?,
+        None => return Err(Error::BadSignature),
+    };
+
+    // The signature is the DER encoding of ECDSA-Sig-Value, which is the format
+    // the RustCrypto crates call "DER".
+    let digest = digest(hash, message);
+    match curve {
+        OID_SECP256R1 => {
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(spki.public_key).map_err(|_| Error::BadSignature)?;
+            let signature = p256::ecdsa::Signature::from_der(signature).map_err(|_| Error::BadSignature)?;
+            key.verify_prehash(&digest, &signature).map_err(|_| Error::BadSignature)
+        }
+        OID_SECP384R1 => {
+            let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(spki.public_key).map_err(|_| Error::BadSignature)?;
+            let signature = p384::ecdsa::Signature::from_der(signature).map_err(|_| Error::BadSignature)?;
+            key.verify_prehash(&digest, &signature).map_err(|_| Error::BadSignature)
+        }
+        OID_SECP521R1 => {
+            // The crate won't take a digest shorter than half the size of the curve,
+            // as SHA-256 is for P-521. Padding it with zeros leaves its value, which
+            // is all ECDSA uses, the same.
+            let mut digest = digest;
+            if digest.len() < 64 {
+                let mut padded = vec![0; 64 - digest.len()];
+                padded.extend_from_slice(&digest);
+                digest = padded;
+            }
+            let key = p521::ecdsa::VerifyingKey::from_sec1_bytes(spki.public_key).map_err(|_| Error::BadSignature)?;
+            let signature = p521::ecdsa::Signature::from_der(signature).map_err(|_| Error::BadSignature)?;
+            key.verify_prehash(&digest, &signature).map_err(|_| Error::BadSignature)
+        }
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+
+fn verify_ed25519(spki: &SubjectPublicKeyInfo, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+    use ed25519_dalek::Verifier;
+
+    if spki.algorithm.oid != OID_ED25519 {
+        return Err(Error::BadSignature);
+    }
+    if spki.public_key.len() != ed25519_dalek::PUBLIC_KEY_LENGTH {
+        return Err(Error::BadSignature);
+    }
+    let mut key = [0; ed25519_dalek::PUBLIC_KEY_LENGTH];
+    key.copy_from_slice(spki.public_key);
+    let key = ed25519_dalek::VerifyingKey::from_bytes(&key).map_err(|_| Error::BadSignature)?;
+    let signature = ed25519_dalek::Signature::from_slice(signature).map_err(|_| Error::BadSignature)?;
+    key.verify(message, &signature).map_err(|_| Error::BadSignature)
+}
+
+
+// Verifies an Ed448 signature as RFC 8032 section 5.2.7 describes, with no context.
+fn verify_ed448(spki: &SubjectPublicKeyInfo, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+    if spki.algorithm.oid != OID_ED448 {
+        return Err(Error::BadSignature);
+    }
+    if spki.public_key.len() != ED448_KEY_LENGTH || signature.len() != ED448_SIGNATURE_LENGTH {
+        return Err(Error::BadSignature);
+    }
+    let (encoded_r, encoded_s) = signature.split_at(ED448_KEY_LENGTH);
+    let key = decode_ed448_point(spki.public_key)?;
+    let r = decode_ed448_point(encoded_r)?;
+
+    // S must be less than the order of the group, so reducing it mustn't change it.
+    let mut wide = [0; ED448_SIGNATURE_LENGTH];
+    wide[..encoded_s.len()].copy_from_slice(encoded_s);
+    let s = Scalar::from_bytes_mod_order_wide(&wide);
+    if s.to_bytes_rfc_8032()[..] != *encoded_s {
+        return Err(Error::BadSignature);
+    }
+
+    // k = SHAKE256(dom4(0, "") || R || A || M, 114), where dom4 is "SigEd448" followed
+    // by the flag and the length of the context, both zero here.
+    let mut shake = Shake256::default();
+    shake.update(b"SigEd448\x00\x00");
+    shake.update(encoded_r);
+    shake.update(spki.public_key);
+    shake.update(message);
+    let mut k = [0; ED448_SIGNATURE_LENGTH];
+    shake.finalize_xof().read(&mut k);
+    let k = Scalar::from_bytes_mod_order_wide(&k);
+
+    // The signature is good if [4][S]B = [4]R + [4][k]A.
+    let difference = ExtendedPoint::generator() * s - r - key * k;
+    if difference.double().double() == ExtendedPoint::identity() {
+        Ok(())
+    } else {
+        Err(Error::BadSignature)
+    }
+}
+
+// Decodes a point, rejecting any encoding other than the canonical one. Decompression
+// alone accepts a y that isn't reduced modulo p and ignores all but the top bit of the
+// last byte, so we encode the point again and insist we get back what we were given.
+fn decode_ed448_point(encoded: &[u8]) -> Result<ExtendedPoint, Error> {
+    let mut bytes = [0; ED448_KEY_LENGTH];
+    bytes.copy_from_slice(encoded);
+    let point = CompressedEdwardsY(bytes).decompress().ok_or(Error::BadSignature)?;
+    if point.compress().0 != bytes {
+        return Err(Error::BadSignature);
+    }
+    Ok(point)
+}
+
+
+#[cfg(test)]
+mod test {
+    use signature::{rsa_modulus_bits, verify, Error, OID_ED448};
+    use test::{certifi_chain, test_pki_chain};
+    use x509::{AlgorithmIdentifier, Certificate, SubjectPublicKeyInfo};
+
+    // Pairs of a certificate authority and a leaf it signed, using a key of each kind
+    // we support.
+    fn signed_by_each_algorithm() -> Vec<(&'static [u8], &'static [u8])> {
+        vec![
+            (include_bytes!("../fixtures/signatures/rsa-pss-ca.crt"),
+             include_bytes!("../fixtures/signatures/rsa-pss.crt")),
+            (include_bytes!("../fixtures/signatures/rsa-pss-sha512-ca.crt"),
+             include_bytes!("../fixtures/signatures/rsa-pss-sha512.crt")),
+            (include_bytes!("../fixtures/signatures/p521-ca.crt"),
+             include_bytes!("../fixtures/signatures/p521.crt")),
+            (include_bytes!("../fixtures/signatures/p521-ca.crt"),
+             include_bytes!("../fixtures/signatures/p521-sha256.crt")),
+            (include_bytes!("../fixtures/signatures/ed25519-ca.crt"),
+             include_bytes!("../fixtures/signatures/ed25519.crt")),
+            (include_bytes!("../fixtures/signatures/ed448-ca.crt"),
+             include_bytes!("../fixtures/signatures/ed448.crt")),
+        ]
+    }
+
+    fn verify_with(issuer: &[u8], leaf: &[u8], tbs: Option<&[u8]>) -> Result<(), Error> {
+        let issuer = Certificate::parse(issuer).unwrap();
+        let leaf = Certificate::parse(leaf).unwrap();
+        verify(&issuer.spki, &leaf.signature_algorithm, tbs.unwrap_or(leaf.tbs), leaf.signature)
+    }
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // Verifies an Ed448 signature over a message with a bare public key, all given in hex.
+    fn verify_ed448_hex(key: &str, message: &str, signature: &str) -> Result<(), Error> {
+        let key = unhex(key);
+        let spki = SubjectPublicKeyInfo {
+            algorithm: AlgorithmIdentifier { oid: OID_ED448, parameters: None },
+            public_key: &key,
+        };
+        verify(&spki, &spki.algorithm, &unhex(message), &unhex(signature))
+    }
+
+    #[test]
+    fn verifies_rsa_pkcs1_signature() {
+        let chain = certifi_chain();
+        assert_eq!(verify_with(chain[1], chain[0], None), Ok(()));
+    }
+
+    #[test]
+    fn rejects_signature_from_wrong_key() {
+        let chain = certifi_chain();
+        assert_eq!(verify_with(chain[0], chain[0], None), Err(Error::BadSignature));
+    }
+
+    #[test]
+    fn measures_rsa_keys() {
+        let chain = certifi_chain();
+        let leaf = Certificate::parse(chain[0]).unwrap();
+        let second = Certificate::parse(chain[2]).unwrap();
+        assert_eq!(rsa_modulus_bits(&leaf.spki), Some(2048));
+        assert_eq!(rsa_modulus_bits(&second.spki), Some(4096));
+
+        let ecdsa = Certificate::parse(test_pki_chain()[0]).unwrap();
+        assert_eq!(rsa_modulus_bits(&ecdsa.spki), None);
+
+        let pss = Certificate::parse(include_bytes!("../fixtures/signatures/rsa-pss-ca.crt")).unwrap();
+        assert_eq!(rsa_modulus_bits(&pss.spki), Some(2048));
+    }
+
+    #[test]
+    fn rejects_tampered_message() {
+        let chain = certifi_chain();
+        let leaf = Certificate::parse(chain[0]).unwrap();
+        let mut tbs = leaf.tbs.to_vec();
+        let last = tbs.len() - 1;
+        tbs[last] ^= 0x01;
+        assert_eq!(verify_with(chain[1], chain[0], Some(&tbs)), Err(Error::BadSignature));
+    }
+
+    #[test]
+    fn verifies_each_algorithm() {
+        for (issuer, leaf) in signed_by_each_algorithm() {
+            assert_eq!(verify_with(issuer, leaf, None), Ok(()));
+
+            let mut tbs = Certificate::parse(leaf).unwrap().tbs.to_vec();
+            let last = tbs.len() - 1;
+            tbs[last] ^= 0x01;
+            assert_eq!(verify_with(issuer, leaf, Some(&tbs)), Err(Error::BadSignature));
+
+            // The leaf's own key is a P-256 key, which made none of these signatures.
+            assert_eq!(verify_with(leaf, leaf, None), Err(Error::BadSignature));
+        }
+    }
+
+    #[test]
+    fn verifies_rfc_8032_ed448_vectors() {
+        // The examples without a context from RFC 8032 section 7.4: key, message, signature.
+        let vectors = [
+            ("5fd7449b59b461fd2ce787ec616ad46a1da1342485a70e1f8a0ea75d80e96778\
+             edf124769b46c7061bd6783df1e50f6cd1fa1abeafe8256180",
+             "",
+             "533a37f6bbe457251f023c0d88f976ae2dfb504a843e34d2074fd823d41a591f\
+             2b233f034f628281f2fd7a22ddd47d7828c59bd0a21bfd3980ff0d2028d4b18a\
+             9df63e006c5d1c2d345b925d8dc00b4104852db99ac5c7cdda8530a113a0f4db\
+             b61149f05a7363268c71d95808ff2e652600"),
+            ("43ba28f430cdff456ae531545f7ecd0ac834a55d9358c0372bfa0c6c6798c086\
+             6aea01eb00742802b8438ea4cb82169c235160627b4c3a9480",
+             "03",
+             "26b8f91727bd62897af15e41eb43c377efb9c610d48f2335cb0bd0087810f435\
+             2541b143c4b981b7e18f62de8ccdf633fc1bf037ab7cd779805e0dbcc0aae1cb\
+             cee1afb2e027df36bc04dcecbf154336c19f0af7e0a6472905e799f1953d2a0f\
+             f3348ab21aa4adafd1d234441cf807c03a00"),
+            ("dcea9e78f35a1bf3499a831b10b86c90aac01cd84b67a0109b55a36e9328b1e3\
+             65fce161d71ce7131a543ea4cb5f7e9f1d8b00696447001400",
+             "0c3e544074ec63b0265e0c",
+             "1f0a8888ce25e8d458a21130879b840a9089d999aaba039eaf3e3afa090a09d3\
+             89dba82c4ff2ae8ac5cdfb7c55e94d5d961a29fe0109941e00b8dbdeea6d3b05\
+             1068df7254c0cdc129cbe62db2dc957dbb47b51fd3f213fb8698f064774250a5\
+             028961c9bf8ffd973fe5d5c206492b140e00"),
+            ("3ba16da0c6f2cc1f30187740756f5e798d6bc5fc015d7c63cc9510ee3fd44adc\
+             24d8e968b6e46e6f94d19b945361726bd75e149ef09817f580",
+             "64a65f3cdedcdd66811e2915",
+             "7eeeab7c4e50fb799b418ee5e3197ff6bf15d43a14c34389b59dd1a7b1b85b4a\
+             e90438aca634bea45e3a2695f1270f07fdcdf7c62b8efeaf00b45c2c96ba457e\
+             b1a8bf075a3db28e5c24f6b923ed4ad747c3c9e03c7079efb87cb110d3a99861\
+             e72003cbae6d6b8b827e4e6c143064ff3c00"),
+            ("b3da079b0aa493a5772029f0467baebee5a8112d9d3a22532361da294f7bb381\
+             5c5dc59e176b4d9f381ca0938e13c6c07b174be65dfa578e80",
+             "64a65f3cdedcdd66811e2915e7",
+             "6a12066f55331b6c22acd5d5bfc5d71228fbda80ae8dec26bdd306743c5027cb\
+             4890810c162c027468675ecf645a83176c0d7323a2ccde2d80efe5a1268e8aca\
+             1d6fbc194d3f77c44986eb4ab4177919ad8bec33eb47bbb5fc6e28196fd1caf5\
+             6b4e7e0ba5519234d047155ac727a1053100"),
+            ("df9705f58edbab802c7f8363cfe5560ab1c6132c20a9f1dd163483a26f8ac53a\
+             39d6808bf4a1dfbd261b099bb03b3fb50906cb28bd8a081f00",
+             "bd0f6a3747cd561bdddf4640a332461a4a30a12a434cd0bf40d766d9c6d458e5\
+             512204a30c17d1f50b5079631f64eb3112182da3005835461113718d1a5ef944",
+             "554bc2480860b49eab8532d2a533b7d578ef473eeb58c98bb2d0e1ce488a98b1\
+             8dfde9b9b90775e67f47d4a1c3482058efc9f40d2ca033a0801b63d45b3b722e\
+             f552bad3b4ccb667da350192b61c508cf7b6b5adadc2c8d9a446ef003fb05cba\
+             5f30e88e36ec2703b349ca229c2670833900"),
+        ];
+        for &(key, message, signature) in &vectors {
+            assert_eq!(verify_ed448_hex(key, message, signature), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejects_non_canonical_ed448_encodings() {
+        // Each of these signatures is good for the empty message once the key and R are
+        // decoded, but either the key or R has an encoding RFC 8032 doesn't allow, so
+        // anyone could produce several signatures that all verify.
+
+        // The key with a low bit set in its last byte, which decoding ignores.
+        let key = "5fd7449b59b461fd2ce787ec616ad46a1da1342485a70e1f8a0ea75d80e96778\
+            edf124769b46c7061bd6783df1e50f6cd1fa1abeafe8256181";
+        let signature = "ac86b3b2fe3694ccae9030a46162b9858e11de7bf6a2d83fd32d9c7b072a2212\
+            0827cf6589514dd1ac805f5152edfb02244f2604b915e500005cdf5318e918ef\
+            5d6aa09e721c38599bebdb273c2acac019e9d9eb28fd52996868b450b2200bc4\
+            f5ff09df56cd743ac972030f12d422781900";
+        assert_eq!(verify_ed448_hex(key, "", signature), Err(Error::BadSignature));
+
+        // R with a low bit set in its last byte.
+        let key = "5fd7449b59b461fd2ce787ec616ad46a1da1342485a70e1f8a0ea75d80e96778\
+            edf124769b46c7061bd6783df1e50f6cd1fa1abeafe8256180";
+        let signature = "ac86b3b2fe3694ccae9030a46162b9858e11de7bf6a2d83fd32d9c7b072a2212\
+            0827cf6589514dd1ac805f5152edfb02244f2604b915e500011ea7a009974d2b\
+            24946616a416d9de0624d8d9414d9f7bd8bcbc1ad57ecfd50d206a4af5f52220\
+            f4c0053e507d7c4efec1b9177452e6ed3200";
+        assert_eq!(verify_ed448_hex(key, "", signature), Err(Error::BadSignature));
+
+        // The identity as the key, encoded with y = p + 1.
+        let key = "00000000000000000000000000000000000000000000000000000000ffffffff\
+            ffffffffffffffffffffffffffffffffffffffffffffffff00";
+        let signature = "ac86b3b2fe3694ccae9030a46162b9858e11de7bf6a2d83fd32d9c7b072a2212\
+            0827cf6589514dd1ac805f5152edfb02244f2604b915e5000009030000000000\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            000000000000000000000000000000000000";
+        assert_eq!(verify_ed448_hex(key, "", signature), Err(Error::BadSignature));
+
+        // The identity as R, encoded with y = p + 1.
+        let key = "5fd7449b59b461fd2ce787ec616ad46a1da1342485a70e1f8a0ea75d80e96778\
+            edf124769b46c7061bd6783df1e50f6cd1fa1abeafe8256180";
+        let signature = "00000000000000000000000000000000000000000000000000000000ffffffff\
+            ffffffffffffffffffffffffffffffffffffffffffffffff00f64834345dc0fc\
+            5a79c0e6214cd3b3943a76df58091d39329acf7260cce10099bf8f346e53c747\
+            9db5349c7098aa7351b16ea0fd5250761700";
+        assert_eq!(verify_ed448_hex(key, "", signature), Err(Error::BadSignature));
+
+        // The first RFC 8032 example with the order of the group added to S.
+        let key = "5fd7449b59b461fd2ce787ec616ad46a1da1342485a70e1f8a0ea75d80e96778\
+            edf124769b46c7061bd6783df1e50f6cd1fa1abeafe8256180";
+        let signature = "533a37f6bbe457251f023c0d88f976ae2dfb504a843e34d2074fd823d41a591f\
+            2b233f034f628281f2fd7a22ddd47d7828c59bd0a21bfd3980f25278d3667403\
+            c14bcec5f9cfde9955ebc8333c0ae78fc86e518317c5c7cdda8530a113a0f4db\
+            b61149f05a7363268c71d95808ff2e656600";
+        assert_eq!(verify_ed448_hex(key, "", signature), Err(Error::BadSignature));
+    }
+
+    #[test]
+    fn reports_unsupported_algorithms() {
+        // DSA isn't supported at all, and secp256k1 isn't a curve we support.
+        let dsa_ca = include_bytes!("../fixtures/signatures/dsa-ca.crt");
+        let dsa = include_bytes!("../fixtures/signatures/dsa.crt");
+        assert_eq!(verify_with(dsa_ca, dsa, None), Err(Error::UnsupportedAlgorithm));
+
+        let secp256k1_ca = include_bytes!("../fixtures/signatures/secp256k1-ca.crt");
+        let secp256k1 = include_bytes!("../fixtures/signatures/secp256k1.crt");
+        assert_eq!(verify_with(secp256k1_ca, secp256k1, None), Err(Error::UnsupportedAlgorithm));
+    }
+}
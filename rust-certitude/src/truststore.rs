@@ -0,0 +1,351 @@
+// Loading of trust anchors from the places Unix systems keep them.
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use sha1::{Digest, Sha1};
+
+use der;
+use pem;
+use x509;
+
+// Well-known locations of PEM bundles of trusted certificates. Only the first of
+// these that exists is used.
+const BUNDLE_LOCATIONS: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",                // Debian, Ubuntu, Gentoo, Arch
+    "/etc/pki/tls/certs/ca-bundle.crt",                  // Fedora, RHEL 6
+    "/etc/pki/ca-trust/extracted/pem/tls-ca-bundle.pem", // CentOS, RHEL 7
+    "/etc/ssl/ca-bundle.pem",                            // openSUSE
+    "/etc/pki/tls/cacert.pem",                           // OpenELEC
+    "/etc/ssl/cert.pem",                                 // Alpine, OS X
+];
+
+// Well-known directories of individual trusted certificates.
+const DIRECTORY_LOCATIONS: &[&str] = &[
+    "/etc/ssl/certs",     // SLES, openSUSE
+    "/etc/pki/tls/certs", // Fedora, RHEL
+];
+
+
+/// Where to find the trust anchors for validating a certificate chain.
+#[derive(Debug, Clone, Copy)]
+pub enum TrustAnchors<'a> {
+    /// The system's trust anchors.
+    System,
+    /// Only the given DER-encoded trust anchors.
+    Only(&'a [&'a [u8]]),
+    /// The system's trust anchors, and the given DER-encoded ones as well.
+    SystemAnd(&'a [&'a [u8]]),
+}
+
+
+/// A set of trust anchors: the certificates that certificate chains are validated
+/// against.
+///
+/// On Windows and OS X the platform backends get their trust anchors from the
+/// operating system. Elsewhere, and for the portable validator, Certitude needs to
+/// find them itself: `TrustStore::system` knows where the common Linux
+/// distributions keep them.
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    anchors: Vec<Vec<u8>>,
+
+    // Directories in the layout created by OpenSSL's `c_rehash`, which are only
+    // read as and when we need an anchor with a particular subject.
+    hashed_directories: Vec<PathBuf>,
+}
+
+impl TrustStore {
+    /// Load the system's trust anchors.
+    ///
+    /// The `SSL_CERT_FILE` environment variable may name a PEM bundle to use in
+    /// place of the distribution's, and `SSL_CERT_DIR` may contain a colon-separated
+    /// list of directories of PEM files. Otherwise, the first bundle found in one of
+    /// the well-known locations is used. The well-known directories are only searched
+    /// if no bundle can be found, as on most systems they contain the same
+    /// certificates as the bundle. A directory that has been prepared by OpenSSL's
+    /// `c_rehash` is treated as by `TrustStore::from_hashed_directory`.
+    ///
+    /// Returns an error if no trust anchors at all could be found.
+    pub fn system() -> io::Result<TrustStore> {
+        let bundles = locations(env::var_os("SSL_CERT_FILE"), BUNDLE_LOCATIONS);
+        let directories = locations(env::var_os("SSL_CERT_DIR"), DIRECTORY_LOCATIONS);
+        let directories_configured = env::var_os("SSL_CERT_DIR").is_some();
+
+        let mut store = TrustStore::default();
+        if let Some(bundle) = bundles.iter().find(|path| path.is_file()) {
+            store.add_pem_file(bundle)?;
+        }
+        if directories_configured || store.anchors.is_empty() {
+            for directory in directories.iter().filter(|path| path.is_dir()) {
+                if is_hashed_directory(directory)? {
+                    store.hashed_directories.push(directory.clone());
+                } else {
+                    store.add_directory(directory)?;
+                }
+            }
+        }
+
+        if store.anchors.is_empty() && store.hashed_directories.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no trust anchors found"));
+        }
+        Ok(store)
+    }
+
+    /// Load the trust anchors in a PEM bundle.
+    pub fn from_pem_file<P: AsRef<Path>>(path: P) -> io::Result<TrustStore> {
+        let mut store = TrustStore::default();
+        store.add_pem_file(path.as_ref())?;
+        Ok(store)
+    }
+
+    /// Load the trust anchors in every PEM file in a directory.
+    pub fn from_directory<P: AsRef<Path>>(path: P) -> io::Result<TrustStore> {
+        let mut store = TrustStore::default();
+        store.add_directory(path.as_ref())?;
+        Ok(store)
+    }
+
+    /// Use the trust anchors in a directory prepared by OpenSSL's `c_rehash`.
+    ///
+    /// In such a directory each anchor is stored in a file named after a hash of
+    /// its subject, which means that anchors can be found by name without reading
+    /// every file. The directory is not read until an anchor is needed.
+    pub fn from_hashed_directory<P: AsRef<Path>>(path: P) -> io::Result<TrustStore> {
+        let path = path.as_ref();
+        if !fs::metadata(path)?.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a directory"));
+        }
+        let mut store = TrustStore::default();
+        store.hashed_directories.push(path.to_path_buf());
+        Ok(store)
+    }
+
+    /// The DER encodings of the trust anchors.
+    ///
+    /// This does not include anchors in hashed directories, which are only loaded
+    /// when they're needed.
+    pub fn anchors(&self) -> Vec<&[u8]> {
+        self.anchors.iter().map(|anchor| &anchor[..]).collect()
+    }
+
+    /// The hashed directories that anchors will be looked up in.
+    pub fn hashed_directories(&self) -> &[PathBuf] {
+        &self.hashed_directories
+    }
+
+    // Loads the anchors in the hashed directories that may have the given
+    // DER-encoded subject. Files are named for a 32-bit hash, so the caller must
+    // still check the subject of everything returned.
+    pub(crate) fn hashed_anchors(&self, subject: &[u8]) -> Vec<Vec<u8>> {
+        let hash = match subject_hash(subject) {
+            Ok(hash) => hash,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut anchors = Vec::new();
+        for directory in &self.hashed_directories {
+            // Collisions are resolved by incrementing the suffix, so keep going
+            // until there's a gap.
+            for suffix in 0.. {
+                let path = directory.join(format!("{:08x}.{}", hash, suffix));
+                let mut contents = Vec::new();
+                match File::open(&path).and_then(|mut file| file.read_to_end(&mut contents)) {
+                    Ok(_) => anchors.extend(pem::parse_certificates(&contents)),
+                    Err(_) => break,
+                }
+            }
+        }
+        anchors
+    }
+
+    pub(crate) fn from_anchors(anchors: &[&[u8]]) -> TrustStore {
+        let mut store = TrustStore::default();
+        for anchor in anchors {
+            store.add_anchor(anchor);
+        }
+        store
+    }
+
+    pub(crate) fn add_anchor(&mut self, anchor: &[u8]) {
+        if !self.anchors.iter().any(|existing| existing[..] == *anchor) {
+            self.anchors.push(anchor.to_vec());
+        }
+    }
+
+    fn add_pem_file(&mut self, path: &Path) -> io::Result<()> {
+        let mut contents = Vec::new();
+        File::open(path)?.read_to_end(&mut contents)?;
+        for anchor in pem::parse_certificates(&contents) {
+            self.add_anchor(&anchor);
+        }
+        Ok(())
+    }
+
+    // Directories tend to contain all kinds of things: rather than failing on
+    // anything we can't read, just skip it.
+    fn add_directory(&mut self, path: &Path) -> io::Result<()> {
+        let mut entries = fs::read_dir(path)?
+                              .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                              .filter(|path| path.is_file())
+                              .collect::<Vec<_>>();
+        entries.sort();
+        for entry in entries {
+            let _ = self.add_pem_file(&entry);
+        }
+        Ok(())
+    }
+}
+
+
+// The system's trust anchors, shared by a verifier and its clones. They're loaded the
+// first time a backend asks for them rather than for every chain, and backends that
+// get their anchors from the operating system never ask.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SystemTrustStore(Arc<OnceLock<Option<TrustStore>>>);
+
+impl SystemTrustStore {
+    // The system's trust anchors, or nothing if there aren't any to be found.
+    pub fn get(&self) -> Option<&TrustStore> {
+        self.0.get_or_init(|| TrustStore::system().ok()).as_ref()
+    }
+}
+
+
+// The locations to search: those named by the environment variable, if it's set,
+// or else the defaults.
+fn locations(configured: Option<OsString>, defaults: &[&str]) -> Vec<PathBuf> {
+    match configured {
+        Some(paths) => env::split_paths(&paths).collect(),
+        None => defaults.iter().map(PathBuf::from).collect(),
+    }
+}
+
+
+// Whether `c_rehash` has been run over a directory: that is, whether it contains
+// anything named like "5ad8a5d6.0".
+fn is_hashed_directory(path: &Path) -> io::Result<bool> {
+    for entry in fs::read_dir(path)? {
+        if let Some(name) = entry?.file_name().to_str() {
+            if is_hashed_name(name) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn is_hashed_name(name: &str) -> bool {
+    match name.split_once('.') {
+        Some((hash, suffix)) => {
+            hash.len() == 8 &&
+            hash.bytes().all(|b| b.is_ascii_hexdigit()) &&
+            !suffix.is_empty() &&
+            suffix.bytes().all(|b| b.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+
+// The hash OpenSSL uses to name files in hashed directories: the first four bytes,
+// little-endian, of the SHA-1 digest of the canonical encoding of the name.
+fn subject_hash(name: &[u8]) -> Result<u32, der::Error> {
+    let digest = Sha1::digest(canonical_name(name)?);
+    Ok(u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]]))
+}
+
+
+// OpenSSL's canonical encoding of a Name: its canonical RDNs, without the enclosing
+// SEQUENCE.
+fn canonical_name(name: &[u8]) -> Result<Vec<u8>, der::Error> {
+    Ok(x509::canonical_rdns(name)?.concat())
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    use test::{test_pki_chain, test_pki_root};
+    use truststore::{locations, subject_hash, SystemTrustStore, TrustStore};
+    use x509::Certificate;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/truststore").join(name)
+    }
+
+    #[test]
+    fn loads_bundle() {
+        let store = TrustStore::from_pem_file(fixture("bundle.pem")).unwrap();
+        let anchors = store.anchors();
+        assert_eq!(anchors.len(), 2);
+        assert!(anchors.contains(&test_pki_root()));
+    }
+
+    #[test]
+    fn loads_directory() {
+        // The directory contains the same two certificates as the bundle, in
+        // separate files, along with some things that aren't certificates at all.
+        let store = TrustStore::from_directory(fixture("certs")).unwrap();
+        let anchors = store.anchors();
+        assert_eq!(anchors.len(), 2);
+        assert!(anchors.contains(&test_pki_root()));
+    }
+
+    #[test]
+    fn loads_system_anchors_once() {
+        // The store is loaded the first time it's asked for, then the same one is
+        // given out every time after, clones included.
+        let system = SystemTrustStore::default();
+        let loaded = system.get().map(|store| store as *const TrustStore);
+        assert_eq!(system.get().map(|store| store as *const TrustStore), loaded);
+        assert_eq!(system.clone().get().map(|store| store as *const TrustStore), loaded);
+    }
+
+    #[test]
+    fn hashes_subjects_as_openssl_does() {
+        let root = Certificate::parse(test_pki_root()).unwrap();
+        assert_eq!(subject_hash(root.subject), Ok(0x6b6a47f9));
+
+        // C=GB + O="  Certitude\t  Test  PKI " as a BMPString, CN="Ünïcode ROOT".
+        let name = [0x30, 0x5f, 0x31, 0x44, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x06, 0x13,
+                    0x02, 0x47, 0x42, 0x30, 0x37, 0x06, 0x03, 0x55, 0x04, 0x0a, 0x1e, 0x30,
+                    0x00, 0x20, 0x00, 0x20, 0x00, 0x43, 0x00, 0x65, 0x00, 0x72, 0x00, 0x74,
+                    0x00, 0x69, 0x00, 0x74, 0x00, 0x75, 0x00, 0x64, 0x00, 0x65, 0x00, 0x09,
+                    0x00, 0x20, 0x00, 0x20, 0x00, 0x54, 0x00, 0x65, 0x00, 0x73, 0x00, 0x74,
+                    0x00, 0x20, 0x00, 0x20, 0x00, 0x50, 0x00, 0x4b, 0x00, 0x49, 0x00, 0x20,
+                    0x31, 0x17, 0x30, 0x15, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x0e, 0xc3,
+                    0x9c, 0x6e, 0xc3, 0xaf, 0x63, 0x6f, 0x64, 0x65, 0x20, 0x52, 0x4f, 0x4f,
+                    0x54];
+        assert_eq!(subject_hash(&name), Ok(0xd6a7e382));
+    }
+
+    #[test]
+    fn loads_hashed_directory_lazily() {
+        let store = TrustStore::from_hashed_directory(fixture("hashed")).unwrap();
+        assert!(store.anchors().is_empty());
+
+        let root = Certificate::parse(test_pki_root()).unwrap();
+        assert_eq!(store.hashed_anchors(root.subject), vec![test_pki_root().to_vec()]);
+        let leaf = Certificate::parse(test_pki_chain()[0]).unwrap();
+        assert!(store.hashed_anchors(leaf.subject).is_empty());
+    }
+
+    #[test]
+    fn missing_bundle_is_an_error() {
+        assert!(TrustStore::from_pem_file(fixture("missing.pem")).is_err());
+    }
+
+    #[test]
+    fn environment_overrides_defaults() {
+        let configured = Some(OsString::from("/a/certs:/b/certs"));
+        assert_eq!(locations(configured, &["/c/certs"]),
+                   vec![PathBuf::from("/a/certs"), PathBuf::from("/b/certs")]);
+        assert_eq!(locations(None, &["/c/certs"]), vec![PathBuf::from("/c/certs")]);
+    }
+}
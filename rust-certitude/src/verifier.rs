@@ -0,0 +1,1228 @@
+// Validation of certificate chains against a configurable policy.
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aia::{self, IssuerFetcher, SharedFetcher};
+use algorithms::AlgorithmPolicy;
+use cache::IntermediateCache;
+use chain::VerifiedChain;
+use client::{self, ClientName};
+use crl::{self, Crl};
+use der;
+use error::{ErrorKind, ValidationError};
+use hostname::{self, Reference};
+use issuers;
+use name_constraints;
+use ocsp::{self, OcspStatus};
+use platform;
+use signature::{self, KeyType};
+use truststore::SystemTrustStore;
+use x509::{self, Certificate};
+use TrustAnchors;
+use ValidationResult;
+
+/// What the leaf certificate must be allowed to be used for.
+///
+/// A leaf with the extended key usage extension must list the purpose, or allow any
+/// purpose, and a leaf with the key usage extension must allow a use of its key that
+/// suits the purpose.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum KeyPurpose {
+    /// Identifying a TLS server. This is the default.
+    ServerAuth,
+    /// Identifying a TLS client.
+    ClientAuth,
+    /// Signing code.
+    CodeSigning,
+    /// Protecting email, as with S/MIME.
+    EmailProtection,
+    /// Signing time-stamps, as a time-stamping authority does.
+    TimeStamping,
+    /// Any other purpose, given by the dotted-decimal form of its object identifier,
+    /// such as `"1.3.6.1.5.5.7.3.9"` for signing OCSP responses. No certificate may
+    /// be used for a purpose that isn't a valid object identifier.
+    Other(String),
+}
+
+impl KeyPurpose {
+    // The contents of the purpose's object identifier, as the extended key usage
+    // extension lists it, or nothing if it isn't a valid object identifier.
+    fn oid(&self) -> Option<Vec<u8>> {
+        match *self {
+            KeyPurpose::ServerAuth => Some(x509::OID_KP_SERVER_AUTH.to_vec()),
+            KeyPurpose::ClientAuth => Some(x509::OID_KP_CLIENT_AUTH.to_vec()),
+            KeyPurpose::CodeSigning => Some(x509::OID_KP_CODE_SIGNING.to_vec()),
+            KeyPurpose::EmailProtection => Some(x509::OID_KP_EMAIL_PROTECTION.to_vec()),
+            KeyPurpose::TimeStamping => Some(x509::OID_KP_TIME_STAMPING.to_vec()),
+            KeyPurpose::Other(ref oid) => der::encode_oid(oid).ok(),
+        }
+    }
+
+    // The uses of the key that suit the purpose, following RFC 5280, for a key of the
+    // given type. For TLS, that depends on the key exchange: an RSA key may encipher
+    // the premaster secret, an EC key may agree it, and either may sign. A client
+    // never enciphers with its key. We don't know what other purposes need, so allow
+    // any use.
+    fn key_usages(&self, key_type: KeyType) -> u16 {
+        let signature = x509::KEY_USAGE_DIGITAL_SIGNATURE;
+        let key_exchange = match key_type {
+            KeyType::Rsa => x509::KEY_USAGE_KEY_ENCIPHERMENT,
+            KeyType::Ec => x509::KEY_USAGE_KEY_AGREEMENT,
+            KeyType::Other => 0,
+        };
+        match *self {
+            KeyPurpose::ServerAuth => signature | key_exchange,
+            KeyPurpose::ClientAuth => signature | (key_exchange & x509::KEY_USAGE_KEY_AGREEMENT),
+            KeyPurpose::CodeSigning => signature,
+            KeyPurpose::EmailProtection => signature | x509::KEY_USAGE_NON_REPUDIATION | key_exchange,
+            KeyPurpose::TimeStamping => signature | x509::KEY_USAGE_NON_REPUDIATION,
+            KeyPurpose::Other(_) => !0,
+        }
+    }
+}
+
+
+/// Whether to check that the certificates in a chain have not been revoked.
+///
+/// By default revocation isn't checked. When it is, the platform checks it however it
+/// does, if it can. A verifier given CRLs checks them itself instead, and asks nothing
+/// of the platform. An OCSP response stapled to the handshake only takes the place of
+/// the platform's check of the leaf.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum RevocationMode {
+    /// Don't check revocation. This is the default.
+    Disabled,
+    /// Reject revoked certificates, but trust certificates whose revocation status
+    /// can't be determined.
+    BestEffort,
+    /// Reject revoked certificates, and certificates whose revocation status can't
+    /// be determined.
+    Required,
+}
+
+
+// The parts of a verifier's policy that the backends are responsible for. The
+// hostname isn't one of them: the verifier checks that itself. It checks the leaf's
+// key usages against the purpose too, but the backends should still ask the
+// platform for the purpose where it can, so that the rest of the chain is checked
+// for it as the platform sees fit.
+//
+// Backends that can't honour some part of the policy fail with
+// `ErrorKind::MissingFunctionality`, rather than quietly validating something else.
+pub(crate) struct Policy<'a> {
+    pub anchors: TrustAnchors<'a>,
+    // The system's trust anchors, for backends that find them themselves, loaded by
+    // the first of them to need them.
+    pub system_store: SystemTrustStore,
+    pub purpose: KeyPurpose,
+    // The time to validate at, if not now.
+    pub time: Option<SystemTime>,
+    pub revocation: RevocationMode,
+    // The OCSP response stapled to the handshake, if there was one that we can rely
+    // on and revocation is to be checked. Backends that can give it to the platform
+    // should, so that the platform checks the leaf with it rather than going online,
+    // and the rest of the chain as it would anyway.
+    pub ocsp_response: Option<&'a [u8]>,
+}
+
+impl<'a> Policy<'a> {
+    // The default policy, which the backends' tests start from: a TLS server,
+    // validated now.
+    #[cfg(test)]
+    pub fn new(anchors: TrustAnchors<'a>) -> Policy<'a> {
+        Policy {
+            anchors,
+            system_store: SystemTrustStore::default(),
+            purpose: KeyPurpose::ServerAuth,
+            time: None,
+            revocation: RevocationMode::Disabled,
+            ocsp_response: None,
+        }
+    }
+
+    // The time to validate at, in seconds since the Unix epoch.
+    pub fn unix_time(&self) -> i64 {
+        let time = self.time.unwrap_or_else(SystemTime::now);
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        }
+    }
+}
+
+
+/// Validates certificate chains against a configurable policy.
+///
+/// A `Verifier` is made with a `VerifierBuilder`, and can then be used to validate
+/// any number of chains. The default verifier validates a TLS server's certificate
+/// chain against the system's trust anchors, exactly as `verify_cert_chain` does.
+///
+/// Where Certitude finds the system's trust anchors itself, a verifier loads them the
+/// first time it needs them and keeps them, for itself and its clones, so reusing a
+/// verifier saves reading them for every chain.
+///
+/// # Examples
+///
+/// ```
+/// let verifier = Verifier::builder()
+///     .anchors(&[internal_root])
+///     .minimum_rsa_bits(2048)
+///     .build();
+/// match verifier.verify(certs, "service.internal") {
+///     Ok(chain) => println!("trusted through {} certificates", chain.certificates().len()),
+///     Err(e) => println!("not trusted: {}", e),
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    system_anchors: bool,
+    system_store: SystemTrustStore,
+    anchors: Vec<Vec<u8>>,
+    time: Option<SystemTime>,
+    purpose: KeyPurpose,
+    revocation: RevocationMode,
+    crls: Vec<Vec<u8>>,
+    algorithms: AlgorithmPolicy,
+    check_hostname: bool,
+    common_name_fallback: bool,
+    client_names: Vec<ClientName>,
+    fetcher: Option<SharedFetcher>,
+    cache: Option<Arc<IntermediateCache>>,
+}
+
+impl Verifier {
+    /// Starts building a verifier, with the default policy.
+    pub fn builder() -> VerifierBuilder {
+        VerifierBuilder {
+            verifier: Verifier {
+                system_anchors: true,
+                system_store: SystemTrustStore::default(),
+                anchors: Vec::new(),
+                time: None,
+                purpose: KeyPurpose::ServerAuth,
+                revocation: RevocationMode::Disabled,
+                crls: Vec::new(),
+                algorithms: AlgorithmPolicy::new(),
+                check_hostname: true,
+                common_name_fallback: false,
+                client_names: Vec::new(),
+                fetcher: None,
+                cache: None,
+            },
+            cache_capacity: None,
+        }
+    }
+
+    /// Starts building a verifier for the certificate chains of TLS clients, for a
+    /// server to check. The leaf must be usable by a TLS client, and as a client has
+    /// no hostname, none is checked. Otherwise the policy is the default.
+    pub fn client_builder() -> VerifierBuilder {
+        Verifier::builder().purpose(KeyPurpose::ClientAuth).check_hostname(false)
+    }
+
+    /// Validate a chain of DER-encoded certificates for the given hostname, returning
+    /// the chain that was built or reporting why it is not trusted.
+    ///
+    /// The chain is given as for `validate_cert_chain`, leaf first. If hostname
+    /// checking has been turned off, `hostname` is ignored.
+    pub fn verify(&self, encoded_certs: &[&[u8]], hostname: &str) -> Result<VerifiedChain, ValidationError> {
+        self.verify_with(encoded_certs, hostname, platform::verify_chain)
+    }
+
+    /// Validate a chain of DER-encoded certificates for the given hostname.
+    ///
+    /// This is the same as `verify`, but reports the result as `validate_cert_chain`
+    /// does.
+    pub fn validate(&self, encoded_certs: &[&[u8]], hostname: &str) -> ValidationResult {
+        self.verify(encoded_certs, hostname).into()
+    }
+
+    /// Validate a chain of DER-encoded certificates for the given hostname, along with
+    /// the DER-encoded OCSP response the server stapled to the handshake.
+    ///
+    /// This is the same as `verify`, but the response is checked: it must be signed
+    /// by the leaf's issuer, or by a responder the issuer delegated to with the
+    /// OCSPSigning extended key usage, be about the leaf, and be current at the time
+    /// of validation. If it is, the status it gives is on the returned chain, as
+    /// `VerifiedChain::ocsp_status`, whatever the revocation mode. A response that
+    /// can't be relied on is ignored.
+    ///
+    /// Unless revocation is disabled, a leaf the response says is revoked fails with
+    /// `ErrorKind::Revoked`. The response only takes the place of the check of the
+    /// leaf: the platform still checks the rest of the chain as it would anyway, and
+    /// where it can, it is given the response too. Given CRLs, the verifier checks
+    /// the chain itself as ever, but with `RevocationMode::Required` a good response
+    /// covers the leaf in place of a CRL.
+    pub fn verify_stapled(&self, encoded_certs: &[&[u8]], hostname: &str, ocsp_response: &[u8])
+        -> Result<VerifiedChain, ValidationError>
+    {
+        let hostname = if self.check_hostname { Some(hostname) } else { None };
+        self.verify_for(encoded_certs, hostname, Some(ocsp_response), platform::verify_chain)
+    }
+
+    /// Validate a TLS client's chain of DER-encoded certificates, returning the chain
+    /// that was built or reporting why it is not trusted.
+    ///
+    /// This is the same as `verify`, but there is no hostname to check, whether or
+    /// not hostname checking is on. Any client names the verifier requires are still
+    /// checked. The purpose is whatever the verifier was built with, which for one
+    /// from `client_builder` is a TLS client.
+    pub fn verify_client(&self, encoded_certs: &[&[u8]]) -> Result<VerifiedChain, ValidationError> {
+        self.verify_client_with(encoded_certs, platform::verify_chain)
+    }
+
+    /// Validate a TLS client's chain of DER-encoded certificates.
+    ///
+    /// This is the same as `verify_client`, but reports the result as
+    /// `validate_cert_chain` does.
+    pub fn validate_client(&self, encoded_certs: &[&[u8]]) -> ValidationResult {
+        self.verify_client(encoded_certs).into()
+    }
+
+    // Validates the chain as `verify` does, but with the given backend rather than
+    // the platform's. This is how the portable validator shares the verifier's
+    // checks when it isn't the platform's.
+    pub(crate) fn verify_with<F>(&self, encoded_certs: &[&[u8]], hostname: &str, backend: F)
+        -> Result<VerifiedChain, ValidationError>
+        where F: Fn(&[&[u8]], &Policy) -> Result<VerifiedChain, ValidationError>
+    {
+        let hostname = if self.check_hostname { Some(hostname) } else { None };
+        self.verify_for(encoded_certs, hostname, None, backend)
+    }
+
+    // Validates the chain as `verify_client` does, but with the given backend.
+    pub(crate) fn verify_client_with<F>(&self, encoded_certs: &[&[u8]], backend: F)
+        -> Result<VerifiedChain, ValidationError>
+        where F: Fn(&[&[u8]], &Policy) -> Result<VerifiedChain, ValidationError>
+    {
+        self.verify_for(encoded_certs, None, None, backend)
+    }
+
+    // Validates the chain for the hostname, if there is one to check, with the given
+    // backend, and checks the stapled OCSP response, if there is one.
+    fn verify_for<F>(&self, encoded_certs: &[&[u8]], hostname: Option<&str>, ocsp_response: Option<&[u8]>, backend: F)
+        -> Result<VerifiedChain, ValidationError>
+        where F: Fn(&[&[u8]], &Policy) -> Result<VerifiedChain, ValidationError>
+    {
+        // A malformed hostname is reported whatever the chain, before it's looked at.
+        if let Some(hostname) = hostname {
+            let reference = hostname::parse_reference(hostname)?;
+            if let Some(leaf) = encoded_certs.first() {
+                self.check_hostname(leaf, &reference)?;
+            }
+        }
+        if let Some(leaf) = encoded_certs.first() {
+            self.check_leaf(leaf)?;
+        }
+
+        let anchors = self.anchors.iter().map(|anchor| &anchor[..]).collect::<Vec<_>>();
+        let mut policy = Policy {
+            anchors: match (self.system_anchors, anchors.is_empty()) {
+                (true, true) => TrustAnchors::System,
+                (true, false) => TrustAnchors::SystemAnd(&anchors),
+                (false, _) => TrustAnchors::Only(&anchors),
+            },
+            system_store: self.system_store.clone(),
+            purpose: self.purpose.clone(),
+            time: self.time,
+            // Given CRLs, we check revocation against them ourselves, not the platform.
+            revocation: if self.crls.is_empty() { self.revocation } else { RevocationMode::Disabled },
+            ocsp_response: None,
+        };
+        // A stapled response that can't be relied on is left out, as if none had been
+        // stapled.
+        if policy.revocation != RevocationMode::Disabled {
+            let time = policy.unix_time();
+            policy.ocsp_response = ocsp_response.filter(|response| relies_on_staple(encoded_certs, response, time));
+        }
+
+        let chain = self.build_chain(encoded_certs, &policy, backend)?;
+        check_issuers(encoded_certs, &chain)?;
+        check_name_constraints(encoded_certs, &chain)?;
+        self.check_algorithms(encoded_certs, &chain)?;
+        let ocsp_status = self.check_revocation(encoded_certs, &chain, ocsp_response, policy.unix_time())?;
+        if let Some(ref cache) = self.cache {
+            cache.insert(&chain);
+        }
+        Ok(chain.with_ocsp_status(ocsp_status))
+    }
+
+    // Has the backend build the chain. If that fails for want of an issuer, and the
+    // chain is missing one by name, the missing issuers are looked for and the backend
+    // tries again, for as long as that turns up certificates it hasn't seen.
+    fn build_chain<F>(&self, encoded_certs: &[&[u8]], policy: &Policy, backend: F)
+        -> Result<VerifiedChain, ValidationError>
+        where F: Fn(&[&[u8]], &Policy) -> Result<VerifiedChain, ValidationError>
+    {
+        let mut fetched_urls = Vec::new();
+        let mut found_certs: Vec<Vec<u8>> = Vec::new();
+        loop {
+            let certs = encoded_certs.iter().cloned()
+                .chain(found_certs.iter().map(|cert| &cert[..]))
+                .collect::<Vec<_>>();
+            // A chain with every issuer it names has nothing to look for, whatever
+            // goes wrong.
+            let incomplete = (self.cache.is_some() || self.fetcher.is_some()) && has_missing_issuers(&certs);
+            let error = match backend(&certs, policy) {
+                Ok(chain) => return Ok(chain),
+                Err(error) => diagnose(&certs, error),
+            };
+            let found = match error.kind() {
+                ErrorKind::UnknownIssuer if incomplete => self.find_missing_issuers(&certs, &mut fetched_urls),
+                // Where the platform can't say an issuer is missing, any rejection
+                // may be for want of one.
+                ErrorKind::NotTrusted if incomplete && !platform::REPORTS_UNKNOWN_ISSUER => {
+                    self.find_missing_issuers(&certs, &mut fetched_urls)
+                }
+                _ => Vec::new(),
+            };
+            if found.is_empty() {
+                // The certificates we found weren't in the chain we were given, so
+                // can't be blamed by their position in it.
+                return Err(match error.certificate() {
+                    Some(index) if index >= encoded_certs.len() => ValidationError::new(error.kind(), None),
+                    _ => error,
+                });
+            }
+            found_certs.extend(found);
+        }
+    }
+
+    // Looks for the issuers missing from the chain among the intermediates the
+    // verifier remembers, and only if none are there, fetches them. Returns the
+    // certificates found that aren't in the chain already.
+    fn find_missing_issuers(&self, encoded_certs: &[&[u8]], fetched_urls: &mut Vec<String>) -> Vec<Vec<u8>> {
+        if let Some(ref cache) = self.cache {
+            let found = cache.missing_issuers(encoded_certs);
+            if !found.is_empty() {
+                return found;
+            }
+        }
+        match self.fetcher {
+            Some(ref fetcher) => aia::fetch_missing_issuers(&*fetcher.0, encoded_certs, fetched_urls),
+            None => Vec::new(),
+        }
+    }
+
+    // Checks that the leaf certificate is valid for the hostname. This is done before
+    // the chain is validated, so that a mismatch is reported the same way everywhere.
+    fn check_hostname(&self, encoded_leaf: &[u8], reference: &Reference) -> Result<(), ValidationError> {
+        let leaf = match Certificate::parse(encoded_leaf) {
+            Ok(leaf) => leaf,
+            Err(_) => return Err(ValidationError::at(ErrorKind::MalformedCertificateInChain, 0)),
+        };
+        hostname::check_hostname(&leaf, reference, self.common_name_fallback)
+    }
+
+    // Checks that the leaf certificate has one of the client names, if any are
+    // required, and may be used for the purpose. Like the hostname, this is done
+    // before the chain is validated, so that platforms which can only ask for some
+    // purposes still check them all, and all report them the same way.
+    fn check_leaf(&self, encoded_leaf: &[u8]) -> Result<(), ValidationError> {
+        let leaf = match Certificate::parse(encoded_leaf) {
+            Ok(leaf) => leaf,
+            Err(_) => return Err(ValidationError::at(ErrorKind::MalformedCertificateInChain, 0)),
+        };
+        if !client::has_any_name(&leaf, &self.client_names) {
+            return Err(ValidationError::at(ErrorKind::ClientNameMismatch, 0));
+        }
+        check_purpose(&leaf, &self.purpose).map_err(|kind| ValidationError::at(kind, 0))
+    }
+
+    // Checks the signatures and keys in the verified chain against the algorithm
+    // policy. Platforms all have their own ideas about which algorithms are too weak,
+    // so we don't leave it to them.
+    fn check_algorithms(&self, encoded_certs: &[&[u8]], chain: &VerifiedChain) -> Result<(), ValidationError> {
+        let certs = parse_chain(encoded_certs, chain)?;
+        self.algorithms.check_chain(&certs).map_err(|(violation, kind, algorithm)| {
+            let index = position(encoded_certs, &chain.certificates()[violation]);
+            ValidationError::new(kind, index).with_algorithm(algorithm)
+        })
+    }
+
+    // Checks the verified chain against the CRLs and stapled OCSP response we were
+    // given, if revocation is to be checked, returning the status the response gives
+    // for the leaf if it can be relied on. Without CRLs, the platform has checked the
+    // rest. CRLs that can't be parsed are ignored.
+    fn check_revocation(&self, encoded_certs: &[&[u8]], chain: &VerifiedChain, ocsp_response: Option<&[u8]>, time: i64)
+        -> Result<Option<OcspStatus>, ValidationError>
+    {
+        let certs = parse_chain(encoded_certs, chain)?;
+        // A leaf that is itself a trust anchor has no issuer to vouch for it.
+        let ocsp_status = match (ocsp_response, certs.get(1)) {
+            (Some(response), Some(issuer)) => ocsp::leaf_status(response, &certs[0], issuer, time),
+            _ => None,
+        };
+        if self.revocation == RevocationMode::Disabled {
+            return Ok(ocsp_status);
+        }
+        if let Some(OcspStatus::Revoked(reason)) = ocsp_status {
+            let index = position(encoded_certs, chain.leaf());
+            return Err(ValidationError::new(ErrorKind::Revoked, index).with_reason(reason));
+        }
+        if self.crls.is_empty() {
+            return Ok(ocsp_status);
+        }
+        let crls = self.crls.iter().filter_map(|crl| Crl::parse(crl).ok()).collect::<Vec<_>>();
+        let required = self.revocation == RevocationMode::Required;
+        let leaf_covered = ocsp_status == Some(OcspStatus::Good);
+        crl::check_chain(&certs, &crls, time, required, leaf_covered).map_err(|(violation, kind, reason)| {
+            let index = position(encoded_certs, &chain.certificates()[violation]);
+            ValidationError::new(kind, index).with_reason(reason)
+        })?;
+        Ok(ocsp_status)
+    }
+}
+
+// Checks that the leaf's key usage and extended key usage allow the purpose.
+pub(crate) fn check_purpose(leaf: &Certificate, purpose: &KeyPurpose) -> Result<(), ErrorKind> {
+    let oid = purpose.oid().ok_or(ErrorKind::InvalidUsage)?;
+    if let Some(usage) = leaf.key_usage {
+        if usage & purpose.key_usages(signature::key_type(&leaf.spki)) == 0 {
+            return Err(ErrorKind::InvalidKeyUsage);
+        }
+    }
+    if let Some(ref purposes) = leaf.extended_key_usage {
+        if !purposes.iter().any(|&p| p == &oid[..] || p == x509::OID_ANY_EXTENDED_KEY_USAGE) {
+            return Err(ErrorKind::InvalidUsage);
+        }
+    }
+    Ok(())
+}
+
+// Checks that each certificate authority in the verified chain may issue the
+// certificates below it. Platforms check this too, but don't all say which rule was
+// broken, so we check it ourselves to report it the same way everywhere.
+fn check_issuers(encoded_certs: &[&[u8]], chain: &VerifiedChain) -> Result<(), ValidationError> {
+    let certs = parse_chain(encoded_certs, chain)?;
+    issuers::check_path(&certs.iter().collect::<Vec<_>>(), false).map_err(|(violation, kind)| {
+        ValidationError::new(kind, position(encoded_certs, &chain.certificates()[violation]))
+    })
+}
+
+// Checks the names in the verified chain against the name constraints of the
+// certificate authorities in it. Platforms disagree about which kinds of name they
+// constrain, if any, so we don't leave it to them.
+fn check_name_constraints(encoded_certs: &[&[u8]], chain: &VerifiedChain) -> Result<(), ValidationError> {
+    let certs = parse_chain(encoded_certs, chain)?;
+    name_constraints::check_chain(&certs).map_err(|violation| {
+        let index = position(encoded_certs, &chain.certificates()[violation]);
+        ValidationError::new(ErrorKind::NameConstraintViolation, index)
+    })
+}
+
+// Parses the certificates in the verified chain.
+fn parse_chain<'a>(encoded_certs: &[&[u8]], chain: &'a VerifiedChain) -> Result<Vec<Certificate<'a>>, ValidationError> {
+    let mut certs = Vec::new();
+    for encoded_cert in chain.certificates() {
+        match Certificate::parse(encoded_cert) {
+            Ok(cert) => certs.push(cert),
+            Err(_) => {
+                let index = position(encoded_certs, encoded_cert);
+                return Err(ValidationError::new(ErrorKind::MalformedCertificateInChain, index));
+            }
+        }
+    }
+    Ok(certs)
+}
+
+// When the platform rejects a chain without saying why, or only says that an issuer
+// can't issue certificates, looks for a certificate authority in the chain we were
+// given that breaks one of the rules for issuers, and reports that instead.
+fn diagnose(encoded_certs: &[&[u8]], error: ValidationError) -> ValidationError {
+    match error.kind() {
+        ErrorKind::NotTrusted | ErrorKind::NotCertificateAuthority => {}
+        _ => return error,
+    }
+    let certs = match encoded_certs.iter().map(|cert| Certificate::parse(cert)).collect::<Result<Vec<_>, _>>() {
+        Ok(certs) => certs,
+        Err(_) => return error,
+    };
+    let path = issuers::given_path(&certs);
+    let path_certs = path.iter().map(|&index| &certs[index]).collect::<Vec<_>>();
+    // A self-signed certificate at the end of the path is a root, which is trusted
+    // for what it is.
+    let check_last = path_certs.last().is_some_and(|last| !last.is_self_issued());
+    match issuers::check_path(&path_certs, check_last) {
+        Ok(()) => error,
+        Err((violation, kind)) => ValidationError::at(kind, path[violation]),
+    }
+}
+
+// Whether the stapled response can be relied on for the leaf of the chain we were
+// given, by one of the certificates in it that could have issued the leaf.
+fn relies_on_staple(encoded_certs: &[&[u8]], response: &[u8], time: i64) -> bool {
+    let leaf = match encoded_certs.first().map(|leaf| Certificate::parse(leaf)) {
+        Some(Ok(leaf)) => leaf,
+        _ => return false,
+    };
+    encoded_certs[1..].iter()
+        .filter_map(|cert| Certificate::parse(cert).ok())
+        .any(|issuer| ocsp::leaf_status(response, &leaf, &issuer, time).is_some())
+}
+
+// Whether any certificate in the chain names an issuer that isn't in it, and isn't
+// self-issued. Certificates that can't be parsed are left for the backend to report.
+fn has_missing_issuers(encoded_certs: &[&[u8]]) -> bool {
+    let certs = encoded_certs.iter().filter_map(|cert| Certificate::parse(cert).ok()).collect::<Vec<_>>();
+    !issuers::missing_issuers(&certs).is_empty()
+}
+
+// The position of a certificate from the verified chain in the chain we were given,
+// if it was given at all.
+fn position(encoded_certs: &[&[u8]], encoded_cert: &[u8]) -> Option<usize> {
+    encoded_certs.iter().position(|&encoded| encoded == encoded_cert)
+}
+
+
+impl Default for Verifier {
+    fn default() -> Verifier {
+        Verifier::builder().build()
+    }
+}
+
+
+/// Builds a `Verifier`.
+///
+/// Each method sets one part of the policy, and returns the builder so that calls
+/// can be chained. Anything that isn't set keeps its default.
+///
+/// Not every platform can honour every part of the policy. Where a platform can't,
+/// validation fails with `ErrorKind::MissingFunctionality` rather than quietly
+/// ignoring it.
+#[derive(Debug, Clone)]
+pub struct VerifierBuilder {
+    verifier: Verifier,
+    // The capacity of the intermediate cache, which is made afresh for each verifier
+    // built.
+    cache_capacity: Option<usize>,
+}
+
+impl VerifierBuilder {
+    /// Trust only the given DER-encoded trust anchors, and not the system's.
+    ///
+    /// As with `validate_cert_chain_with_anchors`, anchors that can't be parsed are
+    /// ignored.
+    pub fn anchors(mut self, anchors: &[&[u8]]) -> VerifierBuilder {
+        self.verifier.system_anchors = false;
+        self.verifier.anchors = anchors.iter().map(|anchor| anchor.to_vec()).collect();
+        self
+    }
+
+    /// Trust the given DER-encoded trust anchors as well as the system's.
+    ///
+    /// This replaces any anchors given to `anchors`.
+    pub fn additional_anchors(mut self, anchors: &[&[u8]]) -> VerifierBuilder {
+        self.verifier.system_anchors = true;
+        self.verifier.anchors = anchors.iter().map(|anchor| anchor.to_vec()).collect();
+        self
+    }
+
+    /// Validate as though it were the given time, rather than now.
+    pub fn time(mut self, time: SystemTime) -> VerifierBuilder {
+        self.verifier.time = Some(time);
+        self
+    }
+
+    /// Require the leaf certificate to be usable for the given purpose. By default
+    /// it must be usable by a TLS server.
+    pub fn purpose(mut self, purpose: KeyPurpose) -> VerifierBuilder {
+        self.verifier.purpose = purpose;
+        self
+    }
+
+    /// Set whether to check that certificates haven't been revoked. By default
+    /// revocation isn't checked.
+    pub fn revocation(mut self, revocation: RevocationMode) -> VerifierBuilder {
+        self.verifier.revocation = revocation;
+        self
+    }
+
+    /// Check revocation against the given DER-encoded CRLs, rather than asking the
+    /// platform, whenever revocation is checked at all. This replaces any CRLs given
+    /// before, and by default there are none.
+    ///
+    /// Every certificate in the chain below the trust anchor is checked, against the
+    /// CRLs that its issuer in the chain signed and that are current at the time of
+    /// validation. Nothing is fetched, so this works where the platform can't check
+    /// revocation, or mustn't go online to do so. A revoked certificate fails with
+    /// `ErrorKind::Revoked`, and with `RevocationMode::Required`, one that no CRL
+    /// covers fails with `ErrorKind::RevocationStatusUnknown`. CRLs that can't be
+    /// parsed, or that only cover some of what their issuer issued, are ignored.
+    pub fn crls(mut self, crls: &[&[u8]]) -> VerifierBuilder {
+        self.verifier.crls = crls.iter().map(|crl| crl.to_vec()).collect();
+        self
+    }
+
+    /// Reject chains containing an RSA key with a modulus smaller than `bits`. The
+    /// trust anchor's key counts too. By default, whatever the platform accepts is
+    /// accepted.
+    ///
+    /// This is the same as setting the minimum on the algorithm policy.
+    pub fn minimum_rsa_bits(mut self, bits: usize) -> VerifierBuilder {
+        self.verifier.algorithms = self.verifier.algorithms.minimum_rsa_bits(bits);
+        self
+    }
+
+    /// Reject chains that use algorithms or keys the policy doesn't allow. This
+    /// replaces the whole algorithm policy, including any minimum RSA key size set
+    /// before. By default, whatever the platform accepts is accepted.
+    pub fn algorithm_policy(mut self, policy: AlgorithmPolicy) -> VerifierBuilder {
+        self.verifier.algorithms = policy;
+        self
+    }
+
+    /// Set whether to check that the leaf certificate is valid for the hostname. This
+    /// is on by default, and should only be turned off when something else checks
+    /// the certificate's identity.
+    pub fn check_hostname(mut self, check: bool) -> VerifierBuilder {
+        self.verifier.check_hostname = check;
+        self
+    }
+
+    /// Set whether a leaf certificate without any DNS names may be matched against
+    /// the hostname by its subject's common name. RFC 6125 permits this, but it is
+    /// deprecated and off by default.
+    pub fn common_name_fallback(mut self, fallback: bool) -> VerifierBuilder {
+        self.verifier.common_name_fallback = fallback;
+        self
+    }
+
+    /// Require the leaf certificate to have at least one of the given names, as a
+    /// server might require of its clients. This replaces any names given before, and
+    /// by default no name is required.
+    ///
+    /// Names are checked whether or not the hostname is, but are most useful with
+    /// `Verifier::client_builder` and `verify_client`.
+    pub fn client_names(mut self, names: &[ClientName]) -> VerifierBuilder {
+        self.verifier.client_names = names.to_vec();
+        self
+    }
+
+    /// Fetch the certificates of issuers missing from a chain with the given fetcher,
+    /// from the URLs the certificates they issued give for them. By default nothing
+    /// is fetched, and a chain missing an intermediate is not trusted.
+    ///
+    /// Fetched certificates are only used to build the chain: like the trust anchors,
+    /// they have no position in the chain that was passed in, so
+    /// `ValidationError::certificate` is `None` for them.
+    pub fn issuer_fetcher(mut self, fetcher: Arc<dyn IssuerFetcher>) -> VerifierBuilder {
+        self.verifier.fetcher = Some(SharedFetcher(fetcher));
+        self
+    }
+
+    /// Remember the intermediates of chains that validate, up to `capacity` of them,
+    /// and use them to complete later chains that are missing an intermediate, as
+    /// the platform verifiers do. Remembered intermediates are tried before any are
+    /// fetched. By default nothing is remembered.
+    ///
+    /// Each verifier built has a cache of its own, which only its clones share. Once
+    /// the cache is full, the intermediates verified least recently are forgotten.
+    /// Like fetched intermediates, remembered ones have no position in the chain that
+    /// was passed in.
+    pub fn intermediate_cache(mut self, capacity: usize) -> VerifierBuilder {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+
+    // Trust the anchors as the free functions describe them.
+    pub(crate) fn trust_anchors(self, anchors: TrustAnchors) -> VerifierBuilder {
+        match anchors {
+            TrustAnchors::System => self,
+            TrustAnchors::Only(anchors) => self.anchors(anchors),
+            TrustAnchors::SystemAnd(anchors) => self.additional_anchors(anchors),
+        }
+    }
+
+    /// Builds the verifier.
+    pub fn build(self) -> Verifier {
+        Verifier {
+            system_store: SystemTrustStore::default(),
+            cache: self.cache_capacity.map(|capacity| Arc::new(IntermediateCache::new(capacity))),
+            ..self.verifier
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use algorithms::{Algorithm, AlgorithmPolicy, Curve, SignatureAlgorithm};
+    use chain::VerifiedChain;
+    use error::{ErrorKind, ValidationError};
+    use test::{self_signed_chain, test_pki_chain, test_pki_root, MemoryFetcher};
+    use TrustAnchors;
+    use client::ClientName;
+    use crl::RevocationReason;
+    use ocsp::OcspStatus;
+    use platform;
+    use verifier::{KeyPurpose, Policy, RevocationMode, Verifier};
+    use ValidationResult;
+
+    #[test]
+    fn can_validate_with_anchors() {
+        let chain = test_pki_chain();
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).build();
+        let verified = verifier.verify(&chain, "certitude.test").unwrap();
+        assert_eq!(verified.anchor(), test_pki_root());
+        assert_eq!(verifier.validate(&chain, "lukasa.co.uk"), ValidationResult::NotTrusted);
+
+        let verifier = Verifier::builder().anchors(&[self_signed_chain()[0]]).build();
+        assert_eq!(verifier.validate(&chain, "certitude.test"), ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn can_validate_with_additional_anchors() {
+        let chain = test_pki_chain();
+        let verifier = Verifier::builder().additional_anchors(&[test_pki_root()]).build();
+        assert_eq!(verifier.validate(&chain, "certitude.test"), ValidationResult::Trusted);
+        assert_eq!(Verifier::default().validate(&chain, "certitude.test"), ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn can_skip_hostname_check() {
+        let chain = test_pki_chain();
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).check_hostname(false).build();
+        assert_eq!(verifier.validate(&chain, "lukasa.co.uk"), ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn checks_hostname_before_the_chain() {
+        // The chain isn't trusted either, but the hostname is what's reported.
+        let chain = test_pki_chain();
+        let error = Verifier::default().verify(&chain, "lukasa.co.uk");
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).build();
+        assert_eq!(verifier.validate(&chain, "www.certitude.test"), ValidationResult::Trusted);
+        assert_eq!(verifier.validate(&chain, "a.b.certitude.test"), ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn reports_malformed_hostname_whatever_the_chain() {
+        let malformed = Err(ValidationError::new(ErrorKind::MalformedHostname, None));
+        assert_eq!(Verifier::default().verify(&test_pki_chain(), "certitude..test"), malformed);
+        assert_eq!(Verifier::default().verify(&[], "certitude..test"), malformed);
+        assert_eq!(Verifier::default().verify(&[b"not a certificate"], "certitude..test"), malformed);
+
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).check_hostname(false).build();
+        assert_eq!(verifier.validate(&test_pki_chain(), ""), ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn can_fall_back_to_common_name() {
+        let leaf = include_bytes!("../fixtures/hostname/common-name-only.crt");
+        let chain = [&leaf[..], test_pki_chain()[1]];
+        let verifier = Verifier::builder().trust_anchors(TrustAnchors::Only(&[test_pki_root()]));
+        let error = verifier.clone().build().verify(&chain, "legacy.certitude.test");
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::HostnameMismatch, 0)));
+
+        let verifier = verifier.common_name_fallback(true).build();
+        assert_eq!(verifier.validate(&chain, "legacy.certitude.test"), ValidationResult::Trusted);
+        assert_eq!(verifier.validate(&chain, "certitude.test"), ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn can_validate_ip_addresses() {
+        let leaf = include_bytes!("../fixtures/hostname/ip-addresses.crt");
+        let chain = [&leaf[..], test_pki_chain()[1]];
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).build();
+        assert_eq!(verifier.validate(&chain, "10.0.0.1"), ValidationResult::Trusted);
+        assert_eq!(verifier.validate(&chain, "[fd00::1]"), ValidationResult::Trusted);
+        assert_eq!(verifier.validate(&chain, "10.0.0.2"), ValidationResult::NotTrusted);
+        assert_eq!(verifier.validate(&chain, "[10.0.0.1]"), ValidationResult::MalformedHostname);
+    }
+
+    #[test]
+    fn checks_purpose() {
+        // The test leaf is only for TLS servers.
+        let chain = test_pki_chain();
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).check_hostname(false);
+        let error = verifier.clone().purpose(KeyPurpose::ClientAuth).build().verify(&chain, "");
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::InvalidUsage, 0)));
+
+        // Each of these leaves is only for its own purpose.
+        let leaves: [(&[u8], KeyPurpose); 5] = [
+            (include_bytes!("../fixtures/key-purpose/client-auth.crt"), KeyPurpose::ClientAuth),
+            (include_bytes!("../fixtures/key-purpose/code-signing.crt"), KeyPurpose::CodeSigning),
+            (include_bytes!("../fixtures/key-purpose/email-protection.crt"), KeyPurpose::EmailProtection),
+            (include_bytes!("../fixtures/key-purpose/time-stamping.crt"), KeyPurpose::TimeStamping),
+            (include_bytes!("../fixtures/key-purpose/ocsp-signing.crt"), KeyPurpose::Other("1.3.6.1.5.5.7.3.9".to_owned())),
+        ];
+        for &(leaf, ref purpose) in &leaves {
+            let chain = [leaf, test_pki_chain()[1]];
+            let valid = verifier.clone().purpose(purpose.clone()).build().validate(&chain, "");
+            assert_eq!(valid, ValidationResult::Trusted, "{:?}", purpose);
+            let error = verifier.clone().build().verify(&chain, "");
+            assert_eq!(error, Err(ValidationError::at(ErrorKind::InvalidUsage, 0)), "{:?}", purpose);
+        }
+    }
+
+    #[test]
+    fn checks_key_usage_for_the_purpose() {
+        // The leaf's extended key usage allows both, but its key may only agree keys,
+        // which is no use for signing code.
+        let leaf = include_bytes!("../fixtures/key-purpose/key-agreement-only.crt");
+        let chain = [&leaf[..], test_pki_chain()[1]];
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).check_hostname(false);
+        let valid = verifier.clone().purpose(KeyPurpose::ClientAuth).build().validate(&chain, "");
+        assert_eq!(valid, ValidationResult::Trusted);
+        let error = verifier.purpose(KeyPurpose::CodeSigning).build().verify(&chain, "");
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::InvalidKeyUsage, 0)));
+    }
+
+    #[test]
+    fn checks_key_usage_suits_tls_key_exchange() {
+        // An EC key can't encipher the premaster secret, and a key that may only sign
+        // CRLs is no use to a TLS server at all.
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).check_hostname(false).build();
+        let leaves: [&[u8]; 2] = [
+            include_bytes!("../fixtures/issuers/ec-key-encipherment.crt"),
+            include_bytes!("../fixtures/issuers/crl-sign-only.crt"),
+        ];
+        for &leaf in &leaves {
+            let error = verifier.verify(&[leaf, test_pki_chain()[1]], "");
+            assert_eq!(error, Err(ValidationError::at(ErrorKind::InvalidKeyUsage, 0)));
+        }
+    }
+
+    #[test]
+    fn reports_each_issuer_violation() {
+        let leaf = include_bytes!("../fixtures/issuers/leaf.crt");
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).build();
+        let cases: [(&[u8], ErrorKind); 3] = [
+            (include_bytes!("../fixtures/issuers/not-ca.crt"), ErrorKind::NotCertificateAuthority),
+            (include_bytes!("../fixtures/issuers/no-basic-constraints.crt"), ErrorKind::NotCertificateAuthority),
+            (include_bytes!("../fixtures/issuers/no-key-cert-sign.crt"), ErrorKind::KeyCertSignNotAllowed),
+        ];
+        for &(intermediate, kind) in &cases {
+            let error = verifier.verify(&[&leaf[..], intermediate], "misissued.certitude.test");
+            assert_eq!(error, Err(ValidationError::at(kind, 1)));
+        }
+    }
+
+    #[test]
+    fn reports_exceeded_path_length() {
+        // The test intermediate has a path length of zero, so can't issue another CA.
+        let leaf = include_bytes!("../fixtures/issuers/leaf-of-sub-ca.crt");
+        let sub_ca = include_bytes!("../fixtures/issuers/sub-ca.crt");
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).build();
+        let error = verifier.verify(&[&leaf[..], sub_ca, test_pki_chain()[1]], "sub.certitude.test");
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::PathLengthExceeded, 2)));
+    }
+
+    #[test]
+    fn accepts_any_purpose() {
+        let leaf = include_bytes!("../fixtures/key-purpose/any-purpose.crt");
+        let chain = [&leaf[..], test_pki_chain()[1]];
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).check_hostname(false);
+        for purpose in [KeyPurpose::CodeSigning, KeyPurpose::EmailProtection, KeyPurpose::Other("1.2.3.4".to_owned())] {
+            let valid = verifier.clone().purpose(purpose.clone()).build().validate(&chain, "");
+            assert_eq!(valid, ValidationResult::Trusted, "{:?}", purpose);
+        }
+    }
+
+    #[test]
+    fn refuses_invalid_purposes() {
+        let leaf = include_bytes!("../fixtures/key-purpose/any-purpose.crt");
+        let chain = [&leaf[..], test_pki_chain()[1]];
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).check_hostname(false);
+        for &oid in &["", "serverAuth", "1", "1.3.6.01", "4.1.2"] {
+            let error = verifier.clone().purpose(KeyPurpose::Other(oid.to_owned())).build().verify(&chain, "");
+            assert_eq!(error, Err(ValidationError::at(ErrorKind::InvalidUsage, 0)), "{}", oid);
+        }
+    }
+
+    #[test]
+    fn can_validate_clients() {
+        let leaf = include_bytes!("../fixtures/client/gateway.crt");
+        let chain = [&leaf[..], test_pki_chain()[1]];
+        let verifier = Verifier::client_builder().anchors(&[test_pki_root()]).build();
+        let verified = verifier.verify_client(&chain).unwrap();
+        assert_eq!(verified.anchor(), test_pki_root());
+
+        // A TLS server's certificate won't do.
+        let error = verifier.verify_client(&test_pki_chain());
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::InvalidUsage, 0)));
+
+        // Nor will a client certificate for a server.
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).check_hostname(false).build();
+        assert_eq!(verifier.verify_client(&chain), Err(ValidationError::at(ErrorKind::InvalidUsage, 0)));
+    }
+
+    #[test]
+    fn ignores_hostname_for_clients() {
+        let leaf = include_bytes!("../fixtures/client/gateway.crt");
+        let chain = [&leaf[..], test_pki_chain()[1]];
+        let verifier = Verifier::client_builder().anchors(&[test_pki_root()]).check_hostname(true).build();
+        assert_eq!(verifier.validate_client(&chain), ValidationResult::Trusted);
+        assert_eq!(verifier.validate(&chain, "gateway.certitude.test"), ValidationResult::Trusted);
+        assert_eq!(verifier.validate(&chain, "certitude.test"), ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn checks_client_names() {
+        let leaf = include_bytes!("../fixtures/client/gateway.crt");
+        let chain = [&leaf[..], test_pki_chain()[1]];
+        let verifier = Verifier::client_builder().anchors(&[test_pki_root()]);
+
+        let names = [ClientName::Uri("spiffe://certitude.test/ns/prod/*".to_owned())];
+        let valid = verifier.clone().client_names(&names).build().validate_client(&chain);
+        assert_eq!(valid, ValidationResult::Trusted);
+        let names = [ClientName::Email("@certitude.test".to_owned())];
+        let valid = verifier.clone().client_names(&names).build().validate_client(&chain);
+        assert_eq!(valid, ValidationResult::Trusted);
+
+        // The name is checked before the chain, which isn't trusted here either.
+        let names = [
+            ClientName::Uri("spiffe://certitude.test/ns/dev/*".to_owned()),
+            ClientName::Email("admin@certitude.test".to_owned()),
+        ];
+        let error = verifier.client_names(&names).build().verify_client(&chain[..1]);
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::ClientNameMismatch, 0)));
+    }
+
+    #[test]
+    fn validates_at_the_given_time() {
+        // The test leaf is valid from 2024 until 2044.
+        let chain = test_pki_chain();
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]);
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        let valid = verifier.clone().time(at(1_700_000_000)).build().validate(&chain, "certitude.test");
+        assert_eq!(valid, ValidationResult::NotTrusted);
+        let valid = verifier.clone().time(at(1_800_000_000)).build().validate(&chain, "certitude.test");
+        assert_eq!(valid, ValidationResult::Trusted);
+        let valid = verifier.time(at(2_400_000_000)).build().validate(&chain, "certitude.test");
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn rejects_small_rsa_keys() {
+        // The test root has a 2048-bit RSA key: everything else is ECDSA.
+        let chain = test_pki_chain();
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).minimum_rsa_bits(2048).build();
+        assert_eq!(verifier.validate(&chain, "certitude.test"), ValidationResult::Trusted);
+
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).minimum_rsa_bits(3072).build();
+        let expected = ValidationError::new(ErrorKind::WeakKey, None).with_algorithm(Algorithm::RsaKey(2048));
+        assert_eq!(verifier.verify(&chain, "certitude.test"), Err(expected));
+    }
+
+    #[test]
+    fn checks_algorithm_policy() {
+        // The intermediate's key is on P-384, and it's signed with RSA.
+        let chain = test_pki_chain();
+        let policy = AlgorithmPolicy::new().curves(&[Curve::P256]);
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).algorithm_policy(policy).build();
+        let expected = ValidationError::at(ErrorKind::DisallowedAlgorithm, 1).with_algorithm(Algorithm::Curve(Curve::P384));
+        assert_eq!(verifier.verify(&chain, "certitude.test"), Err(expected));
+
+        let policy = AlgorithmPolicy::new().signature_algorithms(&[SignatureAlgorithm::Ecdsa]);
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).algorithm_policy(policy).build();
+        let expected = ValidationError::at(ErrorKind::DisallowedAlgorithm, 1)
+            .with_algorithm(Algorithm::Signature(SignatureAlgorithm::RsaPkcs1));
+        assert_eq!(verifier.verify(&chain, "certitude.test"), Err(expected));
+
+        // A policy replaces the minimum RSA key size set before it.
+        let verifier = Verifier::builder().anchors(&[test_pki_root()])
+                                          .minimum_rsa_bits(3072)
+                                          .algorithm_policy(AlgorithmPolicy::new())
+                                          .build();
+        assert_eq!(verifier.validate(&chain, "certitude.test"), ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn fetches_missing_intermediates() {
+        let leaf = include_bytes!("../fixtures/aia/leaf.crt");
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]);
+        assert_eq!(verifier.clone().build().validate(&[leaf], "certitude.test"), ValidationResult::NotTrusted);
+
+        let fetcher = Arc::new(MemoryFetcher::with_intermediate());
+        let verified = verifier.clone().issuer_fetcher(fetcher.clone()).build().verify(&[leaf], "certitude.test").unwrap();
+        assert_eq!(verified.certificates(), &[leaf.to_vec(), test_pki_chain()[1].to_vec(), test_pki_root().to_vec()]);
+        assert_eq!(fetcher.requests(), vec!["http://certitude.test/intermediate.crt"]);
+
+        // The intermediate may come in a PKCS#7 bundle.
+        let leaf = include_bytes!("../fixtures/aia/leaf-p7c.crt");
+        let verifier = verifier.issuer_fetcher(Arc::new(MemoryFetcher::with_intermediate())).build();
+        assert_eq!(verifier.validate(&[leaf], "certitude.test"), ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn fetches_only_when_an_issuer_is_missing() {
+        let leaf = include_bytes!("../fixtures/aia/leaf.crt");
+        let fetcher = Arc::new(MemoryFetcher::with_intermediate());
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).issuer_fetcher(fetcher.clone()).build();
+        assert_eq!(verifier.validate(&[leaf, test_pki_chain()[1]], "certitude.test"), ValidationResult::Trusted);
+        assert_eq!(verifier.validate(&[leaf], "lukasa.co.uk"), ValidationResult::NotTrusted);
+        assert!(fetcher.requests().is_empty());
+
+        // Nor when the chain is rejected for some other reason than a missing issuer,
+        // where the platform says why.
+        let rejected = |_: &[&[u8]], _: &Policy| Err(ErrorKind::NotTrusted.into());
+        assert_eq!(verifier.verify_with(&[leaf], "certitude.test", rejected), Err(ErrorKind::NotTrusted.into()));
+        assert_eq!(fetcher.requests().is_empty(), platform::REPORTS_UNKNOWN_ISSUER);
+        let expired = |_: &[&[u8]], _: &Policy| Err(ValidationError::at(ErrorKind::Expired, 0));
+        let fetcher = Arc::new(MemoryFetcher::with_intermediate());
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).issuer_fetcher(fetcher.clone()).build();
+        assert_eq!(verifier.verify_with(&[leaf], "certitude.test", expired), Err(ValidationError::at(ErrorKind::Expired, 0)));
+        assert!(fetcher.requests().is_empty());
+    }
+
+    #[test]
+    fn does_not_blame_fetched_certificates() {
+        // The intermediate is served cross-signed by a bridge that can't be found,
+        // which leaves it with a missing issuer of its own.
+        let leaf = include_bytes!("../fixtures/aia/leaf.crt");
+        let cross_signed = include_bytes!("../fixtures/cross-signed/intermediate-by-bridge.crt");
+        let fetcher = Arc::new(MemoryFetcher::new(&[("http://certitude.test/intermediate.crt", cross_signed)]));
+        let verifier = Verifier::builder().anchors(&[test_pki_root()]).issuer_fetcher(fetcher.clone()).build();
+        let error = verifier.verify(&[leaf], "certitude.test").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnknownIssuer);
+        assert_eq!(error.certificate(), None);
+        assert_eq!(fetcher.requests().len(), 1);
+    }
+
+    #[test]
+    fn remembers_intermediates() {
+        let chain = test_pki_chain();
+        let builder = Verifier::builder().anchors(&[test_pki_root()]).intermediate_cache(16);
+        let verifier = builder.clone().build();
+        assert_eq!(verifier.validate(&chain[..1], "certitude.test"), ValidationResult::NotTrusted);
+
+        // A chain that fails after it's built teaches the cache nothing.
+        assert_eq!(verifier.validate(&chain, "lukasa.co.uk"), ValidationResult::NotTrusted);
+        assert_eq!(verifier.validate(&chain[..1], "certitude.test"), ValidationResult::NotTrusted);
+
+        assert_eq!(verifier.validate(&chain, "certitude.test"), ValidationResult::Trusted);
+        let verified = verifier.verify(&chain[..1], "certitude.test").unwrap();
+        assert_eq!(verified.intermediates(), &[chain[1].to_vec()]);
+
+        // Clones share the cache, but other verifiers don't, even from the same builder.
+        assert_eq!(verifier.clone().validate(&chain[..1], "certitude.test"), ValidationResult::Trusted);
+        assert_eq!(builder.build().validate(&chain[..1], "certitude.test"), ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn prefers_remembered_intermediates_to_fetching() {
+        let leaf = include_bytes!("../fixtures/aia/leaf.crt");
+        let fetcher = Arc::new(MemoryFetcher::with_intermediate());
+        let verifier = Verifier::builder().anchors(&[test_pki_root()])
+                                          .intermediate_cache(16)
+                                          .issuer_fetcher(fetcher.clone())
+                                          .build();
+        assert_eq!(verifier.validate(&[leaf], "certitude.test"), ValidationResult::Trusted);
+        assert_eq!(verifier.validate(&[leaf], "certitude.test"), ValidationResult::Trusted);
+        assert_eq!(fetcher.requests().len(), 1);
+    }
+
+    #[test]
+    fn checks_revocation_against_crls() {
+        let chain = test_pki_chain();
+        let intermediate_crl = include_bytes!("../fixtures/crl/intermediate.crl");
+        let revoked_crl = include_bytes!("../fixtures/crl/intermediate-revoked.crl");
+        let root_crl = include_bytes!("../fixtures/crl/root.crl");
+        let verifier = Verifier::builder().anchors(&[test_pki_root()])
+                                          .time(UNIX_EPOCH + Duration::from_secs(1_800_000_000));
+
+        // The platform isn't asked, so this works even where it can't check revocation.
+        let required = verifier.clone().revocation(RevocationMode::Required);
+        let valid = required.clone().crls(&[intermediate_crl, root_crl]).build().validate(&chain, "certitude.test");
+        assert_eq!(valid, ValidationResult::Trusted);
+        let error = required.clone().crls(&[intermediate_crl]).build().verify(&chain, "certitude.test");
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::RevocationStatusUnknown, 1)));
+
+        let best_effort = verifier.clone().revocation(RevocationMode::BestEffort).crls(&[revoked_crl]).build();
+        let expected = ValidationError::at(ErrorKind::Revoked, 0).with_reason(Some(RevocationReason::KeyCompromise));
+        assert_eq!(best_effort.verify(&chain, "certitude.test"), Err(expected));
+        assert_eq!(best_effort.validate(&chain, "certitude.test"), ValidationResult::Revoked);
+
+        // CRLs are only used when revocation is checked.
+        let disabled = verifier.crls(&[revoked_crl]).build();
+        assert_eq!(disabled.validate(&chain, "certitude.test"), ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn checks_stapled_ocsp_responses() {
+        let chain = test_pki_chain();
+        let good = include_bytes!("../fixtures/ocsp/good.der");
+        let revoked = include_bytes!("../fixtures/ocsp/revoked.der");
+        let unknown = include_bytes!("../fixtures/ocsp/unknown.der");
+        let forged = include_bytes!("../fixtures/ocsp/forged.der");
+        let verifier = Verifier::builder().anchors(&[test_pki_root()])
+                                          .time(UNIX_EPOCH + Duration::from_secs(1_800_000_000));
+
+        // The status is reported even when revocation isn't checked.
+        let disabled = verifier.clone().build();
+        let verified = disabled.verify_stapled(&chain, "certitude.test", good).unwrap();
+        assert_eq!(verified.ocsp_status(), Some(OcspStatus::Good));
+        let verified = disabled.verify_stapled(&chain, "certitude.test", revoked).unwrap();
+        assert_eq!(verified.ocsp_status(), Some(OcspStatus::Revoked(Some(RevocationReason::KeyCompromise))));
+        assert_eq!(disabled.verify(&chain, "certitude.test").unwrap().ocsp_status(), None);
+
+        let best_effort = verifier.clone().revocation(RevocationMode::BestEffort).build();
+        let expected = ValidationError::at(ErrorKind::Revoked, 0).with_reason(Some(RevocationReason::KeyCompromise));
+        assert_eq!(best_effort.verify_stapled(&chain, "certitude.test", revoked), Err(expected));
+        let verified = best_effort.verify_stapled(&chain, "certitude.test", unknown).unwrap();
+        assert_eq!(verified.ocsp_status(), Some(OcspStatus::Unknown));
+        let verified = best_effort.verify_stapled(&chain, "certitude.test", forged).unwrap();
+        assert_eq!(verified.ocsp_status(), None);
+
+        // A good response covers the leaf, but not the intermediate, which the platform
+        // must still check without CRLs.
+        let root_crl = include_bytes!("../fixtures/crl/root.crl");
+        let revoked_crl = include_bytes!("../fixtures/crl/intermediate-revoked.crl");
+        let required = verifier.revocation(RevocationMode::Required);
+        let result = required.clone().crls(&[root_crl]).build().verify_stapled(&chain, "certitude.test", good);
+        assert_eq!(result.unwrap().ocsp_status(), Some(OcspStatus::Good));
+        if cfg!(any(target_os = "linux", feature = "portable")) {
+            let error = required.clone().build().verify_stapled(&chain, "certitude.test", good);
+            assert_eq!(error, Err(ErrorKind::MissingFunctionality.into()));
+        }
+        let error = required.clone().crls(&[root_crl]).build().verify_stapled(&chain, "certitude.test", unknown);
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::RevocationStatusUnknown, 0)));
+        // A CRL that revokes the leaf isn't outweighed by a good response.
+        let error = required.crls(&[revoked_crl, root_crl]).build().verify_stapled(&chain, "certitude.test", good);
+        assert_eq!(error, Err(ValidationError::at(ErrorKind::Revoked, 0).with_reason(Some(RevocationReason::KeyCompromise))));
+    }
+
+    #[test]
+    fn leaves_the_platform_to_check_the_rest_of_a_stapled_chain() {
+        let chain = test_pki_chain();
+        let good = include_bytes!("../fixtures/ocsp/good.der");
+        let forged = include_bytes!("../fixtures/ocsp/forged.der");
+        let verifier = Verifier::builder().anchors(&[test_pki_root()])
+                                          .time(UNIX_EPOCH + Duration::from_secs(1_800_000_000));
+        let asked = RefCell::new(Vec::new());
+        let backend = |certs: &[&[u8]], policy: &Policy| {
+            asked.borrow_mut().push((policy.revocation, policy.ocsp_response.map(|response| response.to_vec())));
+            let mut path = certs.iter().map(|cert| cert.to_vec()).collect::<Vec<_>>();
+            path.push(test_pki_root().to_vec());
+            Ok(VerifiedChain::new(path).unwrap())
+        };
+
+        // The platform still checks revocation, and is given only a response we rely on.
+        let best_effort = verifier.clone().revocation(RevocationMode::BestEffort).build();
+        let verified = best_effort.verify_for(&chain, Some("certitude.test"), Some(good), backend).unwrap();
+        assert_eq!(verified.ocsp_status(), Some(OcspStatus::Good));
+        assert!(best_effort.verify_for(&chain, Some("certitude.test"), Some(forged), backend).is_ok());
+        assert!(best_effort.verify_for(&chain, Some("certitude.test"), Some(b"junk"), backend).is_ok());
+        assert_eq!(asked.replace(Vec::new()), vec![
+            (RevocationMode::BestEffort, Some(good.to_vec())),
+            (RevocationMode::BestEffort, None),
+            (RevocationMode::BestEffort, None),
+        ]);
+
+        // Given CRLs, we check the whole chain ourselves, and nor is it given one
+        // when revocation isn't checked at all.
+        let root_crl = include_bytes!("../fixtures/crl/root.crl");
+        let with_crls = verifier.clone().revocation(RevocationMode::Required).crls(&[root_crl]).build();
+        assert!(with_crls.verify_for(&chain, Some("certitude.test"), Some(good), backend).is_ok());
+        assert!(verifier.build().verify_for(&chain, Some("certitude.test"), Some(good), backend).is_ok());
+        assert_eq!(asked.into_inner(), vec![(RevocationMode::Disabled, None), (RevocationMode::Disabled, None)]);
+    }
+}
@@ -1,34 +1,65 @@
 use std::mem;
 use std::ptr;
+use std::slice;
 use std::string::String;
-use std::ffi::OsStr;
 use std::os::raw::{c_void, c_char};
-use std::os::windows::ffi::OsStrExt;
 
 use crypt32::{CertOpenStore, CertCloseStore, CertAddEncodedCertificateToStore,
               CertFreeCertificateContext, CertGetCertificateChain,
-              CertFreeCertificateChain, CertVerifyCertificateChainPolicy};
-use winapi::minwindef::DWORD;
+              CertFreeCertificateChain, CertVerifyCertificateChainPolicy,
+              CertSetCertificateContextProperty};
+use winapi::minwindef::{DWORD, FILETIME};
 use winapi::wincrypt::{PCCERT_CHAIN_CONTEXT, CERT_STORE_PROV_MEMORY, HCERTSTORE,
                        CERT_STORE_DEFER_CLOSE_UNTIL_LAST_FREE_FLAG, PCCERT_CONTEXT,
                        X509_ASN_ENCODING, CERT_STORE_ADD_ALWAYS, CERT_CHAIN_PARA,
                        CERT_CHAIN_POLICY_PARA, CERT_CHAIN_POLICY_STATUS,
-                       CERT_CHAIN_POLICY_SSL, szOID_PKIX_KP_SERVER_AUTH,
-                       szOID_SERVER_GATED_CRYPTO, szOID_SGC_NETSCAPE};
+                       CERT_CHAIN_POLICY_BASE, CERT_CHAIN_POLICY_SSL,
+                       CERT_CHAIN_POLICY_ALLOW_UNKNOWN_CA_FLAG,
+                       CERT_CHAIN_POLICY_IGNORE_ALL_REV_UNKNOWN_FLAGS,
+                       CERT_CHAIN_REVOCATION_CHECK_CHAIN_EXCLUDE_ROOT,
+                       CERT_OCSP_RESPONSE_PROP_ID, CRYPT_DATA_BLOB,
+                       szOID_PKIX_KP_SERVER_AUTH, szOID_PKIX_KP_CLIENT_AUTH,
+                       szOID_PKIX_KP_CODE_SIGNING, szOID_PKIX_KP_EMAIL_PROTECTION,
+                       szOID_PKIX_KP_TIMESTAMP_SIGNING, szOID_SERVER_GATED_CRYPTO,
+                       szOID_SGC_NETSCAPE};
+use winapi::winerror::{HRESULT, CERT_E_EXPIRED, CERT_E_ROLE, CERT_E_PATHLENCONST, CERT_E_CRITICAL,
+                       CERT_E_PURPOSE, CERT_E_MALFORMED, CERT_E_UNTRUSTEDROOT, CERT_E_CHAINING,
+                       CERT_E_REVOKED, CERT_E_CN_NO_MATCH, CERT_E_WRONG_USAGE, CERT_E_INVALID_NAME,
+                       TRUST_E_CERT_SIGNATURE, CRYPT_E_NO_REVOCATION_CHECK,
+                       CRYPT_E_REVOCATION_OFFLINE};
 use winapi::winnt::LPWSTR;
 
-use ValidationResult;
-
-pub fn validate_cert_chain(encoded_certs: &[&[u8]], hostname: &str) -> ValidationResult {
-    let context = match build_cert_context(encoded_certs) {
-        Ok(context) => context,
-        Err(e) => return e,
-    };
-    let chain = match build_chain(context) {
-        Ok(chain) => chain,
-        Err(e) => return e,
+use chain::VerifiedChain;
+use error::{ErrorKind, ValidationError};
+use verifier::{KeyPurpose, Policy, RevocationMode};
+use TrustAnchors;
+
+// Validates the chain against the policy.
+//
+// CryptoAPI has no simple way to change the trusted roots for a single chain, so
+// we give it the caller's anchors alongside the intermediates, tell it not to worry
+// about roots it doesn't trust, and then check for ourselves that the chain it built
+// ends at one of the anchors.
+pub fn verify_chain(encoded_certs: &[&[u8]], policy: &Policy) -> Result<VerifiedChain, ValidationError> {
+    let extra_anchors = match policy.anchors {
+        TrustAnchors::System => &[][..],
+        TrustAnchors::Only(anchors) | TrustAnchors::SystemAnd(anchors) => anchors,
     };
-    verify_chain_against_policy(chain, hostname)
+    let context = build_cert_context(encoded_certs, extra_anchors)?;
+    if let Some(response) = policy.ocsp_response {
+        staple_ocsp_response(&context, response)?;
+    }
+    let chain = build_chain(context, policy)?;
+    match policy.anchors {
+        TrustAnchors::System => verify_chain_against_policy(&chain, encoded_certs, policy, false)?,
+        TrustAnchors::Only(anchors) => verify_chain_against_anchors(&chain, encoded_certs, policy, anchors)?,
+        TrustAnchors::SystemAnd(anchors) => {
+            verify_chain_against_policy(&chain, encoded_certs, policy, false).or_else(|e| {
+                verify_chain_against_anchors(&chain, encoded_certs, policy, anchors).map_err(|_| e)
+            })?
+        }
+    }
+    verified_chain(&chain).ok_or_else(|| ErrorKind::ErrorDuringValidation.into())
 }
 
 
@@ -90,24 +121,43 @@ impl Drop for CertChainContext {
 }
 
 
-// Verify that a given certificate chain meets the security policy.
-fn verify_chain_against_policy(chain_context: CertChainContext, hostname: &str) -> ValidationResult {
-    // To begin, we need to create the policy. The policy is simple: suitable for
-    // SSL, suitable for this host. First, we need the hostname as a null-terminated array of wchar_t.
-    // This bizarre one-liner does that.
-    let mut encoded_host = OsStr::new(hostname).encode_wide().chain(Some(0).into_iter()).collect::<Vec<_>>();
-
-    // Then we need some structs to tell Windows what policy we want.
+// Verify that a given certificate chain meets the security policy. If the root
+// is going to be checked separately, it doesn't have to be one Windows trusts.
+fn verify_chain_against_policy(chain_context: &CertChainContext,
+                               encoded_certs: &[&[u8]],
+                               policy: &Policy,
+                               allow_unknown_root: bool) -> Result<(), ValidationError> {
+    // To begin, we need some structs to tell Windows what policy we want. For TLS,
+    // that's the SSL policy for the right side of the connection. We check the
+    // hostname ourselves, so Windows isn't given one, and without one it doesn't
+    // check. For anything else, the base policy is all there is: the usage was
+    // checked when the chain was built.
+    let (policy_oid, auth_type) = match policy.purpose {
+        KeyPurpose::ServerAuth => (CERT_CHAIN_POLICY_SSL, 2),  // AUTHTYPE_SERVER
+        KeyPurpose::ClientAuth => (CERT_CHAIN_POLICY_SSL, 1),  // AUTHTYPE_CLIENT
+        _ => (CERT_CHAIN_POLICY_BASE, 0),
+    };
     let mut extra_policy = SSL_EXTRA_CERT_CHAIN_POLICY_PARA {
         cbSize: mem::size_of::<SSL_EXTRA_CERT_CHAIN_POLICY_PARA>() as u32,
-        dwAuthType: 2,  // AUTHTYPE_SERVER
+        dwAuthType: auth_type,
         fdwChecks: 0,
-        pwszServerName: encoded_host.as_mut_ptr(),  // This is safe: the function won't modify the buffer.
+        pwszServerName: ptr::null_mut(),
     };
-    let mut policy = CERT_CHAIN_POLICY_PARA {
+    let extra_policy_ptr = match policy_oid {
+        CERT_CHAIN_POLICY_SSL => &mut extra_policy as *mut _ as *mut c_void,
+        _ => ptr::null_mut(),
+    };
+    let mut flags = 0;
+    if allow_unknown_root {
+        flags |= CERT_CHAIN_POLICY_ALLOW_UNKNOWN_CA_FLAG;
+    }
+    if policy.revocation == RevocationMode::BestEffort {
+        flags |= CERT_CHAIN_POLICY_IGNORE_ALL_REV_UNKNOWN_FLAGS;
+    }
+    let mut policy_parameters = CERT_CHAIN_POLICY_PARA {
         cbSize: mem::size_of::<CERT_CHAIN_POLICY_PARA>() as u32,
-        dwFlags: 0,
-        pvExtraPolicyPara: &mut extra_policy as *mut _ as *mut c_void,
+        dwFlags: flags,
+        pvExtraPolicyPara: extra_policy_ptr,
     };
 
     // Finally we need a structure where Windows can tell us the result.
@@ -122,43 +172,136 @@ fn verify_chain_against_policy(chain_context: CertChainContext, hostname: &str)
     // We can now ask Windows to validate.
     unsafe {
         let verified = CertVerifyCertificateChainPolicy(
-            CERT_CHAIN_POLICY_SSL as *const i8,
+            policy_oid as *const i8,
             chain_context.0,
-            &mut policy,
+            &mut policy_parameters,
             &mut result,
         );
 
         if verified == 0 {
-            return ValidationResult::ErrorDuringValidation;
+            return Err(ErrorKind::ErrorDuringValidation.into());
         }
     }
-    // This is probably overbroad: look at the errors in https://msdn.microsoft.com/en-us/library/windows/desktop/aa377188(v=vs.85).aspx
-    // and re-evaluate.
-    match result.dwError {
-        0 => ValidationResult::Trusted,
-        _ => ValidationResult::NotTrusted,
+    if result.dwError == 0 {
+        return Ok(());
+    }
+
+    // Windows tells us which certificate was at fault by its position in the chain it
+    // built, which needn't be the same as its position in the chain we were given.
+    let index = chain_element(chain_context, result.lChainIndex, result.lElementIndex)
+                    .and_then(|cert| encoded_certs.iter().position(|&encoded| encoded == cert));
+    Err(ValidationError::new(policy_error_to_error_kind(result.dwError as HRESULT), index))
+}
+
+
+// Convert the error from the policy check to the reason validation failed. The
+// errors are listed at https://msdn.microsoft.com/en-us/library/windows/desktop/aa377188(v=vs.85).aspx
+fn policy_error_to_error_kind(error: HRESULT) -> ErrorKind {
+    match error {
+        // Windows doesn't distinguish certificates that have expired from those
+        // that aren't valid yet.
+        CERT_E_EXPIRED => ErrorKind::Expired,
+        CERT_E_CN_NO_MATCH => ErrorKind::HostnameMismatch,
+        CERT_E_UNTRUSTEDROOT | CERT_E_CHAINING => ErrorKind::UnknownIssuer,
+        TRUST_E_CERT_SIGNATURE => ErrorKind::BadSignature,
+        CERT_E_REVOKED => ErrorKind::Revoked,
+        CERT_E_ROLE => ErrorKind::NotCertificateAuthority,
+        CERT_E_PATHLENCONST => ErrorKind::PathLengthExceeded,
+        CERT_E_PURPOSE | CERT_E_WRONG_USAGE => ErrorKind::InvalidUsage,
+        CERT_E_CRITICAL => ErrorKind::UnhandledCriticalExtension,
+        CERT_E_INVALID_NAME => ErrorKind::NameConstraintViolation,
+        CERT_E_MALFORMED => ErrorKind::MalformedCertificateInChain,
+        CRYPT_E_NO_REVOCATION_CHECK | CRYPT_E_REVOCATION_OFFLINE => ErrorKind::RevocationStatusUnknown,
+        _ => ErrorKind::NotTrusted,
+    }
+}
+
+
+// Verify that a given certificate chain meets the security policy and ends at one
+// of the given anchors, whether or not Windows trusts it.
+fn verify_chain_against_anchors(chain_context: &CertChainContext,
+                                encoded_certs: &[&[u8]],
+                                policy: &Policy,
+                                anchors: &[&[u8]]) -> Result<(), ValidationError> {
+    verify_chain_against_policy(chain_context, encoded_certs, policy, true)?;
+
+    let root = chain_root(chain_context);
+    match root {
+        Some(root) if anchors.contains(&root) => Ok(()),
+        _ => {
+            let index = root.and_then(|root| encoded_certs.iter().position(|&encoded| encoded == root));
+            Err(ValidationError::new(ErrorKind::UnknownIssuer, index))
+        }
+    }
+}
+
+
+// The chain Windows built, from the leaf to the root.
+fn verified_chain(chain_context: &CertChainContext) -> Option<VerifiedChain> {
+    let mut certificates = Vec::new();
+    while let Some(cert) = chain_element(chain_context, 0, certificates.len() as i32) {
+        certificates.push(cert.to_vec());
+    }
+    VerifiedChain::new(certificates)
+}
+
+
+// The DER encoding of the last certificate in the chain Windows built.
+fn chain_root(chain_context: &CertChainContext) -> Option<&[u8]> {
+    unsafe {
+        let chain = &*chain_context.0;
+        if chain.cChain == 0 {
+            return None;
+        }
+        let simple_chain = &**chain.rgpChain;
+        if simple_chain.cElement == 0 {
+            return None;
+        }
+        chain_element(chain_context, 0, simple_chain.cElement as i32 - 1)
+    }
+}
+
+
+// The DER encoding of an element of the chain Windows built, if it exists.
+fn chain_element(chain_context: &CertChainContext, chain_index: i32, element_index: i32) -> Option<&[u8]> {
+    unsafe {
+        let chain = &*chain_context.0;
+        if chain_index < 0 || chain_index as u32 >= chain.cChain {
+            return None;
+        }
+        let simple_chain = &**chain.rgpChain.offset(chain_index as isize);
+        if element_index < 0 || element_index as u32 >= simple_chain.cElement {
+            return None;
+        }
+        let element = &**simple_chain.rgpElement.offset(element_index as isize);
+        let cert = &*element.pCertContext;
+        Some(slice::from_raw_parts(cert.pbCertEncoded, cert.cbCertEncoded as usize))
     }
 }
 
 
 // Builds a certificate chain context. This tells Windows to build a chain, but
-// doesn't validate that it's acceptable for the host in question.
-fn build_chain(cert_context: CertContext) -> Result<CertChainContext, ValidationResult> {
-    // Define acceptable certificate uses. In this case, we would like to just use SERVER_AUTH, but
-    // Chrome uses SERVER_GATED_CRYPTO and SGC_NETSCAPE because...well, who knows, but let's do that
-    // anyway.
+// doesn't validate that it's acceptable for the host in question. Whether each
+// certificate is valid is decided at the policy's time, and if revocation is to be
+// checked, Windows finds out whether each certificate has been revoked now.
+fn build_chain(cert_context: CertContext, policy: &Policy) -> Result<CertChainContext, ValidationError> {
+    // Define acceptable certificate uses. For a TLS server, we would like to just use SERVER_AUTH,
+    // but Chrome uses SERVER_GATED_CRYPTO and SGC_NETSCAPE because...well, who knows, but let's do
+    // that anyway. Anything else is just the one purpose.
     //
     // We get mutable pointers to these strings and then a mutable pointer to the array, but only
     // because Windows isn't good enough with saying that things are const. These won't change.
     // Annoyingly, though, we have to convince Rust to do it.
-    let mut server_auth: Vec<c_char> = as_cchar_vec!(szOID_PKIX_KP_SERVER_AUTH);
-    let mut server_gated_crypto: Vec<c_char> = as_cchar_vec!(szOID_SERVER_GATED_CRYPTO);
-    let mut sgc_netscape: Vec<c_char> = as_cchar_vec!(szOID_SGC_NETSCAPE);
-    let mut usage = [
-        server_auth.as_mut_ptr(),
-        server_gated_crypto.as_mut_ptr(),
-        sgc_netscape.as_mut_ptr(),
-    ];
+    let identifiers = match policy.purpose {
+        KeyPurpose::ServerAuth => vec![szOID_PKIX_KP_SERVER_AUTH, szOID_SERVER_GATED_CRYPTO, szOID_SGC_NETSCAPE],
+        KeyPurpose::ClientAuth => vec![szOID_PKIX_KP_CLIENT_AUTH],
+        KeyPurpose::CodeSigning => vec![szOID_PKIX_KP_CODE_SIGNING],
+        KeyPurpose::EmailProtection => vec![szOID_PKIX_KP_EMAIL_PROTECTION],
+        KeyPurpose::TimeStamping => vec![szOID_PKIX_KP_TIMESTAMP_SIGNING],
+        KeyPurpose::Other(ref oid) => vec![&oid[..]],
+    };
+    let mut identifiers: Vec<Vec<c_char>> = identifiers.into_iter().map(|oid| as_cchar_vec!(oid)).collect();
+    let mut usage: Vec<*mut c_char> = identifiers.iter_mut().map(|oid| oid.as_mut_ptr()).collect();
 
     let mut chain_parameters: CERT_CHAIN_PARA = unsafe{ mem::zeroed() };
     chain_parameters.RequestedUsage.dwType = 1;  // USAGE_MATCH_TYPE_OR
@@ -166,23 +309,34 @@ fn build_chain(cert_context: CertContext) -> Result<CertChainContext, Validation
     chain_parameters.RequestedUsage.Usage.rgpszUsageIdentifier = usage.as_mut_ptr();
     chain_parameters.cbSize = mem::size_of::<CERT_CHAIN_PARA>() as u32;
 
+    let flags = match policy.revocation {
+        RevocationMode::Disabled => 0,
+        RevocationMode::BestEffort | RevocationMode::Required => CERT_CHAIN_REVOCATION_CHECK_CHAIN_EXCLUDE_ROOT,
+    };
+
+    let mut time = policy.time.map(|_| file_time(policy.unix_time()));
+    let time_ptr = match time {
+        Some(ref mut time) => time as *mut FILETIME,
+        None => ptr::null_mut(),  // use the default system time
+    };
+
     let mut chain_context_ptr = ptr::null();
     unsafe {
         let got_chain = CertGetCertificateChain(
             ptr::null_mut(),  // default engine
             cert_context.0,  // leaf certificate
-            ptr::null_mut(),  // use the default system time
+            time_ptr,  // the time to validate at
             (*(cert_context.0)).hCertStore,  // where to find intermediate certs
             &mut chain_parameters,  // The chain building constraints
-            0,  // no flags
+            flags,
             ptr::null_mut(),  // reserved
             &mut chain_context_ptr
         );
         if got_chain == 0 {
-            return Err(ValidationResult::NotTrusted);
+            return Err(ErrorKind::NotTrusted.into());
         }
         if chain_context_ptr.is_null() {
-            return Err(ValidationResult::ErrorDuringValidation);
+            return Err(ErrorKind::ErrorDuringValidation.into());
         }
     }
     let context = CertChainContext(chain_context_ptr as PCCERT_CHAIN_CONTEXT);
@@ -190,8 +344,41 @@ fn build_chain(cert_context: CertContext) -> Result<CertChainContext, Validation
 }
 
 
-// Builds the certificate chain provided into a certificate store.
-fn build_cert_context(encoded_certs: &[&[u8]]) -> Result<CertContext, ValidationResult> {
+// Converts seconds since the Unix epoch to a FILETIME, which counts 100ns intervals
+// since the start of 1601.
+fn file_time(unix_time: i64) -> FILETIME {
+    let intervals = ((unix_time + 11_644_473_600) * 10_000_000) as u64;
+    FILETIME {
+        dwLowDateTime: intervals as DWORD,
+        dwHighDateTime: (intervals >> 32) as DWORD,
+    }
+}
+
+
+// Attaches the stapled OCSP response to the leaf, so that CryptoAPI checks the leaf's
+// revocation with it rather than going to the responder itself. The intermediates
+// are still checked as ever.
+fn staple_ocsp_response(context: &CertContext, response: &[u8]) -> Result<(), ValidationError> {
+    let blob = CRYPT_DATA_BLOB {
+        cbData: response.len() as DWORD,
+        pbData: response.as_ptr() as *mut u8,
+    };
+    let ok = unsafe {
+        CertSetCertificateContextProperty(context.0, CERT_OCSP_RESPONSE_PROP_ID, 0, &blob as *const _ as *const c_void)
+    };
+    if ok == 0 {
+        return Err(ErrorKind::ErrorDuringValidation.into());
+    }
+    Ok(())
+}
+
+
+// Builds the certificate chain provided, and any extra anchors, into a certificate store.
+fn build_cert_context(encoded_certs: &[&[u8]], anchors: &[&[u8]]) -> Result<CertContext, ValidationError> {
+    if encoded_certs.is_empty() {
+        return Err(ErrorKind::MalformedCertificateInChain.into());
+    }
+
     // Build a backing store, in-memory.
     let store_ptr = unsafe {
         let backing_store = CertOpenStore(
@@ -202,7 +389,7 @@ fn build_cert_context(encoded_certs: &[&[u8]]) -> Result<CertContext, Validation
             ptr::null(),
         );
         if backing_store.is_null() {
-            return Err(ValidationResult::ErrorDuringValidation);
+            return Err(ErrorKind::ErrorDuringValidation.into());
         }
         backing_store
     };
@@ -221,7 +408,7 @@ fn build_cert_context(encoded_certs: &[&[u8]]) -> Result<CertContext, Validation
             &mut primary_cert_ptr,
         );
         if ok == 0 {
-            return Err(ValidationResult::MalformedCertificateInChain);
+            return Err(ErrorKind::MalformedCertificateInChain.into());
         }
     }
     let primary_cert = CertContext(primary_cert_ptr);
@@ -239,25 +426,60 @@ fn build_cert_context(encoded_certs: &[&[u8]]) -> Result<CertContext, Validation
                 ptr::null_mut(),
             );
             if ok == 0 {
-                return Err(ValidationResult::MalformedCertificateInChain);
+                return Err(ErrorKind::MalformedCertificateInChain.into());
             }
         }
     }
 
+    // The anchors go in the same store, so that Windows can find them while
+    // building the chain. Skip any it can't parse, as the other backends do.
+    for anchor in anchors {
+        unsafe {
+            CertAddEncodedCertificateToStore(
+                store.0,
+                X509_ASN_ENCODING,
+                anchor.as_ptr(),
+                anchor.len() as u32,
+                CERT_STORE_ADD_ALWAYS,
+                ptr::null_mut(),
+            );
+        }
+    }
+
     Ok(primary_cert)
 }
 
 #[cfg(test)]
 mod test {
-    use windows::validate_cert_chain;
-    use test::{expired_chain, certifi_chain, self_signed_chain};
+    use chain::VerifiedChain;
+    use error::ValidationError;
+    use windows::verify_chain;
+    use test::{expired_chain, certifi_chain, certifi_time, self_signed_chain, test_pki_chain, test_pki_root};
+    use verifier::{Policy, Verifier};
+    use TrustAnchors;
     use ValidationResult;
 
+    fn verify_cert_chain(encoded_certs: &[&[u8]], hostname: &str, anchors: TrustAnchors) -> Result<VerifiedChain, ValidationError> {
+        Verifier::builder().trust_anchors(anchors).build().verify_with(encoded_certs, hostname, verify_chain)
+    }
+
+    fn validate_cert_chain(encoded_certs: &[&[u8]], hostname: &str) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::System).into()
+    }
+
+    fn validate_cert_chain_with_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::Only(anchors)).into()
+    }
+
+    fn validate_cert_chain_with_additional_anchors(encoded_certs: &[&[u8]], hostname: &str, anchors: &[&[u8]]) -> ValidationResult {
+        verify_cert_chain(encoded_certs, hostname, TrustAnchors::SystemAnd(anchors)).into()
+    }
+
     #[test]
     fn can_validate_good_chain() {
         let chain = certifi_chain();
-        let valid = validate_cert_chain(&chain, "certifi.io");
-        assert_eq!(valid, ValidationResult::Trusted);
+        let policy = Policy { time: Some(certifi_time()), ..Policy::new(TrustAnchors::System) };
+        assert!(verify_chain(&chain, &policy).is_ok());
     }
 
     #[test]
@@ -298,14 +520,55 @@ mod test {
     #[test]
     fn test_fails_on_invalid_asn1() {
         // We need to mutate the first byte of the original cert.
-        let mut chain = certifi_chain();
+        let chain = certifi_chain();
         let mut first_chain = chain[0].to_vec();
         first_chain[0] = 0xff;
         let mut chain_builder = vec![first_chain.as_slice()];
         chain_builder.append(&mut chain[1..].to_vec());
         let new_chain = chain_builder.as_slice();
 
-        let valid = validate_cert_chain(&new_chain, "certifi.io");
+        let valid = validate_cert_chain(new_chain, "certifi.io");
         assert_eq!(valid, ValidationResult::MalformedCertificateInChain);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn can_validate_with_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+    }
+
+    #[test]
+    fn returns_the_verified_chain() {
+        let chain = test_pki_chain();
+        let verified = verify_cert_chain(&chain, "certitude.test", TrustAnchors::Only(&[test_pki_root()])).unwrap();
+        assert_eq!(verified.certificates(), &[chain[0].to_vec(), chain[1].to_vec(), test_pki_root().to_vec()]);
+    }
+
+    #[test]
+    fn fails_without_matching_anchor() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[self_signed_chain()[0]]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+        let valid = validate_cert_chain_with_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn checks_hostname_with_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_anchors(&chain, "lukasa.co.uk", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+
+    #[test]
+    fn can_validate_with_additional_anchors() {
+        let chain = test_pki_chain();
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::Trusted);
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "lukasa.co.uk", &[test_pki_root()]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+        let valid = validate_cert_chain_with_additional_anchors(&chain, "certitude.test", &[]);
+        assert_eq!(valid, ValidationResult::NotTrusted);
+    }
+}
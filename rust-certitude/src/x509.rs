@@ -0,0 +1,647 @@
+// Parsing of X.509 certificates, as profiled by RFC 5280.
+//
+// Certificates are parsed without copying: everything here borrows from the
+// DER encoding it was parsed from. Only the parts of the certificate that the
+// portable validator actually needs are exposed.
+
+use der::{self, Reader};
+
+// Name attributes, as the contents of their DER-encoded OIDs.
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_EMAIL_ADDRESS: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01];
+
+// Extension identifiers, as the contents of their DER-encoded OIDs.
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+const OID_EXTENDED_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const OID_SUBJECT_KEY_IDENTIFIER: &[u8] = &[0x55, 0x1d, 0x0e];
+const OID_AUTHORITY_KEY_IDENTIFIER: &[u8] = &[0x55, 0x1d, 0x23];
+const OID_NAME_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x1e];
+const OID_AUTHORITY_INFO_ACCESS: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x01];
+
+// The access method, in the authority information access extension, for a URL
+// that the certificate's issuer can be fetched from.
+const OID_AD_CA_ISSUERS: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x02];
+
+// Key purposes for the extended key usage extension.
+pub const OID_KP_SERVER_AUTH: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+pub const OID_KP_CLIENT_AUTH: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x02];
+pub const OID_KP_CODE_SIGNING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x03];
+pub const OID_KP_EMAIL_PROTECTION: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x04];
+pub const OID_KP_TIME_STAMPING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x08];
+pub const OID_ANY_EXTENDED_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25, 0x00];
+
+// Bits of the key usage extension.
+pub const KEY_USAGE_DIGITAL_SIGNATURE: u16 = 0x8000;
+pub const KEY_USAGE_NON_REPUDIATION: u16 = 0x4000;
+pub const KEY_USAGE_KEY_ENCIPHERMENT: u16 = 0x2000;
+pub const KEY_USAGE_KEY_AGREEMENT: u16 = 0x0800;
+pub const KEY_USAGE_KEY_CERT_SIGN: u16 = 0x0400;
+pub const KEY_USAGE_CRL_SIGN: u16 = 0x0200;
+
+
+pub struct AlgorithmIdentifier<'a> {
+    pub oid: &'a [u8],
+    // The complete encoding of the parameters, if there are any.
+    pub parameters: Option<&'a [u8]>,
+}
+
+pub struct SubjectPublicKeyInfo<'a> {
+    pub algorithm: AlgorithmIdentifier<'a>,
+    pub public_key: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+pub struct BasicConstraints {
+    pub ca: bool,
+    pub path_len: Option<u32>,
+}
+
+#[derive(Clone, Copy)]
+pub enum GeneralName<'a> {
+    Rfc822Name(&'a [u8]),
+    DnsName(&'a [u8]),
+    // The complete encoding of the name.
+    DirectoryName(&'a [u8]),
+    Uri(&'a [u8]),
+    // The address in network byte order: four bytes for IPv4, sixteen for IPv6. In a
+    // name constraint, the address is followed by a mask of the same length.
+    IpAddress(&'a [u8]),
+    Unsupported,
+}
+
+// The subtrees of the name constraints extension. The minimum and maximum of a
+// subtree are never used, so a subtree that has them is `GeneralName::Unsupported`.
+pub struct NameConstraints<'a> {
+    pub permitted: Option<Vec<GeneralName<'a>>>,
+    pub excluded: Option<Vec<GeneralName<'a>>>,
+}
+
+pub struct Certificate<'a> {
+    // The complete encoding of the certificate.
+    pub encoded: &'a [u8],
+    pub tbs: &'a [u8],
+    pub signature_algorithm: AlgorithmIdentifier<'a>,
+    pub signature: &'a [u8],
+    // The contents of the serial number's INTEGER.
+    pub serial: &'a [u8],
+    // The complete encodings of the issuer and subject names.
+    pub issuer: &'a [u8],
+    pub subject: &'a [u8],
+    // The validity period, in seconds since the Unix epoch.
+    pub not_before: i64,
+    pub not_after: i64,
+    pub spki: SubjectPublicKeyInfo<'a>,
+    pub basic_constraints: Option<BasicConstraints>,
+    pub key_usage: Option<u16>,
+    pub extended_key_usage: Option<Vec<&'a [u8]>>,
+    pub name_constraints: Option<NameConstraints<'a>>,
+    // Whether the certificate carries a critical extension we don't understand.
+    pub unhandled_critical_extension: bool,
+    // The URLs that the issuer's certificate can be fetched from, as the authority
+    // information access extension gives them.
+    pub ca_issuers: Vec<&'a [u8]>,
+    subject_alt_names: Option<&'a [u8]>,
+}
+
+impl<'a> Certificate<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Certificate<'a>, der::Error> {
+        let mut outer = Reader::new(data);
+        let encoded = outer.read_element(der::SEQUENCE)?;
+        outer.finish()?;
+
+        let mut cert = Reader::new(der::parse(encoded, der::SEQUENCE)?);
+        let tbs = cert.read_element(der::SEQUENCE)?;
+        let signature_algorithm = parse_algorithm(&mut cert)?;
+        let signature = cert.read_bit_string()?;
+        cert.finish()?;
+
+        let mut tbs_reader = Reader::new(der::parse(tbs, der::SEQUENCE)?);
+        let version = match tbs_reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)? {
+            Some(version) => Reader::new(version).read_small_uint()?,
+            None => 0,
+        };
+        if version > 2 {
+            return Err(der::Error);
+        }
+        // RFC 5280 requires serial numbers to be positive, but CAs have issued negative
+        // ones, so all we insist on is that there is one.
+        let serial = tbs_reader.read(der::INTEGER)?;
+        if serial.is_empty() {
+            return Err(der::Error);
+        }
+
+        // The signature algorithm is repeated inside the signed data, and the two
+        // copies must agree.
+        let inner_algorithm = parse_algorithm(&mut tbs_reader)?;
+        if inner_algorithm.oid != signature_algorithm.oid ||
+           inner_algorithm.parameters != signature_algorithm.parameters {
+            return Err(der::Error);
+        }
+
+        let issuer = tbs_reader.read_element(der::SEQUENCE)?;
+        let mut validity = Reader::new(tbs_reader.read(der::SEQUENCE)?);
+        let not_before = parse_time(&mut validity)?;
+        let not_after = parse_time(&mut validity)?;
+        validity.finish()?;
+        let subject = tbs_reader.read_element(der::SEQUENCE)?;
+
+        let mut spki = Reader::new(tbs_reader.read(der::SEQUENCE)?);
+        let spki_algorithm = parse_algorithm(&mut spki)?;
+        let public_key = spki.read_bit_string()?;
+        spki.finish()?;
+
+        // We have no use for the unique identifiers, but they may be present.
+        tbs_reader.read_optional(der::CONTEXT_SPECIFIC | 1)?;
+        tbs_reader.read_optional(der::CONTEXT_SPECIFIC | 2)?;
+
+        let mut certificate = Certificate {
+            encoded: data,
+            tbs,
+            signature_algorithm,
+            signature,
+            serial,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            spki: SubjectPublicKeyInfo {
+                algorithm: spki_algorithm,
+                public_key,
+            },
+            basic_constraints: None,
+            key_usage: None,
+            extended_key_usage: None,
+            name_constraints: None,
+            unhandled_critical_extension: false,
+            ca_issuers: Vec::new(),
+            subject_alt_names: None,
+        };
+
+        if let Some(extensions) = tbs_reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED | 3)? {
+            if version != 2 {
+                return Err(der::Error);
+            }
+            certificate.parse_extensions(der::parse(extensions, der::SEQUENCE)?)?;
+        }
+        tbs_reader.finish()?;
+
+        Ok(certificate)
+    }
+
+    pub fn is_self_issued(&self) -> bool {
+        same_name(self.issuer, self.subject)
+    }
+
+    // Returns the entries of the subject alternative name extension, or `None`
+    // if the certificate doesn't have one.
+    pub fn subject_alt_names(&self) -> Option<Vec<GeneralName<'a>>> {
+        // The extension was validated when the certificate was parsed.
+        self.subject_alt_names.map(|names| parse_general_names(names).unwrap_or_default())
+    }
+
+    // Returns the common names in the subject, in the order they appear, leaving out
+    // any that aren't in one of the ASCII-compatible string types.
+    pub fn common_names(&self) -> Vec<&'a [u8]> {
+        parse_attributes(self.subject, OID_COMMON_NAME).unwrap_or_default()
+    }
+
+    // Returns the email addresses in the subject, in the order they appear.
+    pub fn email_addresses(&self) -> Vec<&'a [u8]> {
+        parse_attributes(self.subject, OID_EMAIL_ADDRESS).unwrap_or_default()
+    }
+
+    fn parse_extensions(&mut self, extensions: &'a [u8]) -> Result<(), der::Error> {
+        let mut seen = Vec::new();
+        let mut reader = Reader::new(extensions);
+        while !reader.is_empty() {
+            let mut extension = Reader::new(reader.read(der::SEQUENCE)?);
+            let oid = extension.read(der::OID)?;
+            let critical = if extension.peek_tag() == Some(der::BOOLEAN) {
+                extension.read_bool()?
+            } else {
+                false
+            };
+            let value = extension.read(der::OCTET_STRING)?;
+            extension.finish()?;
+
+            if seen.contains(&oid) {
+                return Err(der::Error);
+            }
+            seen.push(oid);
+
+            match oid {
+                OID_BASIC_CONSTRAINTS => {
+                    self.basic_constraints = Some(parse_basic_constraints(value)?);
+                }
+                OID_KEY_USAGE => {
+                    self.key_usage = Some(parse_key_usage(value)?);
+                }
+                OID_EXTENDED_KEY_USAGE => {
+                    self.extended_key_usage = Some(parse_extended_key_usage(value)?);
+                }
+                OID_SUBJECT_ALT_NAME => {
+                    let names = der::parse(value, der::SEQUENCE)?;
+                    parse_general_names(names)?;
+                    self.subject_alt_names = Some(names);
+                }
+                OID_NAME_CONSTRAINTS => {
+                    self.name_constraints = Some(parse_name_constraints(value)?);
+                }
+                OID_AUTHORITY_INFO_ACCESS => {
+                    self.ca_issuers = parse_ca_issuers(value)?;
+                }
+                OID_SUBJECT_KEY_IDENTIFIER | OID_AUTHORITY_KEY_IDENTIFIER => {}
+                // The certificate is well-formed, but RFC 5280 requires that
+                // validation fails: leave that to the validator.
+                _ if critical => self.unhandled_critical_extension = true,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+
+pub fn parse_algorithm<'a>(reader: &mut Reader<'a>) -> Result<AlgorithmIdentifier<'a>, der::Error> {
+    let mut algorithm = Reader::new(reader.read(der::SEQUENCE)?);
+    let oid = algorithm.read(der::OID)?;
+    let parameters = if algorithm.is_empty() {
+        None
+    } else {
+        let (_, _, element) = algorithm.read_any()?;
+        Some(element)
+    };
+    algorithm.finish()?;
+    Ok(AlgorithmIdentifier { oid, parameters })
+}
+
+
+fn parse_basic_constraints(value: &[u8]) -> Result<BasicConstraints, der::Error> {
+    let mut reader = Reader::new(der::parse(value, der::SEQUENCE)?);
+    let ca = if reader.peek_tag() == Some(der::BOOLEAN) {
+        reader.read_bool()?
+    } else {
+        false
+    };
+    let path_len = if reader.is_empty() {
+        None
+    } else {
+        Some(reader.read_small_uint()?)
+    };
+    reader.finish()?;
+    Ok(BasicConstraints { ca, path_len })
+}
+
+
+// Key usage is a named bit string: we return the first two bytes of it, with the
+// first bit (digitalSignature) as the most significant bit.
+fn parse_key_usage(value: &[u8]) -> Result<u16, der::Error> {
+    let bits = der::parse(value, der::BIT_STRING)?;
+    match bits {
+        [_unused, first] => Ok((*first as u16) << 8),
+        [_unused, first, second] => Ok(((*first as u16) << 8) | *second as u16),
+        _ => Err(der::Error),
+    }
+}
+
+
+fn parse_extended_key_usage(value: &[u8]) -> Result<Vec<&[u8]>, der::Error> {
+    let mut reader = Reader::new(der::parse(value, der::SEQUENCE)?);
+    let mut purposes = Vec::new();
+    while !reader.is_empty() {
+        purposes.push(reader.read(der::OID)?);
+    }
+    if purposes.is_empty() {
+        return Err(der::Error);
+    }
+    Ok(purposes)
+}
+
+
+// Returns the caIssuers URLs of the authority information access extension,
+// ignoring its other access methods and any locations that aren't URLs.
+fn parse_ca_issuers(value: &[u8]) -> Result<Vec<&[u8]>, der::Error> {
+    let mut reader = Reader::new(der::parse(value, der::SEQUENCE)?);
+    if reader.is_empty() {
+        return Err(der::Error);
+    }
+    let mut urls = Vec::new();
+    while !reader.is_empty() {
+        let mut description = Reader::new(reader.read(der::SEQUENCE)?);
+        let method = description.read(der::OID)?;
+        let (tag, location, _) = description.read_any()?;
+        description.finish()?;
+        if method == OID_AD_CA_ISSUERS && tag == 0x86 {
+            urls.push(location);
+        }
+    }
+    Ok(urls)
+}
+
+
+fn parse_general_names<'a>(names: &'a [u8]) -> Result<Vec<GeneralName<'a>>, der::Error> {
+    let mut reader = Reader::new(names);
+    let mut result = Vec::new();
+    while !reader.is_empty() {
+        let (tag, contents, _) = reader.read_any()?;
+        result.push(match tag {
+            0x81 => GeneralName::Rfc822Name(contents),
+            0x82 => GeneralName::DnsName(contents),
+            0xa4 => {
+                der::parse(contents, der::SEQUENCE)?;
+                GeneralName::DirectoryName(contents)
+            }
+            0x86 => GeneralName::Uri(contents),
+            0x87 => GeneralName::IpAddress(contents),
+            _ => GeneralName::Unsupported,
+        });
+    }
+    Ok(result)
+}
+
+
+fn parse_name_constraints(value: &[u8]) -> Result<NameConstraints<'_>, der::Error> {
+    let mut reader = Reader::new(der::parse(value, der::SEQUENCE)?);
+    let permitted = reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED)?;
+    let excluded = reader.read_optional(der::CONTEXT_SPECIFIC | der::CONSTRUCTED | 1)?;
+    reader.finish()?;
+    if permitted.is_none() && excluded.is_none() {
+        return Err(der::Error);
+    }
+    Ok(NameConstraints {
+        permitted: permitted.map(parse_general_subtrees).transpose()?,
+        excluded: excluded.map(parse_general_subtrees).transpose()?,
+    })
+}
+
+
+fn parse_general_subtrees(subtrees: &[u8]) -> Result<Vec<GeneralName<'_>>, der::Error> {
+    let mut reader = Reader::new(subtrees);
+    let mut result = Vec::new();
+    while !reader.is_empty() {
+        let subtree = reader.read(der::SEQUENCE)?;
+        let mut base = parse_general_names(subtree)?;
+        if base.len() == 1 {
+            result.push(base.remove(0));
+        } else {
+            result.push(GeneralName::Unsupported);
+        }
+    }
+    if result.is_empty() {
+        return Err(der::Error);
+    }
+    Ok(result)
+}
+
+
+// Returns the string values of the attributes of the given type in the name.
+fn parse_attributes<'a>(name: &'a [u8], oid: &[u8]) -> Result<Vec<&'a [u8]>, der::Error> {
+    let mut names = Vec::new();
+    let mut rdns = Reader::new(der::parse(name, der::SEQUENCE)?);
+    while !rdns.is_empty() {
+        let mut attributes = Reader::new(rdns.read(der::SET)?);
+        while !attributes.is_empty() {
+            let mut attribute = Reader::new(attributes.read(der::SEQUENCE)?);
+            let attribute_oid = attribute.read(der::OID)?;
+            let (tag, value, _) = attribute.read_any()?;
+            attribute.finish()?;
+            match tag {
+                der::UTF8_STRING | der::PRINTABLE_STRING | der::IA5_STRING if attribute_oid == oid => names.push(value),
+                _ => {}
+            }
+        }
+    }
+    Ok(names)
+}
+
+
+// Whether two complete encodings of names are of the same name, by their canonical
+// encodings. Certificate authorities don't always encode their names the same way in
+// the certificates they issue as in their own, so chaining by the exact bytes would
+// refuse chains that OpenSSL accepts.
+pub fn same_name(a: &[u8], b: &[u8]) -> bool {
+    a == b || match (canonical_rdns(a), canonical_rdns(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// The canonical encoding OpenSSL gives each RDN of a Name, in order: every string
+// value converted to a UTF8String, lower-cased and with its whitespace normalised,
+// and the attributes re-sorted afterwards, as DER requires. Names that are the same
+// by this measure are, for our purposes, the same name.
+pub fn canonical_rdns(name: &[u8]) -> Result<Vec<Vec<u8>>, der::Error> {
+    let mut canonical = Vec::new();
+    let mut rdns = Reader::new(der::parse(name, der::SEQUENCE)?);
+    while !rdns.is_empty() {
+        let mut attributes = Reader::new(rdns.read(der::SET)?);
+        let mut encoded = Vec::new();
+        while !attributes.is_empty() {
+            let mut attribute = Reader::new(attributes.read(der::SEQUENCE)?);
+            let mut contents = attribute.read_element(der::OID)?.to_vec();
+            let (tag, value, element) = attribute.read_any()?;
+            attribute.finish()?;
+            match canonical_string(tag, value)? {
+                Some(value) => contents.extend(der::encode(der::UTF8_STRING, value.as_bytes())),
+                None => contents.extend_from_slice(element),
+            }
+            encoded.push(der::encode(der::SEQUENCE, &contents));
+        }
+        encoded.sort();
+        canonical.push(der::encode(der::SET, &encoded.concat()));
+    }
+    Ok(canonical)
+}
+
+
+
+// The canonical form of an attribute value, or `None` if it isn't a string and
+// should be left alone.
+fn canonical_string(tag: u8, value: &[u8]) -> Result<Option<String>, der::Error> {
+    let decoded = match tag {
+        der::UTF8_STRING => String::from_utf8(value.to_vec()).map_err(|_| der::Error)?,
+        // OpenSSL treats all the single-byte string types as Latin-1.
+        der::PRINTABLE_STRING | der::T61_STRING | der::IA5_STRING | der::VISIBLE_STRING => {
+            value.iter().map(|&b| b as char).collect()
+        }
+        der::BMP_STRING => decode_wide(value, 2)?,
+        der::UNIVERSAL_STRING => decode_wide(value, 4)?,
+        _ => return Ok(None),
+    };
+
+    // Only ASCII whitespace counts, and runs of it become a single space.
+    let is_space = |c: char| c == ' ' || ('\t'..='\r').contains(&c);
+    let mut canonical = String::with_capacity(decoded.len());
+    let mut in_space = false;
+    for c in decoded.trim_matches(is_space).chars() {
+        if is_space(c) {
+            in_space = true;
+            continue;
+        }
+        if in_space {
+            canonical.push(' ');
+            in_space = false;
+        }
+        canonical.push(c.to_ascii_lowercase());
+    }
+    Ok(Some(canonical))
+}
+
+
+// Decodes a string of big-endian code points of the given width.
+fn decode_wide(value: &[u8], width: usize) -> Result<String, der::Error> {
+    if !value.len().is_multiple_of(width) {
+        return Err(der::Error);
+    }
+    value.chunks(width)
+         .map(|unit| unit.iter().fold(0, |acc, &b| (acc << 8) | b as u32))
+         .map(|code_point| ::std::char::from_u32(code_point).ok_or(der::Error))
+         .collect()
+}
+
+
+// Parses a UTCTime or GeneralizedTime, returning seconds since the Unix epoch.
+// RFC 5280 requires both to be expressed in UTC with seconds and without
+// fractions, so that's all we accept.
+pub fn parse_time(reader: &mut Reader) -> Result<i64, der::Error> {
+    let (tag, contents, _) = reader.read_any()?;
+    let (year, rest) = match tag {
+        der::UTC_TIME if contents.len() == 13 => {
+            let year = two_digits(&contents[0..2])?;
+            (if year >= 50 { 1900 + year } else { 2000 + year }, &contents[2..])
+        }
+        der::GENERALIZED_TIME if contents.len() == 15 => {
+            (two_digits(&contents[0..2])? * 100 + two_digits(&contents[2..4])?, &contents[4..])
+        }
+        _ => return Err(der::Error),
+    };
+    if rest[10] != b'Z' {
+        return Err(der::Error);
+    }
+
+    let month = two_digits(&rest[0..2])?;
+    let day = two_digits(&rest[2..4])?;
+    let hour = two_digits(&rest[4..6])?;
+    let minute = two_digits(&rest[6..8])?;
+    let second = two_digits(&rest[8..10])?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) ||
+       hour > 23 || minute > 59 || second > 59 {
+        return Err(der::Error);
+    }
+
+    let days = days_from_civil(year, month, day);
+    Ok(days * 86400 + (hour * 3600 + minute * 60 + second) as i64)
+}
+
+
+fn two_digits(digits: &[u8]) -> Result<u32, der::Error> {
+    match digits {
+        [tens @ b'0'..=b'9', units @ b'0'..=b'9'] => Ok(((tens - b'0') * 10 + (units - b'0')) as u32),
+        _ => Err(der::Error),
+    }
+}
+
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+
+// The number of days between 1970-01-01 and the given date in the proleptic
+// Gregorian calendar.
+pub fn days_from_civil(year: u32, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year as i64 - 1 } else { year as i64 };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+
+#[cfg(test)]
+mod test {
+    use x509::{Certificate, GeneralName, days_from_civil, KEY_USAGE_KEY_CERT_SIGN};
+    use test::{certifi_chain, test_pki_chain};
+
+    #[test]
+    fn parses_leaf() {
+        let chain = certifi_chain();
+        let leaf = Certificate::parse(chain[0]).unwrap();
+        assert!(!leaf.basic_constraints.unwrap().ca);
+        assert!(!leaf.is_self_issued());
+
+        // The leaf is valid from 2015-09-16 to 2018-09-16 23:59:59.
+        assert_eq!(leaf.not_before, 1442361600);
+        assert_eq!(leaf.not_after, 1537142399);
+
+        let names = leaf.subject_alt_names().unwrap();
+        let dns_names = names.iter().filter_map(|name| match *name {
+            GeneralName::DnsName(name) => Some(name),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(dns_names, vec![&b"certifi.io"[..], &b"www.certifi.io"[..]]);
+        assert_eq!(leaf.common_names(), vec![&b"certifi.io"[..]]);
+    }
+
+    #[test]
+    fn parses_intermediate() {
+        let chain = certifi_chain();
+        let intermediate = Certificate::parse(chain[1]).unwrap();
+        let leaf = Certificate::parse(chain[0]).unwrap();
+        let constraints = intermediate.basic_constraints.unwrap();
+        assert!(constraints.ca);
+        assert_eq!(constraints.path_len, Some(0));
+        assert!(intermediate.key_usage.unwrap() & KEY_USAGE_KEY_CERT_SIGN != 0);
+        assert_eq!(intermediate.subject, leaf.issuer);
+    }
+
+    #[test]
+    fn parses_ca_issuers() {
+        let leaf = Certificate::parse(include_bytes!("../fixtures/aia/leaf.crt")).unwrap();
+        // The extension also gives an OCSP responder, which isn't an issuer.
+        assert_eq!(leaf.ca_issuers, vec![&b"http://certitude.test/intermediate.crt"[..]]);
+        let leaf = Certificate::parse(test_pki_chain()[0]).unwrap();
+        assert!(leaf.ca_issuers.is_empty());
+    }
+
+    #[test]
+    fn accepts_negative_serial() {
+        let chain = certifi_chain();
+        let original = Certificate::parse(chain[0]).unwrap();
+        let offset = original.serial.as_ptr() as usize - chain[0].as_ptr() as usize;
+        let mut cert = chain[0].to_vec();
+        cert[offset] |= 0x80;
+        let leaf = Certificate::parse(&cert).unwrap();
+        assert_eq!(leaf.serial[0], 0xbc);
+    }
+
+    #[test]
+    fn rejects_truncated_certificate() {
+        let chain = certifi_chain();
+        assert!(Certificate::parse(&chain[0][..50]).is_err());
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let chain = certifi_chain();
+        let mut cert = chain[0].to_vec();
+        cert.push(0);
+        assert!(Certificate::parse(&cert).is_err());
+    }
+
+    #[test]
+    fn computes_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(2038, 1, 19), 24855);
+    }
+}
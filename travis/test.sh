@@ -23,5 +23,10 @@ fi
 
 cd ../c-certitude
 cargo build --verbose ${CARGO_FLAGS}
-clang -L target/${TARGET} -framework Security -framework CoreFoundation -lSystem -lc -lm -lc_certitude "${OPTIMIZATION}" test/test.c
-./a.out
+if [[ "${TRAVIS_OS_NAME}" = linux ]]; then
+    clang -L target/${TARGET} -lc_certitude -lssl -lcrypto -lc -lm "${OPTIMIZATION}" test/test.c
+    LD_LIBRARY_PATH=target/${TARGET} ./a.out
+else
+    clang -L target/${TARGET} -framework Security -framework CoreFoundation -lSystem -lc -lm -lc_certitude "${OPTIMIZATION}" test/test.c
+    ./a.out
+fi